@@ -1,58 +1,124 @@
-use crate::ty::Type;
+use crate::ty::{Const, Type};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Ast {
     pub node: Global,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Global {
     pub definitions: Vec<FnDef>,
+    pub statics: Vec<StaticDef>,
+    pub impls: Vec<Impl>,
 }
 
-#[derive(Debug)]
+impl Global {
+    /// every function, methods included
+    pub fn functions(&self) -> impl Iterator<Item = &FnDef> {
+        self.definitions
+            .iter()
+            .chain(self.impls.iter().flat_map(|i| i.methods.iter()))
+    }
+}
+
+/// `impl Rect { .. }` or `impl Shape for Rect { .. }`,
+/// the methods are named by their symbol like `impl.new.Rect` or `impl.Shape.area.Rect`
+#[derive(Debug, Clone)]
+pub struct Impl {
+    pub methods: Vec<FnDef>,
+}
+
+/// `static mut COUNTER: i64 = 0;`, the initializer is evaluated at compile time
+#[derive(Debug, Clone)]
+pub struct StaticDef {
+    pub name: String,
+    pub ty: Type,
+    pub mutable: bool,
+    pub value: i64,
+}
+
+#[derive(Debug, Clone)]
 pub struct FnDef {
     pub name: String,
-    pub args: Vec<Local>,
+    /// type and const parameters, `T` in `fn max<T>(a: T, b: T) -> T`
+    pub generics: Vec<String>,
+    pub args: Vec<Param>,
     pub fn_type: Type,
     pub body: Block,
+    /// `pub extern "C" fn`, callable from C under its own name
+    pub exported: bool,
+}
+
+/// function parameter like `(a, b): (i64, i64)`
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub pattern: Pattern,
+    pub ty: Type,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Expr {
     Stmt(Stmt),
     Block(Block),
     Return(Return),
     Loop(Loop),
     IfElse(IfElse),
+    Match(Match),
     FnCall(FnCall),
+    Call(Call),
+    Closure(Closure),
     Init(Init),
     Assign(Assign),
+    CompoundAssign(CompoundAssign),
     BinOp(BinOp),
     UnOp(UnOp),
     Enclosed(Enclosed),
+    Tuple(Tuple),
+    Member(Member),
+    Struct(Struct),
+    Variant(Variant),
+    BoxNew(BoxNew),
+    ToDyn(ToDyn),
+    Panic(Panic),
+    Assert(Assert),
+    Index(Index),
+    Slice(Slice),
+    VecNew(VecNew),
+    VecPush(VecPush),
+    VecPop(VecPop),
+    VecConcat(VecConcat),
     Bool(Bool),
     Local(Local),
+    Static(Static),
+    FnRef(FnRef),
+    ConstExpr(ConstExpr),
     Number(Number),
 }
 
 impl Expr {
     pub fn ty(&self) -> Type {
         match self {
-            Expr::Stmt(_) => Type::Void,
-            Expr::Block(Block { exprs }) => {
-                exprs.last().map_or(Type::Void, |last_expr| last_expr.ty())
-            }
+            Expr::Stmt(_) => Type::Unit,
+            Expr::Block(block) => block.ty(),
             Expr::Return(_) => Type::Never,
             Expr::Loop(_) => Type::Never,
             Expr::IfElse(IfElse {
                 cond: _,
                 if_body,
-                else_body: _,
-            }) => if_body
-                .exprs
-                .last()
-                .map_or(Type::Void, |last_expr| last_expr.ty()),
+                else_body,
+            }) => {
+                let body_ty = |body: &Block| body.exprs.last().map_or(Type::Unit, Expr::ty);
+                match (body_ty(if_body), else_body) {
+                    // `if c { panic("..") } else { v }` has the type of `v`
+                    (Type::Never, Some(else_body)) => body_ty(else_body),
+                    (ty, _) => ty,
+                }
+            }
+            Expr::Match(Match { arms, .. }) => arms
+                .iter()
+                .map(|arm| arm.body.ty())
+                .find(|ty| *ty != Type::Never)
+                .unwrap_or(Type::Never),
             Expr::FnCall(FnCall { fn_type, .. }) => {
                 if let Type::Fn { ret, .. } = fn_type {
                     *ret.clone()
@@ -60,9 +126,19 @@ impl Expr {
                     panic!("function's type must be Fn")
                 }
             }
-            Expr::Init(_) => Type::Void,
-            Expr::Assign(_) => Type::Void,
-            Expr::BinOp(BinOp { op, lhs, rhs }) => match (op, lhs.ty(), rhs.ty()) {
+            Expr::Call(Call { callee, .. }) => match callee.ty() {
+                Type::Fn { ret, .. } | Type::Closure { ret, .. } => *ret,
+                ty => panic!("expected function, found {:?}", ty),
+            },
+            Expr::Closure(Closure { ty, .. }) => ty.clone(),
+            Expr::Init(_) => Type::Unit,
+            Expr::Assign(_) => Type::Unit,
+            Expr::CompoundAssign(CompoundAssign { op, lhs, rhs, .. }) => match (lhs.ty(), rhs.ty())
+            {
+                (Type::I64, Type::I64) => Type::Unit,
+                (lhs, rhs) => panic!("{:?}= is not defined between {:?} and {:?}", op, lhs, rhs),
+            },
+            Expr::BinOp(BinOp { op, lhs, rhs, .. }) => match (op, lhs.ty(), rhs.ty()) {
                 (
                     BinOpKind::Add
                     | BinOpKind::Sub
@@ -71,7 +147,9 @@ impl Expr {
                     | BinOpKind::Rem
                     | BinOpKind::BitAnd
                     | BinOpKind::BitOr
-                    | BinOpKind::BitXor,
+                    | BinOpKind::BitXor
+                    | BinOpKind::Shl
+                    | BinOpKind::Shr,
                     Type::I64,
                     Type::I64,
                 ) => Type::I64,
@@ -86,85 +164,379 @@ impl Expr {
                     b,
                 ) if a == b => Type::Bool,
                 (BinOpKind::LogAnd | BinOpKind::LogOr, Type::Bool, Type::Bool) => Type::Bool,
-                (BinOpKind::Add | BinOpKind::Sub, Type::Ptr { to }, Type::I64) => Type::Ptr { to },
+                (BinOpKind::Add | BinOpKind::Sub, Type::Ptr { to, mutable }, Type::I64) => {
+                    Type::Ptr { to, mutable }
+                }
                 (BinOpKind::Add, Type::Array { element, .. }, Type::I64) => {
-                    Type::Ptr { to: element }
+                    // writes through it are checked against the array itself
+                    Type::Ptr {
+                        to: element,
+                        mutable: false,
+                    }
                 }
                 _ => panic!("{:?} is not defined between {:?} and {:?}", op, lhs, rhs),
             },
             Expr::UnOp(UnOp { kind, expr }) => match kind {
-                UnOpKind::Neg => expr.ty(),
+                UnOpKind::Neg { .. } => expr.ty(),
+                UnOpKind::Not => match expr.ty() {
+                    ty @ (Type::Bool | Type::I64) => ty,
+                    ty => panic!("cannot apply `!` to {:?}", ty),
+                },
                 UnOpKind::Ref => Type::Ptr {
                     to: Box::new(expr.ty()),
+                    mutable: false,
+                },
+                UnOpKind::RefMut => Type::Ptr {
+                    to: Box::new(expr.ty()),
+                    mutable: true,
                 },
                 UnOpKind::Deref => match expr.ty() {
-                    Type::Ptr { to } => *to,
+                    Type::Ptr { to, .. } | Type::Box { to } => *to,
                     Type::Array { element, .. } => *element,
                     _ => panic!("only pointer type can be dereferenced"),
                 },
             },
             Expr::Enclosed(Enclosed { expr }) => expr.ty(),
+            Expr::Tuple(Tuple { elements }) if elements.is_empty() => Type::Unit,
+            Expr::Tuple(Tuple { elements }) => Type::Tuple {
+                elements: elements.iter().map(Expr::ty).collect(),
+            },
+            // the fields in front may be of type parameters, without a size yet
+            Expr::Member(Member { expr, index }) => match expr.ty() {
+                Type::Tuple { elements } => elements[*index].clone(),
+                Type::Struct { fields, .. } => fields[*index].ty.clone(),
+                ty => ty.member(*index).1,
+            },
+            Expr::Struct(Struct { ty, .. }) => ty.clone(),
+            Expr::Variant(Variant { ty, .. }) => ty.clone(),
+            Expr::BoxNew(BoxNew { value }) => Type::Box {
+                to: Box::new(value.ty()),
+            },
+            Expr::ToDyn(ToDyn { ty, .. }) => ty.clone(),
+            Expr::Panic(_) => Type::Never,
+            Expr::Assert(_) => Type::Unit,
+            Expr::Index(Index { expr, .. }) => match expr.ty() {
+                Type::Array { element, .. }
+                | Type::Slice { element, .. }
+                | Type::Vec { element, .. }
+                | Type::Ptr { to: element, .. } => *element,
+                ty => panic!("cannot index into {:?}", ty),
+            },
+            Expr::Slice(Slice { expr, mutable, .. }) => match expr.ty() {
+                Type::Array { element, .. }
+                | Type::Slice { element, .. }
+                | Type::Vec { element, .. } => Type::Slice {
+                    element,
+                    mutable: *mutable,
+                },
+                ty => panic!("cannot slice {:?}", ty),
+            },
+            Expr::VecNew(VecNew { ty }) => ty.clone(),
+            Expr::VecPush(_) => Type::Unit,
+            Expr::VecPop(VecPop { vec, .. }) => match vec.ty() {
+                Type::Vec { element, .. } => *element,
+                ty => panic!("{:?} has no method pop", ty),
+            },
+            Expr::VecConcat(VecConcat { ty, .. }) => ty.clone(),
             Expr::Bool(..) => Type::Bool,
             Expr::Local(Local { ty, .. }) => ty.clone(),
-            Expr::Number(..) => Type::I64,
+            Expr::Static(Static { ty, .. }) => ty.clone(),
+            Expr::FnRef(FnRef { ty, .. }) => ty.clone(),
+            Expr::ConstExpr(..) | Expr::Number(..) => Type::I64,
+        }
+    }
+
+    /// value of a constant expression, booleans are 0 or 1
+    pub fn eval(&self) -> Option<i64> {
+        match self {
+            Expr::Number(Number { value }) => value.parse().ok(),
+            Expr::ConstExpr(ConstExpr { value }) => value.eval(),
+            Expr::Bool(Bool::True) => Some(1),
+            Expr::Bool(Bool::False) => Some(0),
+            Expr::Enclosed(Enclosed { expr }) => expr.eval(),
+            Expr::UnOp(UnOp { kind, expr }) => {
+                let v = expr.eval()?;
+                match kind {
+                    UnOpKind::Neg { .. } => Some(v.wrapping_neg()),
+                    UnOpKind::Not if expr.ty() == Type::Bool => Some(v ^ 1),
+                    UnOpKind::Not => Some(!v),
+                    _ => None,
+                }
+            }
+            Expr::BinOp(BinOp { op, lhs, rhs, .. }) => {
+                let (a, b) = (lhs.eval()?, rhs.eval()?);
+                Some(match op {
+                    BinOpKind::Add => a.wrapping_add(b),
+                    BinOpKind::Sub => a.wrapping_sub(b),
+                    BinOpKind::Mul => a.wrapping_mul(b),
+                    BinOpKind::Div => a.checked_div(b)?,
+                    BinOpKind::Rem => a.checked_rem(b)?,
+                    BinOpKind::BitAnd | BinOpKind::LogAnd => a & b,
+                    BinOpKind::BitOr | BinOpKind::LogOr => a | b,
+                    BinOpKind::BitXor => a ^ b,
+                    BinOpKind::Shl => a.wrapping_shl(b as u32),
+                    BinOpKind::Shr => a.wrapping_shr(b as u32),
+                    BinOpKind::Eq => (a == b) as i64,
+                    BinOpKind::Neq => (a != b) as i64,
+                    BinOpKind::Le => (a < b) as i64,
+                    BinOpKind::LeEq => (a <= b) as i64,
+                    BinOpKind::Gt => (a > b) as i64,
+                    BinOpKind::GtEq => (a >= b) as i64,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// value of a constant expression over const parameters, in normal form
+    pub fn eval_const(&self) -> Option<Const> {
+        match self {
+            Expr::ConstExpr(ConstExpr { value }) => Some(value.clone()),
+            Expr::Enclosed(Enclosed { expr }) => expr.eval_const(),
+            Expr::UnOp(UnOp {
+                kind: UnOpKind::Neg { .. },
+                expr,
+            }) => Some(-expr.eval_const()?),
+            Expr::BinOp(BinOp { op, lhs, rhs, .. })
+                if matches!(op, BinOpKind::Add | BinOpKind::Sub | BinOpKind::Mul) =>
+            {
+                let (a, b) = (lhs.eval_const()?, rhs.eval_const()?);
+                Some(match op {
+                    BinOpKind::Add => a + b,
+                    BinOpKind::Sub => a - b,
+                    _ => a * b,
+                })
+            }
+            _ => self.eval().map(Const::number),
+        }
+    }
+
+    /// the expressions directly inside, in evaluation order
+    pub fn children(&mut self) -> Vec<&mut Expr> {
+        match self {
+            Expr::Stmt(Stmt { expr: e })
+            | Expr::Return(Return { expr: e })
+            | Expr::UnOp(UnOp { expr: e, .. })
+            | Expr::Enclosed(Enclosed { expr: e })
+            | Expr::Member(Member { expr: e, .. })
+            | Expr::BoxNew(BoxNew { value: e })
+            | Expr::ToDyn(ToDyn { value: e, .. })
+            | Expr::Assert(Assert { cond: e, .. })
+            | Expr::VecPop(VecPop { vec: e, .. }) => vec![&mut **e],
+            Expr::Closure(Closure { body: e, .. }) => vec![&mut **e],
+            Expr::Slice(Slice { expr, lo, hi, .. }) => std::iter::once(&mut **expr)
+                .chain(lo.as_deref_mut())
+                .chain(hi.as_deref_mut())
+                .collect(),
+            Expr::Block(b) | Expr::Loop(Loop { body: b }) => b.exprs.iter_mut().collect(),
+            Expr::IfElse(IfElse {
+                cond,
+                if_body,
+                else_body,
+            }) => std::iter::once(&mut **cond)
+                .chain(if_body.exprs.iter_mut())
+                .chain(else_body.iter_mut().flat_map(|b| b.exprs.iter_mut()))
+                .collect(),
+            Expr::Match(Match { scrutinee, arms }) => std::iter::once(&mut **scrutinee)
+                .chain(arms.iter_mut().map(|arm| &mut arm.body))
+                .collect(),
+            Expr::Call(Call { callee, args }) => std::iter::once(&mut **callee)
+                .chain(args.iter_mut())
+                .collect(),
+            Expr::Init(Init { value, .. }) => value.as_deref_mut().into_iter().collect(),
+            Expr::Assign(Assign { lhs, rhs })
+            | Expr::CompoundAssign(CompoundAssign { lhs, rhs, .. })
+            | Expr::BinOp(BinOp { lhs, rhs, .. })
+            | Expr::Index(Index {
+                expr: lhs,
+                index: rhs,
+                ..
+            })
+            | Expr::VecPush(VecPush {
+                vec: lhs,
+                value: rhs,
+            })
+            | Expr::VecConcat(VecConcat { lhs, rhs, .. }) => vec![&mut **lhs, &mut **rhs],
+            Expr::FnCall(FnCall { args: members, .. })
+            | Expr::Tuple(Tuple { elements: members })
+            | Expr::Struct(Struct {
+                fields: members, ..
+            })
+            | Expr::Variant(Variant {
+                fields: members, ..
+            }) => members.iter_mut().collect(),
+            Expr::VecNew(_)
+            | Expr::Panic(_)
+            | Expr::Bool(_)
+            | Expr::Local(_)
+            | Expr::Static(_)
+            | Expr::FnRef(_)
+            | Expr::ConstExpr(_)
+            | Expr::Number(_) => vec![],
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Stmt {
     pub expr: Box<Expr>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Block {
     pub exprs: Vec<Expr>,
 }
 
-#[derive(Debug)]
+impl Block {
+    /// whether a `let` of the block takes ownership of a value, dropped when the block ends
+    pub fn owns_locals(&self) -> bool {
+        self.exprs.iter().any(|e| match e {
+            Expr::Stmt(Stmt { expr }) => {
+                matches!(&**expr, Expr::Init(Init { pattern, .. }) if pattern.owns())
+            }
+            Expr::Init(Init { pattern, .. }) => pattern.owns(),
+            _ => false,
+        })
+    }
+
+    pub fn ty(&self) -> Type {
+        self.exprs.last().map_or(Type::Unit, Expr::ty)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Return {
     pub expr: Box<Expr>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Loop {
     pub body: Block,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IfElse {
     pub cond: Box<Expr>,
     pub if_body: Block,
     pub else_body: Option<Block>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+pub struct Match {
+    pub scrutinee: Box<Expr>,
+    pub arms: Vec<Arm>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Arm {
+    pub pattern: Pattern,
+    pub body: Expr,
+}
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// `_`
+    Wildcard,
+    /// `x` or `mut x`, binds the matched value
+    Binding { local: Local, mutable: bool },
+    /// `-1`
+    Int(i64),
+    /// `1..=9`, bounds are inclusive
+    Range { lo: i64, hi: i64 },
+    /// `true`
+    Bool(Bool),
+    /// `(a, _)`, also `()`
+    Tuple(Vec<Pattern>),
+    /// `Point { x, y: _ }`, fields are in declaration order
+    Struct(Vec<Pattern>),
+    /// `&x` or `&mut x`, matches the value behind a pointer
+    Ref(Box<Pattern>),
+    /// `Shape::Circle(r)`, fields are in declaration order
+    Variant {
+        ty: Type,
+        index: usize,
+        fields: Vec<Pattern>,
+    },
+}
+
+impl Pattern {
+    /// whether a binding of this pattern takes ownership of a value that needs drop
+    pub fn owns(&self) -> bool {
+        match self {
+            Pattern::Binding { local, .. } => local.ty.needs_drop(),
+            Pattern::Tuple(fields) | Pattern::Struct(fields) | Pattern::Variant { fields, .. } => {
+                fields.iter().any(Pattern::owns)
+            }
+            Pattern::Ref(inner) => inner.owns(),
+            Pattern::Wildcard | Pattern::Int(_) | Pattern::Range { .. } | Pattern::Bool(_) => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct FnCall {
     pub name: String,
     pub args: Vec<Expr>,
     pub fn_type: Type,
+    /// instantiation of the type parameters of a generic callee
+    pub type_args: Vec<Type>,
+    /// vtable slot of a method called on a `dyn Trait`, the receiver comes first
+    pub dispatch: Option<usize>,
+}
+
+/// `f(a, b)` where `f` is a function pointer or a closure
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub callee: Box<Expr>,
+    pub args: Vec<Expr>,
+}
+
+/// `|x| x + k`, borrows the locals it captures
+#[derive(Debug, Clone)]
+pub struct Closure {
+    pub params: Vec<Param>,
+    pub body: Box<Expr>,
+    pub ty: Type,
+    /// symbol of the function the body is lifted into, set by closure conversion
+    pub name: String,
+    /// locals whose addresses make up the environment, set by closure conversion
+    pub captures: Vec<Local>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Init {
-    pub name: Box<Expr>,
+    pub pattern: Pattern,
     pub value: Option<Box<Expr>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Assign {
     pub lhs: Box<Expr>,
     pub rhs: Box<Expr>,
 }
 
-#[derive(Debug)]
+/// `lhs op= rhs`, where lhs is evaluated only once
+#[derive(Debug, Clone)]
+pub struct CompoundAssign {
+    pub op: BinOpKind,
+    pub lhs: Box<Expr>,
+    pub rhs: Box<Expr>,
+    /// offset in the source, reported when the arithmetic overflows
+    pub pos: usize,
+}
+
+#[derive(Debug, Clone)]
 pub struct BinOp {
     pub op: BinOpKind,
     pub lhs: Box<Expr>,
     pub rhs: Box<Expr>,
+    /// offset in the source, reported when the arithmetic overflows
+    pub pos: usize,
+    /// `a.wrapping_add(b)` and friends, overflow wraps around instead of panicking
+    pub wrapping: bool,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum BinOpKind {
     Eq,
     Neq,
@@ -185,42 +557,182 @@ pub enum BinOpKind {
     BitOr,
     BitXor,
 
+    Shl,
+    Shr,
+
     LogAnd,
     LogOr,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct UnOp {
     pub kind: UnOpKind,
     pub expr: Box<Expr>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum UnOpKind {
-    Neg,
+    Neg {
+        /// offset in the source, reported when the negation overflows
+        pos: usize,
+    },
+    /// logical not for bool, bitwise not for integers
+    Not,
     Ref,
+    RefMut,
     Deref,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 
 pub struct Enclosed {
     pub expr: Box<Expr>,
 }
 
-#[derive(Debug)]
+/// `(a, b)`, or the unit value `()` when empty
+#[derive(Debug, Clone)]
+pub struct Tuple {
+    pub elements: Vec<Expr>,
+}
+
+/// member access like `t.0` or `p.x`
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub expr: Box<Expr>,
+    pub index: usize,
+}
+
+/// struct literal like `Point { x: 1, y: 2 }`
+#[derive(Debug, Clone)]
+pub struct Struct {
+    pub ty: Type,
+    /// in declaration order
+    pub fields: Vec<Expr>,
+}
+
+/// enum constructor like `Shape::Rect { w: 1, h: 2 }`
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub ty: Type,
+    pub index: usize,
+    /// in declaration order
+    pub fields: Vec<Expr>,
+}
+
+/// `Box::new(value)`, moves the value to the heap
+#[derive(Debug, Clone)]
+pub struct BoxNew {
+    pub value: Box<Expr>,
+}
+
+/// `&x` or `Box::new(x)` where a `dyn Trait` is expected,
+/// pairs the pointer with the vtable of the type of `x`
+#[derive(Debug, Clone)]
+pub struct ToDyn {
+    pub value: Box<Expr>,
+    pub ty: Type,
+    /// symbols of the trait methods in vtable order, like `impl.Shape.area`
+    pub methods: Vec<String>,
+}
+
+/// `panic("message")`, reports the message with its location and exits with 101
+#[derive(Debug, Clone)]
+pub struct Panic {
+    pub message: String,
+    pub pos: usize,
+}
+
+/// `assert(cond)` or `assert(cond, "message")`, panics unless `cond` holds
+#[derive(Debug, Clone)]
+pub struct Assert {
+    pub cond: Box<Expr>,
+    pub message: Option<String>,
+    pub pos: usize,
+}
+
+/// `a[i]`, an element of an array, slice, vec or behind a pointer
+#[derive(Debug, Clone)]
+pub struct Index {
+    pub expr: Box<Expr>,
+    pub index: Box<Expr>,
+    /// offset in the source, reported when `index` is out of bounds
+    pub pos: usize,
+}
+
+/// `&a[lo..hi]` or `&mut a[..]`, borrows a range of an array, slice or vec
+#[derive(Debug, Clone)]
+pub struct Slice {
+    pub expr: Box<Expr>,
+    pub lo: Option<Box<Expr>>,
+    pub hi: Option<Box<Expr>>,
+    pub mutable: bool,
+    /// offset in the source, reported when the range is out of bounds
+    pub pos: usize,
+}
+
+/// `Vec::new()`, an empty vec
+#[derive(Debug, Clone)]
+pub struct VecNew {
+    pub ty: Type,
+}
+
+/// `v.push(value)`
+#[derive(Debug, Clone)]
+pub struct VecPush {
+    pub vec: Box<Expr>,
+    pub value: Box<Expr>,
+}
+
+/// `v.pop()`, the last element, panics when `v` is empty
+#[derive(Debug, Clone)]
+pub struct VecPop {
+    pub vec: Box<Expr>,
+    /// offset in the source, reported when `vec` is empty
+    pub pos: usize,
+}
+
+/// `a.concat(b)` of a `Vec<T, n>` and a `Vec<T, m>`, moves both into a `Vec<T, n + m>`
+#[derive(Debug, Clone)]
+pub struct VecConcat {
+    pub lhs: Box<Expr>,
+    pub rhs: Box<Expr>,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone)]
 pub enum Bool {
     True,
     False,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Local {
     pub name: String,
     pub ty: Type,
 }
 
-#[derive(Debug)]
+/// use of a `static` item
+#[derive(Debug, Clone)]
+pub struct Static {
+    pub name: String,
+    pub ty: Type,
+}
+
+/// a function used as a value, like `double` in `let f = double;`
+#[derive(Debug, Clone)]
+pub struct FnRef {
+    pub name: String,
+    pub ty: Type,
+}
+
+/// `N` or an expression over const parameters like `a.len()` of `a: [i64; N + 1]`,
+/// a number once they are instantiated
+#[derive(Debug, Clone)]
+pub struct ConstExpr {
+    pub value: Const,
+}
+
+#[derive(Debug, Clone)]
 pub struct Number {
     pub value: String,
 }
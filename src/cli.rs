@@ -16,4 +16,24 @@ pub struct SofaC {
     /// output to stdout
     #[clap(short, long, group = "output_type")]
     pub stdout: bool,
+
+    /// kind of output
+    #[clap(long, value_enum, default_value = "asm")]
+    pub emit: Emit,
+
+    /// do not check indices and ranges against the length at runtime
+    #[clap(long)]
+    pub no_bounds_checks: bool,
+
+    /// release build, arithmetic is not checked for overflow or division by zero
+    #[clap(short = 'O', long)]
+    pub release: bool,
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum Emit {
+    /// assembly of the whole program
+    Asm,
+    /// C header declaring the exported functions
+    CHeader,
 }
@@ -0,0 +1,251 @@
+//! closure conversion, lifts the body of each closure into a function of its own
+//!
+//! the lifted function takes the environment first, a struct of the addresses of the captured
+//! locals like `main.closure0.env`, and reads them through it.
+
+use crate::{
+    ast::{
+        Arm, Ast, Block, Closure, Expr, FnDef, IfElse, Init, Local, Loop, Match, Member, Param,
+        Pattern, Tuple, UnOp, UnOpKind,
+    },
+    ty::{Field, Type},
+};
+
+/// name of the environment parameter of a lifted closure
+const ENV: &str = "closure.env";
+
+/// lifts every closure into a function, leaving its name and captures in its place
+pub fn convert(mut ast: Ast) -> Ast {
+    let mut lifted = vec![];
+    for f in ast.node.definitions.iter_mut() {
+        let mut conv = Conv {
+            parent: f.name.clone(),
+            count: 0,
+            lifted: &mut lifted,
+        };
+        conv.block(&mut f.body);
+    }
+    ast.node.definitions.extend(lifted);
+    ast
+}
+
+struct Conv<'a> {
+    /// symbol of the function being converted
+    parent: String,
+    /// closures lifted out of it so far
+    count: usize,
+    lifted: &'a mut Vec<FnDef>,
+}
+
+impl Conv<'_> {
+    fn block(&mut self, block: &mut Block) {
+        for e in block.exprs.iter_mut() {
+            self.expr(e);
+        }
+    }
+
+    fn expr(&mut self, e: &mut Expr) {
+        if let Expr::Closure(closure) = e {
+            self.lift(closure);
+            return;
+        }
+        for child in e.children() {
+            self.expr(child);
+        }
+    }
+
+    /// moves the body of `closure` into a new function, then converts that function
+    fn lift(&mut self, closure: &mut Closure) {
+        let name = format!("{}.closure{}", self.parent, self.count);
+        self.count += 1;
+
+        let mut free = Free {
+            scope: vec![],
+            captures: vec![],
+            env: None,
+        };
+        for param in closure.params.iter() {
+            declare(&param.pattern, &mut free.scope);
+        }
+        free.expr(&mut closure.body);
+        let captures = free.captures;
+
+        let env = Type::Struct {
+            name: format!("{}.env", name),
+            fields: captures
+                .iter()
+                .enumerate()
+                .map(|(i, local)| Field {
+                    name: i.to_string(),
+                    ty: Type::Ptr {
+                        to: Box::new(local.ty.clone()),
+                        mutable: true,
+                    },
+                })
+                .collect(),
+        };
+        let env = Type::Ptr {
+            to: Box::new(env),
+            mutable: false,
+        };
+        let mut rewrite = Free {
+            scope: vec![],
+            captures,
+            env: Some(env.clone()),
+        };
+        for param in closure.params.iter() {
+            declare(&param.pattern, &mut rewrite.scope);
+        }
+        rewrite.expr(&mut closure.body);
+        let captures = rewrite.captures;
+
+        let ret = match &closure.ty {
+            Type::Closure { ret, .. } => ret.clone(),
+            _ => unreachable!(),
+        };
+
+        let mut args = vec![Param {
+            pattern: Pattern::Binding {
+                local: Local {
+                    name: ENV.to_string(),
+                    ty: env.clone(),
+                },
+                mutable: false,
+            },
+            ty: env,
+        }];
+        args.append(&mut closure.params);
+        let body = std::mem::replace(&mut *closure.body, Expr::Tuple(Tuple { elements: vec![] }));
+        let mut f = FnDef {
+            name: name.clone(),
+            generics: vec![],
+            fn_type: Type::Fn {
+                args: args.iter().map(|arg| arg.ty.clone()).collect(),
+                ret,
+            },
+            args,
+            body: Block { exprs: vec![body] },
+            exported: false,
+        };
+
+        // closures inside the body capture from the lifted function
+        let mut conv = Conv {
+            parent: name.clone(),
+            count: 0,
+            lifted: self.lifted,
+        };
+        conv.block(&mut f.body);
+        self.lifted.push(f);
+
+        closure.name = name;
+        closure.captures = captures;
+    }
+}
+
+/// the locals a closure body uses from outside of it
+struct Free {
+    /// locals declared inside the body so far
+    scope: Vec<String>,
+    captures: Vec<Local>,
+    /// once known, the captures are replaced by their place in the environment of this type
+    env: Option<Type>,
+}
+
+impl Free {
+    fn expr(&mut self, e: &mut Expr) {
+        match e {
+            Expr::Local(local) => {
+                if self.scope.contains(&local.name) {
+                    return;
+                }
+                let index = match self.captures.iter().position(|c| c.name == local.name) {
+                    Some(index) => index,
+                    None => {
+                        self.captures.push(local.clone());
+                        self.captures.len() - 1
+                    }
+                };
+                if let Some(env) = &self.env {
+                    // `*(*closure.env).index`
+                    let env = Expr::Local(Local {
+                        name: ENV.to_string(),
+                        ty: env.clone(),
+                    });
+                    *e = deref(Expr::Member(Member {
+                        expr: Box::new(deref(env)),
+                        index,
+                    }));
+                }
+            }
+            Expr::Block(b) | Expr::Loop(Loop { body: b }) => self.block(b),
+            Expr::IfElse(IfElse {
+                cond,
+                if_body,
+                else_body,
+            }) => {
+                self.expr(cond);
+                self.block(if_body);
+                if let Some(else_body) = else_body {
+                    self.block(else_body);
+                }
+            }
+            Expr::Match(Match { scrutinee, arms }) => {
+                self.expr(scrutinee);
+                for Arm { pattern, body } in arms.iter_mut() {
+                    let outer = self.scope.len();
+                    declare(pattern, &mut self.scope);
+                    self.expr(body);
+                    self.scope.truncate(outer);
+                }
+            }
+            Expr::Init(Init { pattern, value }) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+                declare(pattern, &mut self.scope);
+            }
+            Expr::Closure(Closure { params, body, .. }) => {
+                let outer = self.scope.len();
+                for param in params.iter() {
+                    declare(&param.pattern, &mut self.scope);
+                }
+                self.expr(body);
+                self.scope.truncate(outer);
+            }
+            _ => {
+                for child in e.children() {
+                    self.expr(child);
+                }
+            }
+        }
+    }
+
+    /// locals declared in `block` end with it
+    fn block(&mut self, block: &mut Block) {
+        let outer = self.scope.len();
+        for e in block.exprs.iter_mut() {
+            self.expr(e);
+        }
+        self.scope.truncate(outer);
+    }
+}
+
+fn deref(e: Expr) -> Expr {
+    Expr::UnOp(UnOp {
+        kind: UnOpKind::Deref,
+        expr: Box::new(e),
+    })
+}
+
+fn declare(pattern: &Pattern, scope: &mut Vec<String>) {
+    match pattern {
+        Pattern::Binding { local, .. } => scope.push(local.name.clone()),
+        Pattern::Tuple(fields) | Pattern::Struct(fields) | Pattern::Variant { fields, .. } => {
+            for field in fields.iter() {
+                declare(field, scope);
+            }
+        }
+        Pattern::Ref(inner) => declare(inner, scope),
+        Pattern::Wildcard | Pattern::Int(_) | Pattern::Range { .. } | Pattern::Bool(_) => {}
+    }
+}
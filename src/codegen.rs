@@ -5,14 +5,35 @@ use std::{
 
 use crate::{
     ast::{
-        Assign, Ast, BinOp, BinOpKind, Block, Enclosed, Expr, FnCall, FnDef, Global, IfElse, Init,
-        Loop, Number, Return, Stmt, UnOp, UnOpKind,
+        Assert, Assign, Ast, BinOp, BinOpKind, Block, BoxNew, Call, Closure, CompoundAssign,
+        ConstExpr, Enclosed, Expr, FnCall, FnDef, FnRef, Global, IfElse, Index, Init, Local, Loop,
+        Match, Member, Number, Panic, Pattern, Return, Slice, Static, Stmt, Struct, ToDyn, Tuple,
+        UnOp, UnOpKind, Variant, VecConcat, VecNew, VecPop, VecPush,
     },
+    frame,
+    loader::{locate, Source},
+    mono::{mangle, symbol},
     ty::Type,
 };
 
-const MAX_STACK_SIZE: usize = 8 * 256;
 const ARG_REGS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+/// registers C callers expect to survive a call
+pub const CALLEE_SAVED_REGS: [&str; 5] = ["rbx", "r12", "r13", "r14", "r15"];
+/// words leading every vtable: size, align and drop glue of the type, the methods follow
+const VTABLE_HEADER: usize = 3;
+
+/// what a call jumps to
+#[derive(Debug, Clone, Copy)]
+enum Callee<'a> {
+    /// a function by its symbol
+    Symbol(&'a str),
+    /// a method in the vtable of the receiver, by its slot
+    Vtable(usize),
+    /// the value of a function pointer
+    Pointer(&'a Expr),
+    /// the code of a closure, taking its environment first
+    Closure(&'a Expr),
+}
 
 #[derive(Debug)]
 pub struct SofaGenerater<W: Write> {
@@ -20,15 +41,50 @@ pub struct SofaGenerater<W: Write> {
     label_id: usize,
     offset: usize,
     offset_table: HashMap<String, usize>,
+    /// return type of the current function
+    ret_ty: Type,
+    /// slot holding the address to return large aggregates into
+    ret_slot: Option<usize>,
+    /// callee-saved registers of an exported function and their slots
+    saved_regs: Vec<(&'static str, usize)>,
+    /// locals owning values that need drop: type, slot and drop flag
+    owned: Vec<(Type, usize, usize)>,
+    /// drop flags of the owning locals in scope, set while the local holds a value
+    drop_flags: HashMap<String, usize>,
+    /// files of the crate, to locate panics
+    sources: Vec<Source>,
+    /// whether indexing and slicing check their bounds
+    bounds_checks: bool,
+    /// whether arithmetic checks for overflow and division by zero
+    overflow_checks: bool,
+    /// string literals, emitted after the code
+    strings: Vec<String>,
+    /// vtables in use: label, implementing type and trait method symbols
+    vtables: Vec<(String, Type, Vec<String>)>,
+}
+
+/// aggregates larger than two words are returned through caller provided memory
+pub fn returns_in_memory(ty: &Type) -> bool {
+    ty.is_aggregate() && ty.size() > 16
 }
 
 impl<W: Write> SofaGenerater<W> {
-    pub fn new(writer: W) -> Self {
+    pub fn new(writer: W, sources: &[Source], bounds_checks: bool, overflow_checks: bool) -> Self {
         Self {
             writer: BufWriter::new(writer),
             label_id: 0,
             offset: 0,
             offset_table: HashMap::new(),
+            ret_ty: Type::Unit,
+            ret_slot: None,
+            saved_regs: vec![],
+            owned: vec![],
+            drop_flags: HashMap::new(),
+            sources: sources.to_vec(),
+            bounds_checks,
+            overflow_checks,
+            strings: vec![],
+            vtables: vec![],
         }
     }
 
@@ -45,7 +101,7 @@ impl<W: Write> SofaGenerater<W> {
 
         self.gen_global(&ast.node);
 
-        writeln!(self.writer).unwrap();
+        writeln!(self.writer, "{}", include_str!("runtime.s")).unwrap();
     }
 
     fn gen_global(&mut self, global: &Global) {
@@ -53,32 +109,239 @@ impl<W: Write> SofaGenerater<W> {
             self.gen_fn(f);
             self.offset = 0;
             self.offset_table.clear();
+            self.ret_slot = None;
+            self.saved_regs.clear();
+            self.owned.clear();
+            self.drop_flags.clear();
+        }
+
+        let mut glued: Vec<Type> = vec![];
+        for (_, ty, _) in self.vtables.clone() {
+            if !glued.contains(&ty) {
+                self.gen_drop_glue(&ty);
+                glued.push(ty);
+            }
+        }
+
+        for s in global.statics.iter() {
+            writeln!(self.writer).unwrap();
+            if s.value == 0 {
+                writeln!(self.writer, ".bss").unwrap();
+                writeln!(self.writer, "{}:", s.name).unwrap();
+                writeln!(self.writer, "    .zero {}", s.ty.size()).unwrap();
+            } else {
+                writeln!(self.writer, ".data").unwrap();
+                writeln!(self.writer, "{}:", s.name).unwrap();
+                writeln!(self.writer, "    .quad {}", s.value).unwrap();
+            }
+        }
+
+        if !self.strings.is_empty() || !self.vtables.is_empty() {
+            writeln!(self.writer).unwrap();
+            writeln!(self.writer, ".section .rodata").unwrap();
         }
+        for (i, s) in self.strings.iter().enumerate() {
+            let escaped = s
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            writeln!(self.writer, ".Lstr{}:", i).unwrap();
+            writeln!(self.writer, "    .ascii \"{}\"", escaped).unwrap();
+        }
+
+        for (label, ty, methods) in self.vtables.iter() {
+            writeln!(self.writer, ".balign 8").unwrap();
+            writeln!(self.writer, "{}:", label).unwrap();
+            writeln!(self.writer, "    .quad {}", ty.size()).unwrap();
+            writeln!(self.writer, "    .quad 8").unwrap(); // every type is word aligned
+            writeln!(
+                self.writer,
+                "    .quad {}",
+                mangle("drop", std::slice::from_ref(ty))
+            )
+            .unwrap();
+            for method in methods.iter() {
+                writeln!(
+                    self.writer,
+                    "    .quad {}",
+                    mangle(method, std::slice::from_ref(ty))
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    /// `drop.Type`, drops the value of `ty` at the address in rdi
+    fn gen_drop_glue(&mut self, ty: &Type) {
+        writeln!(self.writer).unwrap();
+        self.gen_prologue(&mangle("drop", std::slice::from_ref(ty)), 0);
+        writeln!(self.writer, "    push rdi").unwrap();
+        self.gen_load(ty);
+        self.gen_drop(ty);
+        self.gen_epilogue();
+    }
+
+    /// label of the vtable for `ty` implementing `trait_name`
+    fn vtable(&mut self, trait_name: &str, ty: &Type, methods: &[String]) -> String {
+        let label = mangle(
+            &format!("vtable.{}", symbol(trait_name)),
+            std::slice::from_ref(ty),
+        );
+        if !self.vtables.iter().any(|(l, _, _)| *l == label) {
+            self.vtables
+                .push((label.clone(), ty.clone(), methods.to_vec()));
+        }
+        label
     }
 
     fn gen_fn(&mut self, f: &FnDef) {
-        // stack_size should be a multiple of 16;
-        let (name, stack_size) = if f.name == "main" {
-            ("main".to_string(), MAX_STACK_SIZE)
+        self.ret_ty = match &f.fn_type {
+            Type::Fn { ret, .. } => *ret.clone(),
+            _ => panic!("function's type must be Fn"),
+        };
+
+        // stack_size should be a multiple of 16
+        let stack_size = frame::size(f);
+
+        if f.exported {
+            writeln!(self.writer, ".global {}", f.name).unwrap();
+        }
+        self.gen_prologue(&f.name, stack_size);
+
+        if frame::has_drop_flags(f) {
+            // drop flags start cleared, clear the whole frame
+            let label = format!(".L{}_clear", self.label_id);
+            self.label_id += 1;
+            writeln!(self.writer, "    mov rax, rsp").unwrap();
+            writeln!(self.writer, "{}:", label).unwrap();
+            writeln!(self.writer, "    mov qword ptr [rax], 0").unwrap();
+            writeln!(self.writer, "    add rax, 8").unwrap();
+            writeln!(self.writer, "    cmp rax, rbp").unwrap();
+            writeln!(self.writer, "    jne {}", label).unwrap();
+        }
+
+        if f.exported {
+            for reg in CALLEE_SAVED_REGS {
+                let slot = self.alloc(8);
+                writeln!(self.writer, "    mov [rbp-{}], {}", slot, reg).unwrap();
+                self.saved_regs.push((reg, slot));
+            }
+        }
+
+        // the hidden return address takes the first argument register
+        let arg_regs = if returns_in_memory(&self.ret_ty) {
+            let slot = self.alloc(8);
+            self.ret_slot = Some(slot);
+            writeln!(self.writer, "    mov [rbp-{}], rdi", slot).unwrap();
+            &ARG_REGS[1..]
         } else {
-            (f.name.clone(), (f.args.len() + 1) / 2 * 2 * 8)
+            &ARG_REGS[..]
         };
 
-        self.gen_prologue(&name, stack_size);
+        let mut by_address = vec![];
+        let mut destructured = vec![];
+        for (i, arg) in f.args.iter().enumerate() {
+            // aggregates arrive as the address of the caller's value
+            let size = if arg.ty.is_aggregate() {
+                8
+            } else {
+                arg.ty.size()
+            };
+            let slot = self.alloc(size);
+            if size > 0 {
+                match arg_regs.get(i) {
+                    Some(reg) => writeln!(self.writer, "    mov [rbp-{}], {}", slot, reg).unwrap(),
+                    None => {
+                        // the rest is above the return address, in order
+                        let k = i - arg_regs.len();
+                        writeln!(self.writer, "    mov rax, [rbp+{}]", 16 + 8 * k).unwrap();
+                        writeln!(self.writer, "    mov [rbp-{}], rax", slot).unwrap();
+                    }
+                }
+            }
+
+            match &arg.pattern {
+                Pattern::Binding { local, .. } => {
+                    self.offset_table.insert(local.name.clone(), slot);
+                    if arg.ty.is_aggregate() {
+                        by_address.push(local);
+                    } else if let Some(flag) = self.own(local, slot) {
+                        writeln!(self.writer, "    mov qword ptr [rbp-{}], 1", flag).unwrap();
+                    }
+                }
+                pattern => destructured.push((pattern, &arg.ty, slot)),
+            }
+        }
 
-        if !f.args.is_empty() {
-            writeln!(self.writer, "    mov rax, rbp").unwrap();
+        // take own copies of aggregate arguments
+        for arg in by_address {
+            let ptr = self.offset_table[&arg.name];
+            let slot = self.alloc(arg.ty.size());
+            self.offset_table.insert(arg.name.clone(), slot);
+
+            writeln!(self.writer, "    lea rax, [rbp-{}]", slot).unwrap();
+            writeln!(self.writer, "    push rax").unwrap();
+            writeln!(self.writer, "    push [rbp-{}]", ptr).unwrap();
+            self.gen_store(&arg.ty);
+            if let Some(flag) = self.own(arg, slot) {
+                writeln!(self.writer, "    mov qword ptr [rbp-{}], 1", flag).unwrap();
+            }
         }
-        for (arg, reg) in f.args.iter().zip(ARG_REGS) {
-            let size = arg.ty.size();
-            self.offset += size;
-            self.offset_table.insert(arg.name.clone(), self.offset);
 
-            writeln!(self.writer, "    sub rax, {}", size).unwrap();
-            writeln!(self.writer, "    mov [rax], {}", reg).unwrap();
+        // bind the parts of destructured arguments
+        for (pattern, ty, slot) in destructured {
+            let base = if ty.is_aggregate() {
+                slot
+            } else {
+                let base = self.alloc(8);
+                writeln!(self.writer, "    lea rax, [rbp-{}]", slot).unwrap();
+                writeln!(self.writer, "    mov [rbp-{}], rax", base).unwrap();
+                base
+            };
+            self.gen_pattern_bind(pattern, ty, base, 0);
         }
 
         self.gen_block(&f.body);
+        self.gen_return(); // value of the tail expression
+
+        assert_eq!(
+            self.offset,
+            frame::used(f),
+            "frame layout of {} is out of sync",
+            f.name
+        );
+    }
+
+    /// pops the value to return into rax (and rdx), then leaves the function
+    fn gen_return(&mut self) {
+        let ty = self.ret_ty.clone();
+        if let Some(slot) = self.ret_slot {
+            // copy into the caller's memory and hand its address back
+            writeln!(self.writer, "    pop rsi").unwrap();
+            writeln!(self.writer, "    mov rdi, [rbp-{}]", slot).unwrap();
+            writeln!(self.writer, "    mov rcx, {}", ty.size()).unwrap();
+            writeln!(self.writer, "    rep movsb").unwrap();
+            writeln!(self.writer, "    mov rax, [rbp-{}]", slot).unwrap();
+        } else if ty.is_aggregate() {
+            writeln!(self.writer, "    pop rsi").unwrap();
+            writeln!(self.writer, "    mov rax, [rsi]").unwrap();
+            if ty.size() > 8 {
+                writeln!(self.writer, "    mov rdx, [rsi+8]").unwrap();
+            }
+        } else {
+            writeln!(self.writer, "    pop rax").unwrap();
+        }
+
+        if !self.owned.is_empty() {
+            // the returned value is already moved out, drop what is left
+            writeln!(self.writer, "    push rax").unwrap();
+            writeln!(self.writer, "    push rdx").unwrap();
+            for (ty, slot, flag) in self.owned.clone().iter().rev() {
+                self.gen_drop_owned(ty, *slot, *flag);
+            }
+            writeln!(self.writer, "    pop rdx").unwrap();
+            writeln!(self.writer, "    pop rax").unwrap();
+        }
         self.gen_epilogue();
     }
 
@@ -86,33 +349,69 @@ impl<W: Write> SofaGenerater<W> {
         writeln!(self.writer, "{}:", name).unwrap();
         writeln!(self.writer, "    push rbp").unwrap();
         writeln!(self.writer, "    mov rbp, rsp").unwrap();
-        writeln!(self.writer, "    sub rsp, {}", stack_size).unwrap();
+        if stack_size > 0 {
+            writeln!(self.writer, "    sub rsp, {}", stack_size).unwrap();
+        }
     }
 
     fn gen_epilogue(&mut self) {
+        for (reg, slot) in self.saved_regs.iter() {
+            writeln!(self.writer, "    mov {}, [rbp-{}]", reg, slot).unwrap();
+        }
         writeln!(self.writer, "    leave").unwrap(); // equivalent to "mov rsp, rbp" and "pop rbp"
         writeln!(self.writer, "    ret").unwrap();
     }
 
     fn gen_block(&mut self, block: &Block) {
-        for expr in block.exprs.iter() {
+        let owned = self.owned.len();
+        if block.exprs.is_empty() {
+            writeln!(self.writer, "    push 0").unwrap(); // unit
+        }
+        for (i, expr) in block.exprs.iter().enumerate() {
+            if i > 0 {
+                // only the last value is the value of the block
+                writeln!(self.writer, "    pop rax").unwrap();
+            }
             self.gen_expr(expr);
         }
+        if !block.owns_locals() {
+            return;
+        }
+
+        // the locals of the block end with it
+        let ty = block.ty();
+        if ty.is_aggregate() {
+            // the value may be in what is dropped, so it moves out first
+            let slot = self.alloc(ty.size());
+            writeln!(self.writer, "    pop rsi").unwrap();
+            writeln!(self.writer, "    lea rdi, [rbp-{}]", slot).unwrap();
+            writeln!(self.writer, "    mov rcx, {}", ty.size()).unwrap();
+            writeln!(self.writer, "    rep movsb").unwrap();
+            writeln!(self.writer, "    lea rax, [rbp-{}]", slot).unwrap();
+            writeln!(self.writer, "    push rax").unwrap();
+        }
+        for (ty, slot, flag) in self.owned.split_off(owned).iter().rev() {
+            self.gen_drop_owned(ty, *slot, *flag);
+        }
     }
 
     fn gen_expr(&mut self, expr: &Expr) {
         match expr {
             Expr::Stmt(Stmt { expr }) => {
                 self.gen_expr(expr);
-                writeln!(self.writer, "    pop rax").unwrap();
+                let ty = expr.ty();
+                if ty.needs_drop() {
+                    self.gen_drop(&ty);
+                } else {
+                    writeln!(self.writer, "    pop rax").unwrap();
+                }
                 writeln!(self.writer, "    push 0").unwrap(); // unit
                 writeln!(self.writer).unwrap();
             }
             Expr::Block(block) => self.gen_block(block),
             Expr::Return(Return { expr }) => {
                 self.gen_expr(expr);
-                writeln!(self.writer, "    pop rax").unwrap();
-                self.gen_epilogue();
+                self.gen_return();
             }
             Expr::Loop(Loop { body }) => {
                 let label = format!(".L{}_loop", self.label_id);
@@ -159,52 +458,147 @@ impl<W: Write> SofaGenerater<W> {
                     writeln!(self.writer, "    cmp rax, 0").unwrap();
                     writeln!(self.writer, "    je {}", label_end).unwrap();
                     self.gen_block(if_body);
+                    writeln!(self.writer, "    pop rax").unwrap();
                     writeln!(self.writer, "{}:", label_end).unwrap();
+                    writeln!(self.writer, "    push 0").unwrap(); // unit
                 }
             }
-            Expr::FnCall(FnCall { name, args, .. }) => {
-                for (expr, reg) in args.iter().zip(ARG_REGS) {
-                    self.gen_expr(expr);
+            Expr::Match(Match { scrutinee, arms }) => {
+                let label_end = format!(".L{}_match_end", self.label_id);
+                self.label_id += 1;
+
+                // keep the address of the scrutinee for every arm to inspect
+                let ty = scrutinee.ty();
+                if arms.iter().any(|arm| arm.pattern.owns()) {
+                    self.gen_expr(scrutinee);
+                } else {
+                    // nothing is moved out, the scrutinee keeps its value
+                    self.gen_borrowed(scrutinee);
+                }
+                let base = self.gen_place(&ty);
+
+                for arm in arms {
+                    let label_next = format!(".L{}_arm", self.label_id);
+                    self.label_id += 1;
+
+                    self.gen_pattern_test(&arm.pattern, &ty, base, 0, &label_next);
+
+                    let outer = self.offset_table.clone();
+                    let outer_flags = self.drop_flags.clone();
+                    let owned = self.owned.len();
+                    self.gen_pattern_bind(&arm.pattern, &ty, base, 0);
+                    self.gen_expr(&arm.body);
+                    for (ty, slot, flag) in self.owned.split_off(owned).iter().rev() {
+                        self.gen_drop_owned(ty, *slot, *flag);
+                    }
+                    self.offset_table = outer;
+                    self.drop_flags = outer_flags;
+
+                    writeln!(self.writer, "    jmp {}", label_end).unwrap();
+                    writeln!(self.writer, "{}:", label_next).unwrap();
+                }
+                writeln!(self.writer, "    ud2").unwrap(); // no arm matched
+
+                writeln!(self.writer, "{}:", label_end).unwrap();
+            }
+            Expr::FnCall(FnCall {
+                name,
+                args,
+                fn_type,
+                dispatch,
+                ..
+            }) => {
+                // functions defined elsewhere may be called without a declaration
+                let ret = match fn_type {
+                    Type::Fn { ret, .. } => *ret.clone(),
+                    _ => Type::Unknown,
+                };
+                let callee = match dispatch {
+                    Some(slot) => Callee::Vtable(*slot),
+                    None => Callee::Symbol(name),
+                };
+                self.gen_call(callee, args, &ret);
+            }
+            Expr::Call(Call { callee, args }) => {
+                let callee = match callee.ty() {
+                    Type::Fn { .. } => Callee::Pointer(callee),
+                    _ => Callee::Closure(callee),
+                };
+                self.gen_call(callee, args, &expr.ty());
+            }
+            Expr::FnRef(FnRef { name, .. }) => {
+                writeln!(self.writer, "    lea rax, [rip+{}]", name).unwrap();
+                writeln!(self.writer, "    push rax").unwrap();
+            }
+            Expr::Closure(Closure { name, captures, .. }) => {
+                // the environment holds the address of each capture
+                let env = self.alloc(8 * captures.len());
+                for (i, local) in captures.iter().enumerate() {
+                    self.gen_address(&Expr::Local(local.clone()));
                     writeln!(self.writer, "    pop rax").unwrap();
-                    writeln!(self.writer, "    mov {}, rax", reg).unwrap();
+                    writeln!(self.writer, "    mov [rbp-{}], rax", env - 8 * i).unwrap();
                 }
-                writeln!(self.writer, "    call {}", name).unwrap();
+                let slot = self.alloc(16);
+                writeln!(self.writer, "    lea rax, [rip+{}]", name).unwrap();
+                writeln!(self.writer, "    mov [rbp-{}], rax", slot).unwrap();
+                if captures.is_empty() {
+                    writeln!(self.writer, "    mov qword ptr [rbp-{}], 0", slot - 8).unwrap();
+                } else {
+                    writeln!(self.writer, "    lea rax, [rbp-{}]", env).unwrap();
+                    writeln!(self.writer, "    mov [rbp-{}], rax", slot - 8).unwrap();
+                }
+                writeln!(self.writer, "    lea rax, [rbp-{}]", slot).unwrap();
                 writeln!(self.writer, "    push rax").unwrap();
             }
-            Expr::Init(Init { name, value }) => {
-                if let Expr::Local(local) = &**name {
+            Expr::Init(Init {
+                pattern: Pattern::Binding { local, .. },
+                value,
+            }) => {
+                {
                     let size = local.ty.size();
                     self.offset += size;
-                    self.offset_table.insert(local.name.clone(), self.offset);
+                    let slot = self.offset;
+                    self.offset_table.insert(local.name.clone(), slot);
+                    let flag = self.own(local, slot);
+                    if let Some(flag) = flag {
+                        // the value of the previous iteration of a loop
+                        self.gen_drop_owned(&local.ty, slot, flag);
+                    }
 
                     writeln!(self.writer, "    mov rax, rbp").unwrap(); // retrieve rbp into rax
-                    writeln!(self.writer, "    sub rax, {}", self.offset).unwrap(); // local stored at offset from rbp
+                    writeln!(self.writer, "    sub rax, {}", slot).unwrap(); // local stored at offset from rbp
                     writeln!(self.writer, "    push rax").unwrap(); // return local's address
 
                     if let Some(value) = value {
                         assert_eq!(local.ty, value.ty());
 
                         self.gen_expr(value);
-
-                        writeln!(self.writer, "    pop rdi").unwrap();
-                        writeln!(self.writer, "    pop rax").unwrap();
-                        writeln!(self.writer, "    mov [rax], rdi").unwrap();
-                        writeln!(self.writer, "    push 0").unwrap(); // void
+                        self.gen_store(&local.ty);
+                        if let Some(flag) = flag {
+                            writeln!(self.writer, "    mov qword ptr [rbp-{}], 1", flag).unwrap();
+                        }
+                        writeln!(self.writer, "    push 0").unwrap(); // unit
                     } else {
                         writeln!(self.writer, "    pop rax").unwrap();
-                        writeln!(self.writer, "    push 0").unwrap(); // void
+                        writeln!(self.writer, "    push 0").unwrap(); // unit
                     }
-                } else {
-                    panic!("lhs must be addressable")
                 }
             }
+            Expr::Init(Init { pattern, value }) => {
+                let value = value.as_ref().expect("destructuring needs a value");
+                let ty = value.ty();
+                self.gen_expr(value);
+                let base = self.gen_place(&ty);
+                self.gen_pattern_bind(pattern, &ty, base, 0);
+                writeln!(self.writer, "    push 0").unwrap(); // unit
+            }
             Expr::Assign(Assign { lhs, rhs }) => {
                 match &**lhs {
                     Expr::UnOp(UnOp {
                         kind: UnOpKind::Deref,
                         expr,
                     }) => {
-                        self.gen_expr(expr);
+                        self.gen_borrowed(expr);
                     }
                     _ => {
                         assert_eq!(lhs.ty(), rhs.ty());
@@ -212,33 +606,73 @@ impl<W: Write> SofaGenerater<W> {
                     }
                 }
                 self.gen_expr(rhs);
-
+                let ty = rhs.ty();
+                if !ty.needs_drop() {
+                    self.gen_store(&ty);
+                } else if let Expr::Local(Local { name, .. }) = &**lhs {
+                    // the local may have been moved out
+                    let (slot, flag) = (self.offset_table[name], self.drop_flags[name]);
+                    self.gen_drop_owned(&ty, slot, flag);
+                    self.gen_store(&ty);
+                    writeln!(self.writer, "    mov qword ptr [rbp-{}], 1", flag).unwrap();
+                } else {
+                    // drop the old value at the address below the new one
+                    writeln!(self.writer, "    push [rsp+8]").unwrap();
+                    self.gen_load(&ty);
+                    self.gen_drop(&ty);
+                    self.gen_store(&ty);
+                }
+                writeln!(self.writer, "    push 0").unwrap(); // unit
+            }
+            Expr::CompoundAssign(CompoundAssign { op, lhs, rhs, pos }) => {
+                assert_eq!(expr.ty(), Type::Unit);
+                self.gen_address(lhs);
+                // keep the address for the store, load the current value through a copy
+                writeln!(self.writer, "    push [rsp]").unwrap();
+                self.gen_load(&lhs.ty());
+                self.gen_expr(rhs);
                 writeln!(self.writer, "    pop rdi").unwrap();
                 writeln!(self.writer, "    pop rax").unwrap();
-                writeln!(self.writer, "    mov [rax], rdi").unwrap();
-                writeln!(self.writer, "    push 0").unwrap(); // void
+                self.gen_arith(op, *pos, false);
+                writeln!(self.writer, "    push rax").unwrap();
+                self.gen_store(&lhs.ty());
+                writeln!(self.writer, "    push 0").unwrap(); // unit
             }
             Expr::BinOp(binop) => {
                 self.gen_binop(binop);
             }
-            Expr::UnOp(UnOp { kind, expr }) => match kind {
-                UnOpKind::Neg => {
+            e @ Expr::UnOp(UnOp { kind, expr }) => match kind {
+                UnOpKind::Neg { pos } => {
                     self.gen_expr(expr);
                     writeln!(self.writer, "    pop rax").unwrap();
                     writeln!(self.writer, "    neg rax").unwrap();
+                    if self.overflow_checks {
+                        // only `-i64::MIN` overflows
+                        self.gen_check("jno", "attempt to negate with overflow", *pos);
+                    }
+                    writeln!(self.writer, "    push rax").unwrap();
+                }
+                UnOpKind::Not => {
+                    self.gen_expr(expr);
+                    writeln!(self.writer, "    pop rax").unwrap();
+                    match expr.ty() {
+                        Type::Bool => writeln!(self.writer, "    xor rax, 1").unwrap(),
+                        _ => writeln!(self.writer, "    not rax").unwrap(),
+                    }
                     writeln!(self.writer, "    push rax").unwrap();
                 }
-                UnOpKind::Ref => {
+                UnOpKind::Ref | UnOpKind::RefMut => {
                     // TODO:
                     // take reference of imm is not yet supported,
                     // like, &10 or &&a
                     self.gen_address(expr);
                 }
                 UnOpKind::Deref => {
-                    self.gen_expr(expr);
-                    writeln!(self.writer, "    pop rax").unwrap();
-                    writeln!(self.writer, "    mov rax, [rax]").unwrap();
-                    writeln!(self.writer, "    push rax").unwrap();
+                    if e.ty().needs_drop() {
+                        panic!("cannot move {:?} out of a dereference", e.ty())
+                    }
+                    self.gen_borrowed(expr);
+                    self.gen_load(&e.ty());
                 }
             },
             Expr::Enclosed(Enclosed { expr }) => self.gen_expr(expr),
@@ -246,21 +680,742 @@ impl<W: Write> SofaGenerater<W> {
                 crate::ast::Bool::True => writeln!(self.writer, "    push 1").unwrap(),
                 crate::ast::Bool::False => writeln!(self.writer, "    push 0").unwrap(),
             },
-            Expr::Local(local) => {
+            Expr::Tuple(Tuple { elements }) if elements.is_empty() => {
+                writeln!(self.writer, "    push 0").unwrap(); // unit
+            }
+            Expr::Tuple(Tuple { elements }) => self.gen_members(&expr.ty(), elements),
+            Expr::Struct(Struct { ty, fields }) => self.gen_members(ty, fields),
+            Expr::Index(_) => {
+                if expr.ty().needs_drop() {
+                    panic!("cannot move {:?} out of an index", expr.ty())
+                }
+                self.gen_address(expr);
+                self.gen_load(&expr.ty());
+            }
+            Expr::Member(_) => {
+                if expr.ty().needs_drop() {
+                    panic!("cannot move {:?} out of a field", expr.ty())
+                }
+                self.gen_address(expr);
+                self.gen_load(&expr.ty());
+            }
+            Expr::Variant(Variant { ty, index, fields }) => {
+                let def = match ty {
+                    Type::Enum { variants, .. } => &variants[*index],
+                    _ => unreachable!(),
+                };
+                let slot = self.alloc(ty.size());
+
+                writeln!(self.writer, "    mov rax, rbp").unwrap();
+                writeln!(self.writer, "    sub rax, {}", slot).unwrap();
+                writeln!(self.writer, "    mov qword ptr [rax], {}", index).unwrap(); // tag
+
+                for (i, field) in fields.iter().enumerate() {
+                    // payload follows the tag word
+                    writeln!(self.writer, "    mov rax, rbp").unwrap();
+                    writeln!(
+                        self.writer,
+                        "    sub rax, {}",
+                        slot - 8 - def.field_offset(i)
+                    )
+                    .unwrap();
+                    writeln!(self.writer, "    push rax").unwrap();
+                    self.gen_expr(field);
+                    self.gen_store(&field.ty());
+                }
+
+                writeln!(self.writer, "    mov rax, rbp").unwrap();
+                writeln!(self.writer, "    sub rax, {}", slot).unwrap();
+                writeln!(self.writer, "    push rax").unwrap();
+            }
+            Expr::Local(Local { name, ty }) if ty.needs_drop() => {
+                // moves the value out, the local no longer drops it
+                self.gen_address(expr);
+                self.gen_load(ty);
+                let flag = self.drop_flags[name];
+                writeln!(self.writer, "    mov qword ptr [rbp-{}], 0", flag).unwrap();
+            }
+            Expr::Local(Local { ty, .. }) | Expr::Static(Static { ty, .. }) => {
                 self.gen_address(expr);
+                self.gen_load(ty);
+            }
+            Expr::Slice(Slice {
+                expr, lo, hi, pos, ..
+            }) => {
+                let slot = self.alloc(16);
+                let element = match expr.ty() {
+                    Type::Array { element, len } => {
+                        self.gen_borrowed(expr);
+                        writeln!(self.writer, "    push {}", len.to_usize()).unwrap();
+                        element
+                    }
+                    Type::Slice { element, .. } | Type::Vec { element, .. } => {
+                        // the pointer and length lead both
+                        self.gen_borrowed(expr);
+                        writeln!(self.writer, "    pop rax").unwrap();
+                        writeln!(self.writer, "    push [rax]").unwrap();
+                        writeln!(self.writer, "    push [rax+8]").unwrap();
+                        element
+                    }
+                    ty => panic!("cannot slice {:?}", ty),
+                };
+
+                // bounds default to the whole range
+                match lo {
+                    Some(lo) => self.gen_expr(lo),
+                    None => writeln!(self.writer, "    push 0").unwrap(),
+                }
+                match hi {
+                    Some(hi) => self.gen_expr(hi),
+                    None => writeln!(self.writer, "    push [rsp+8]").unwrap(),
+                }
+                writeln!(self.writer, "    pop rsi").unwrap(); // hi
+                writeln!(self.writer, "    pop rdi").unwrap(); // lo
+                writeln!(self.writer, "    pop rdx").unwrap(); // len
+                writeln!(self.writer, "    pop rax").unwrap(); // ptr
+
+                if self.bounds_checks {
+                    let label_fail = format!(".L{}_range_fail", self.label_id);
+                    self.label_id += 1;
+                    let label_ok = format!(".L{}_range_ok", self.label_id);
+                    self.label_id += 1;
+
+                    // unsigned, so negative bounds are out of range too
+                    writeln!(self.writer, "    cmp rdi, rsi").unwrap();
+                    writeln!(self.writer, "    ja {}", label_fail).unwrap();
+                    writeln!(self.writer, "    cmp rsi, rdx").unwrap();
+                    writeln!(self.writer, "    jbe {}", label_ok).unwrap();
+                    writeln!(self.writer, "{}:", label_fail).unwrap();
+                    writeln!(self.writer, "    mov r8, rdx").unwrap();
+                    writeln!(self.writer, "    mov rdx, rdi").unwrap();
+                    writeln!(self.writer, "    mov rcx, rsi").unwrap();
+                    self.gen_panic("sofa_panic_range", *pos);
+                    writeln!(self.writer, "{}:", label_ok).unwrap();
+                }
+
+                writeln!(self.writer, "    sub rsi, rdi").unwrap();
+                writeln!(self.writer, "    mov [rbp-{}], rsi", slot - 8).unwrap();
+                writeln!(self.writer, "    imul rdi, {}", element.size()).unwrap();
+                writeln!(self.writer, "    add rax, rdi").unwrap();
+                writeln!(self.writer, "    mov [rbp-{}], rax", slot).unwrap();
+                writeln!(self.writer, "    lea rax, [rbp-{}]", slot).unwrap();
+                writeln!(self.writer, "    push rax").unwrap();
+            }
+            Expr::VecNew(VecNew { ty }) => {
+                let slot = self.alloc(ty.size());
+                for i in 0..3 {
+                    writeln!(self.writer, "    mov qword ptr [rbp-{}], 0", slot - 8 * i).unwrap();
+                }
+                writeln!(self.writer, "    lea rax, [rbp-{}]", slot).unwrap();
+                writeln!(self.writer, "    push rax").unwrap();
+            }
+            Expr::VecPush(VecPush { vec, value }) => {
+                let ty = value.ty();
+                let label = format!(".L{}_room", self.label_id);
+                self.label_id += 1;
+
+                self.gen_address(vec);
+                self.gen_expr(value);
+
+                writeln!(self.writer, "    mov rax, [rsp+8]").unwrap();
+                writeln!(self.writer, "    mov rdi, [rax+8]").unwrap();
+                writeln!(self.writer, "    cmp rdi, [rax+16]").unwrap();
+                writeln!(self.writer, "    jne {}", label).unwrap();
+                writeln!(self.writer, "    mov rdi, rax").unwrap();
+                writeln!(self.writer, "    mov rsi, {}", ty.size()).unwrap();
+                writeln!(self.writer, "    call sofa_vec_grow").unwrap();
+                writeln!(self.writer, "{}:", label).unwrap();
+
+                // replace the address of the vec with the address of the new element
+                writeln!(self.writer, "    mov rax, [rsp+8]").unwrap();
+                writeln!(self.writer, "    mov rdi, [rax+8]").unwrap();
+                writeln!(self.writer, "    add qword ptr [rax+8], 1").unwrap();
+                writeln!(self.writer, "    imul rdi, {}", ty.size()).unwrap();
+                writeln!(self.writer, "    add rdi, [rax]").unwrap();
+                writeln!(self.writer, "    mov [rsp+8], rdi").unwrap();
+                self.gen_store(&ty);
+                writeln!(self.writer, "    push 0").unwrap(); // unit
+            }
+            Expr::VecConcat(VecConcat { lhs, rhs, ty }) => {
+                let element = match ty {
+                    Type::Vec { element, .. } => element.size(),
+                    _ => unreachable!(),
+                };
+                let slot = self.alloc(ty.size());
+
+                self.gen_expr(lhs);
+                writeln!(self.writer, "    pop rsi").unwrap();
+                for i in 0..3 {
+                    writeln!(self.writer, "    mov rax, [rsi+{}]", 8 * i).unwrap();
+                    writeln!(self.writer, "    mov [rbp-{}], rax", slot - 8 * i).unwrap();
+                }
+                self.gen_expr(rhs);
+                writeln!(self.writer, "    pop rsi").unwrap();
+                writeln!(self.writer, "    lea rdi, [rbp-{}]", slot).unwrap();
+                writeln!(self.writer, "    mov rdx, {}", element).unwrap();
+                writeln!(self.writer, "    call sofa_vec_append").unwrap();
+                writeln!(self.writer, "    lea rax, [rbp-{}]", slot).unwrap();
+                writeln!(self.writer, "    push rax").unwrap();
+            }
+            Expr::VecPop(VecPop { vec, pos }) => {
+                let ty = expr.ty();
 
-                if matches!(local.ty, Type::Array { .. }) {
-                    // leave address
+                self.gen_address(vec);
+                writeln!(self.writer, "    pop rax").unwrap();
+                writeln!(self.writer, "    mov rdi, [rax+8]").unwrap();
+                writeln!(self.writer, "    cmp rdi, 0").unwrap();
+                self.gen_check("jne", "pop from empty vec", *pos);
+                writeln!(self.writer, "    sub rdi, 1").unwrap();
+                writeln!(self.writer, "    mov [rax+8], rdi").unwrap();
+                writeln!(self.writer, "    imul rdi, {}", ty.size()).unwrap();
+                writeln!(self.writer, "    add rdi, [rax]").unwrap();
+                writeln!(self.writer, "    push rdi").unwrap();
+
+                if ty.is_aggregate() {
+                    // the next push reuses the element, take a copy
+                    let slot = self.alloc(ty.size());
+                    writeln!(self.writer, "    pop rsi").unwrap();
+                    writeln!(self.writer, "    lea rax, [rbp-{}]", slot).unwrap();
+                    writeln!(self.writer, "    push rax").unwrap();
+                    writeln!(self.writer, "    push rsi").unwrap();
+                    self.gen_store(&ty);
+                    writeln!(self.writer, "    lea rax, [rbp-{}]", slot).unwrap();
+                    writeln!(self.writer, "    push rax").unwrap();
                 } else {
-                    writeln!(self.writer, "    pop rax").unwrap();
-                    writeln!(self.writer, "    mov rax, [rax]").unwrap(); // address into value on itself
+                    self.gen_load(&ty);
+                }
+            }
+            Expr::BoxNew(BoxNew { value }) => {
+                let ty = value.ty();
+                writeln!(self.writer, "    mov rdi, {}", ty.size()).unwrap();
+                writeln!(self.writer, "    call sofa_alloc").unwrap();
+                writeln!(self.writer, "    push rax").unwrap(); // the box
+                writeln!(self.writer, "    push rax").unwrap(); // address to store into
+                self.gen_expr(value);
+                self.gen_store(&ty);
+            }
+            Expr::ToDyn(ToDyn { value, ty, methods }) => {
+                let vtable = match (ty, value.ty()) {
+                    (Type::Dyn { name, .. }, Type::Ptr { to, .. } | Type::Box { to }) => {
+                        self.vtable(name, &to, methods)
+                    }
+                    _ => unreachable!(),
+                };
+                let slot = self.alloc(16);
+
+                self.gen_expr(value);
+                writeln!(self.writer, "    pop rax").unwrap();
+                writeln!(self.writer, "    mov [rbp-{}], rax", slot).unwrap();
+                writeln!(self.writer, "    lea rax, [rip+{}]", vtable).unwrap();
+                writeln!(self.writer, "    mov [rbp-{}], rax", slot - 8).unwrap();
+                writeln!(self.writer, "    lea rax, [rbp-{}]", slot).unwrap();
+                writeln!(self.writer, "    push rax").unwrap();
+            }
+            Expr::Panic(Panic { message, pos }) => {
+                self.gen_panic_message(message, *pos);
+                writeln!(self.writer, "    push 0").unwrap(); // never
+            }
+            Expr::Assert(Assert { cond, message, pos }) => {
+                let label = format!(".L{}_assert", self.label_id);
+                self.label_id += 1;
+
+                self.gen_expr(cond);
+                writeln!(self.writer, "    pop rax").unwrap();
+                writeln!(self.writer, "    cmp rax, 0").unwrap();
+                writeln!(self.writer, "    jne {}", label).unwrap();
+                self.gen_panic_message(message.as_deref().unwrap_or("assertion failed"), *pos);
+                writeln!(self.writer, "{}:", label).unwrap();
+                writeln!(self.writer, "    push 0").unwrap(); // unit
+            }
+            Expr::ConstExpr(ConstExpr { value }) => {
+                unreachable!("{:?} is instantiated before codegen", value)
+            }
+            Expr::Number(Number { value }) => {
+                if value.parse::<i32>().is_ok() {
+                    writeln!(self.writer, "    push {}", value).unwrap(); // num is imm
+                } else {
+                    // push only takes 32 bit immediates
+                    writeln!(self.writer, "    mov rax, {}", value).unwrap();
                     writeln!(self.writer, "    push rax").unwrap();
                 }
             }
-            Expr::Number(Number { value }) => writeln!(self.writer, "    push {}", value).unwrap(), // num is imm
         }
     }
 
+    /// builds a tuple or struct from its members in a new slot, and pushes its address
+    fn gen_members(&mut self, ty: &Type, members: &[Expr]) {
+        let slot = self.alloc(ty.size());
+
+        for (i, member) in members.iter().enumerate() {
+            let (offset, _) = ty.member(i);
+            writeln!(self.writer, "    lea rax, [rbp-{}]", slot - offset).unwrap();
+            writeln!(self.writer, "    push rax").unwrap();
+            self.gen_expr(member);
+            self.gen_store(&member.ty());
+        }
+
+        writeln!(self.writer, "    lea rax, [rbp-{}]", slot).unwrap();
+        writeln!(self.writer, "    push rax").unwrap();
+    }
+
+    /// calls `callee` with `args` and pushes the result
+    fn gen_call(&mut self, callee: Callee, args: &[Expr], ret: &Type) {
+        let in_memory = returns_in_memory(ret);
+        let arg_regs = if in_memory {
+            &ARG_REGS[1..]
+        } else {
+            &ARG_REGS[..]
+        };
+
+        // evaluate the callee and every argument before loading registers,
+        // later arguments may clobber them
+        if let Callee::Pointer(expr) | Callee::Closure(expr) = callee {
+            self.gen_expr(expr);
+        }
+        for (i, expr) in args.iter().enumerate() {
+            if i == 0 && matches!(callee, Callee::Vtable(_)) {
+                // the receiver stays where it is, only its pointers are read
+                self.gen_borrowed(expr);
+            } else {
+                self.gen_expr(expr);
+            }
+        }
+        let words = match callee {
+            Callee::Pointer(_) | Callee::Closure(_) => 1 + args.len(),
+            Callee::Symbol(_) | Callee::Vtable(_) => args.len(),
+        };
+
+        // each parameter is the word at a depth, or the word loaded from an offset of it
+        let mut params: Vec<(usize, Option<usize>)> =
+            (0..args.len()).rev().map(|depth| (depth, None)).collect();
+        match callee {
+            // `self` is the data pointer of the receiver
+            Callee::Vtable(_) => params[0].1 = Some(0),
+            // the environment goes first
+            Callee::Closure(_) => params.insert(0, (words - 1, Some(8))),
+            Callee::Symbol(_) | Callee::Pointer(_) => {}
+        }
+        let in_regs = params.len().min(arg_regs.len());
+        let on_stack = params.len() - in_regs;
+        for (reg, (depth, offset)) in arg_regs.iter().zip(params.iter()) {
+            writeln!(self.writer, "    mov {}, [rsp+{}]", reg, 8 * depth).unwrap();
+            if let Some(offset) = offset {
+                writeln!(self.writer, "    mov {0}, [{0}+{1}]", reg, offset).unwrap();
+            }
+        }
+        if in_memory {
+            let slot = self.alloc(ret.size());
+            writeln!(self.writer, "    lea rdi, [rbp-{}]", slot).unwrap();
+        }
+
+        // the stack machine leaves rsp anywhere, align it to 16 bytes for the call
+        // and keep the original right above the aligned boundary
+        writeln!(self.writer, "    mov rax, rsp").unwrap();
+        writeln!(self.writer, "    and rsp, -16").unwrap();
+        if on_stack.is_multiple_of(2) {
+            writeln!(self.writer, "    sub rsp, 8").unwrap();
+        }
+        writeln!(self.writer, "    push rax").unwrap();
+        // the rest of the arguments, pushed right to left
+        for (depth, _) in params[in_regs..].iter().rev() {
+            writeln!(self.writer, "    push [rax+{}]", 8 * depth).unwrap();
+        }
+        match callee {
+            Callee::Symbol(name) => {
+                // no vector registers are used for variadic arguments
+                writeln!(self.writer, "    mov al, 0").unwrap();
+                writeln!(self.writer, "    call {}", name).unwrap();
+            }
+            Callee::Vtable(slot) => {
+                // the vtable of the receiver, found through the saved rsp
+                self.gen_callee_word(words, on_stack);
+                writeln!(self.writer, "    mov rax, [rax+8]").unwrap();
+                writeln!(self.writer, "    call [rax+{}]", 8 * (VTABLE_HEADER + slot)).unwrap();
+            }
+            Callee::Pointer(_) => {
+                self.gen_callee_word(words, on_stack);
+                writeln!(self.writer, "    call rax").unwrap();
+            }
+            Callee::Closure(_) => {
+                self.gen_callee_word(words, on_stack);
+                writeln!(self.writer, "    call [rax]").unwrap();
+            }
+        }
+        if on_stack > 0 {
+            writeln!(self.writer, "    add rsp, {}", 8 * on_stack).unwrap();
+        }
+        writeln!(self.writer, "    pop rsp").unwrap();
+        if words > 0 {
+            // drop the evaluated callee and arguments
+            writeln!(self.writer, "    add rsp, {}", 8 * words).unwrap();
+        }
+
+        if ret.is_aggregate() && !in_memory {
+            // small aggregates come back in rax and rdx
+            let slot = self.alloc(ret.size().max(8).next_multiple_of(8));
+            writeln!(self.writer, "    mov [rbp-{}], rax", slot).unwrap();
+            if ret.size() > 8 {
+                writeln!(self.writer, "    mov [rbp-{}], rdx", slot - 8).unwrap();
+            }
+            writeln!(self.writer, "    lea rax, [rbp-{}]", slot).unwrap();
+        }
+        if *ret == Type::Bool {
+            // C only sets `al` for a `bool`
+            writeln!(self.writer, "    movzx eax, al").unwrap();
+        }
+        writeln!(self.writer, "    push rax").unwrap();
+    }
+
+    /// loads into rax the first word evaluated for a call, through the rsp saved below the
+    /// `on_stack` arguments
+    fn gen_callee_word(&mut self, words: usize, on_stack: usize) {
+        writeln!(self.writer, "    mov rax, [rsp+{}]", 8 * on_stack).unwrap();
+        writeln!(self.writer, "    mov rax, [rax+{}]", 8 * (words - 1)).unwrap();
+    }
+
+    /// reserves `size` bytes in the current frame, returns the offset from rbp
+    fn alloc(&mut self, size: usize) -> usize {
+        self.offset += size;
+        self.offset
+    }
+
+    /// makes `local` in `slot` own its value if it needs drop, returns its drop flag
+    fn own(&mut self, local: &Local, slot: usize) -> Option<usize> {
+        if !local.ty.needs_drop() {
+            return None;
+        }
+        let flag = self.alloc(8);
+        self.drop_flags.insert(local.name.clone(), flag);
+        self.owned.push((local.ty.clone(), slot, flag));
+        Some(flag)
+    }
+
+    /// drops the value of `ty` in `slot` if its drop flag is set, and clears the flag
+    fn gen_drop_owned(&mut self, ty: &Type, slot: usize, flag: usize) {
+        let label = format!(".L{}_dropped", self.label_id);
+        self.label_id += 1;
+
+        writeln!(self.writer, "    cmp qword ptr [rbp-{}], 0", flag).unwrap();
+        writeln!(self.writer, "    je {}", label).unwrap();
+        writeln!(self.writer, "    mov qword ptr [rbp-{}], 0", flag).unwrap();
+        writeln!(self.writer, "    lea rax, [rbp-{}]", slot).unwrap();
+        writeln!(self.writer, "    push rax").unwrap();
+        self.gen_load(ty);
+        self.gen_drop(ty);
+        writeln!(self.writer, "{}:", label).unwrap();
+    }
+
+    /// pops a value of `ty` and frees every box it owns
+    fn gen_drop(&mut self, ty: &Type) {
+        match ty {
+            Type::Box { to } => {
+                if to.needs_drop() {
+                    writeln!(self.writer, "    push [rsp]").unwrap();
+                    self.gen_load(to);
+                    self.gen_drop(to);
+                }
+                writeln!(self.writer, "    pop rdi").unwrap();
+                writeln!(self.writer, "    call sofa_free").unwrap();
+                return;
+            }
+            Type::Dyn { boxed: true, .. } => {
+                // the drop glue of the value, then its allocation
+                writeln!(self.writer, "    mov rax, [rsp]").unwrap();
+                writeln!(self.writer, "    mov rdi, [rax]").unwrap();
+                writeln!(self.writer, "    mov rax, [rax+8]").unwrap();
+                writeln!(self.writer, "    call [rax+16]").unwrap();
+                writeln!(self.writer, "    pop rax").unwrap();
+                writeln!(self.writer, "    mov rdi, [rax]").unwrap();
+                writeln!(self.writer, "    call sofa_free").unwrap();
+                return;
+            }
+            Type::Tuple { elements } => {
+                for i in 0..elements.len() {
+                    let (offset, member) = ty.member(i);
+                    self.gen_drop_member(&member, offset);
+                }
+            }
+            Type::Struct { fields, .. } => {
+                for i in 0..fields.len() {
+                    let (offset, member) = ty.member(i);
+                    self.gen_drop_member(&member, offset);
+                }
+            }
+            Type::Enum { variants, .. } => {
+                for (index, def) in variants.iter().enumerate() {
+                    if !def.fields.iter().any(|f| f.ty.needs_drop()) {
+                        continue;
+                    }
+                    let label = format!(".L{}_drop_next", self.label_id);
+                    self.label_id += 1;
+
+                    writeln!(self.writer, "    mov rax, [rsp]").unwrap();
+                    writeln!(self.writer, "    cmp qword ptr [rax], {}", index).unwrap();
+                    writeln!(self.writer, "    jne {}", label).unwrap();
+                    for (i, field) in def.fields.iter().enumerate() {
+                        self.gen_drop_member(&field.ty, 8 + def.field_offset(i));
+                    }
+                    writeln!(self.writer, "{}:", label).unwrap();
+                }
+            }
+            Type::Array { element, len } if element.needs_drop() => {
+                self.gen_drop_elements(element, Some(len.to_usize()));
+            }
+            Type::Vec { element, .. } => {
+                if element.needs_drop() {
+                    self.gen_drop_elements(element, None);
+                }
+                writeln!(self.writer, "    mov rax, [rsp]").unwrap();
+                writeln!(self.writer, "    mov rdi, [rax]").unwrap();
+                writeln!(self.writer, "    call sofa_free").unwrap();
+            }
+            _ => {}
+        }
+        writeln!(self.writer, "    pop rax").unwrap();
+    }
+
+    /// drops the elements of the array of `len`, or the vec, at the address on top of the stack,
+    /// keeping it
+    fn gen_drop_elements(&mut self, element: &Type, len: Option<usize>) {
+        let label = format!(".L{}_drop_loop", self.label_id);
+        self.label_id += 1;
+        let label_end = format!(".L{}_drop_end", self.label_id);
+        self.label_id += 1;
+
+        // index on top of the address
+        writeln!(self.writer, "    push 0").unwrap();
+        writeln!(self.writer, "{}:", label).unwrap();
+        writeln!(self.writer, "    mov rax, [rsp]").unwrap();
+        match len {
+            Some(len) => writeln!(self.writer, "    cmp rax, {}", len).unwrap(),
+            None => {
+                writeln!(self.writer, "    mov rdi, [rsp+8]").unwrap();
+                writeln!(self.writer, "    cmp rax, [rdi+8]").unwrap();
+            }
+        }
+        writeln!(self.writer, "    je {}", label_end).unwrap();
+        writeln!(self.writer, "    imul rax, {}", element.size()).unwrap();
+        match len {
+            Some(_) => writeln!(self.writer, "    add rax, [rsp+8]").unwrap(),
+            None => writeln!(self.writer, "    add rax, [rdi]").unwrap(),
+        }
+        writeln!(self.writer, "    push rax").unwrap();
+        self.gen_load(element);
+        self.gen_drop(element);
+        writeln!(self.writer, "    add qword ptr [rsp], 1").unwrap();
+        writeln!(self.writer, "    jmp {}", label).unwrap();
+        writeln!(self.writer, "{}:", label_end).unwrap();
+        writeln!(self.writer, "    pop rax").unwrap();
+    }
+
+    /// drops the member of `ty` at `offset` from the address on top of the stack, keeping it
+    fn gen_drop_member(&mut self, ty: &Type, offset: usize) {
+        if ty.needs_drop() {
+            writeln!(self.writer, "    mov rax, [rsp]").unwrap();
+            writeln!(self.writer, "    add rax, {}", offset).unwrap();
+            writeln!(self.writer, "    push rax").unwrap();
+            self.gen_load(ty);
+            self.gen_drop(ty);
+        }
+    }
+
+    /// pushes the value of `expr` without moving it out of its place
+    fn gen_borrowed(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Local(_)
+            | Expr::Member(_)
+            | Expr::Index(_)
+            | Expr::UnOp(UnOp {
+                kind: UnOpKind::Deref,
+                ..
+            }) => {
+                self.gen_address(expr);
+                self.gen_load(&expr.ty());
+            }
+            Expr::Enclosed(Enclosed { expr }) => self.gen_borrowed(expr),
+            _ => self.gen_expr(expr),
+        }
+    }
+
+    /// pops a value of `ty` and keeps its address in a new slot, returns the slot
+    fn gen_place(&mut self, ty: &Type) -> usize {
+        if !ty.is_aggregate() && *ty != Type::Unit {
+            let slot = self.alloc(ty.size());
+            writeln!(self.writer, "    pop rax").unwrap();
+            writeln!(self.writer, "    mov [rbp-{}], rax", slot).unwrap();
+            writeln!(self.writer, "    lea rax, [rbp-{}]", slot).unwrap();
+            writeln!(self.writer, "    push rax").unwrap();
+        }
+        let base = self.alloc(8);
+        writeln!(self.writer, "    pop rax").unwrap();
+        writeln!(self.writer, "    mov [rbp-{}], rax", base).unwrap();
+        base
+    }
+
+    /// pops an address and pushes the value of `ty` stored there
+    fn gen_load(&mut self, ty: &Type) {
+        if ty.is_aggregate() {
+            // leave address
+        } else if *ty == Type::Unit {
+            writeln!(self.writer, "    pop rax").unwrap();
+            writeln!(self.writer, "    push 0").unwrap(); // unit takes no space
+        } else if *ty == Type::U8 {
+            writeln!(self.writer, "    pop rax").unwrap();
+            writeln!(self.writer, "    movzx eax, byte ptr [rax]").unwrap();
+            writeln!(self.writer, "    push rax").unwrap();
+        } else {
+            writeln!(self.writer, "    pop rax").unwrap();
+            writeln!(self.writer, "    mov rax, [rax]").unwrap(); // address into value on itself
+            writeln!(self.writer, "    push rax").unwrap();
+        }
+    }
+
+    /// pops a value of `ty` and then an address, and stores the value there
+    fn gen_store(&mut self, ty: &Type) {
+        if ty.is_aggregate() {
+            writeln!(self.writer, "    pop rsi").unwrap();
+            writeln!(self.writer, "    pop rdi").unwrap();
+            writeln!(self.writer, "    mov rcx, {}", ty.size()).unwrap();
+            writeln!(self.writer, "    rep movsb").unwrap();
+        } else if *ty == Type::Unit {
+            writeln!(self.writer, "    pop rdi").unwrap();
+            writeln!(self.writer, "    pop rax").unwrap(); // unit takes no space
+        } else if *ty == Type::U8 {
+            writeln!(self.writer, "    pop rdi").unwrap();
+            writeln!(self.writer, "    pop rax").unwrap();
+            writeln!(self.writer, "    mov [rax], dil").unwrap();
+        } else {
+            writeln!(self.writer, "    pop rdi").unwrap();
+            writeln!(self.writer, "    pop rax").unwrap();
+            writeln!(self.writer, "    mov [rax], rdi").unwrap();
+        }
+    }
+
+    /// jumps to `fail` unless the value of `ty` at `offset` from the address in `[rbp-base]`
+    /// matches `pattern`
+    fn gen_pattern_test(
+        &mut self,
+        pattern: &Pattern,
+        ty: &Type,
+        base: usize,
+        offset: usize,
+        fail: &str,
+    ) {
+        let expected = match pattern {
+            Pattern::Wildcard | Pattern::Binding { .. } => return,
+            Pattern::Ref(inner) => {
+                let (to, base) = self.gen_deref_place(ty, base, offset);
+                self.gen_pattern_test(inner, &to, base, 0, fail);
+                return;
+            }
+            Pattern::Tuple(fields) | Pattern::Struct(fields) => {
+                for (i, field) in fields.iter().enumerate() {
+                    let (member_offset, member_ty) = ty.member(i);
+                    self.gen_pattern_test(field, &member_ty, base, offset + member_offset, fail);
+                }
+                return;
+            }
+            Pattern::Range { lo, hi } => {
+                writeln!(self.writer, "    mov rax, [rbp-{}]", base).unwrap();
+                writeln!(self.writer, "    mov rax, [rax+{}]", offset).unwrap();
+                writeln!(self.writer, "    mov rdi, {}", lo).unwrap();
+                writeln!(self.writer, "    cmp rax, rdi").unwrap();
+                writeln!(self.writer, "    jl {}", fail).unwrap();
+                writeln!(self.writer, "    mov rdi, {}", hi).unwrap();
+                writeln!(self.writer, "    cmp rax, rdi").unwrap();
+                writeln!(self.writer, "    jg {}", fail).unwrap();
+                return;
+            }
+            Pattern::Int(value) => *value,
+            Pattern::Bool(crate::ast::Bool::True) => 1,
+            Pattern::Bool(crate::ast::Bool::False) => 0,
+            Pattern::Variant { index, .. } => *index as i64,
+        };
+
+        writeln!(self.writer, "    mov rax, [rbp-{}]", base).unwrap();
+        writeln!(self.writer, "    mov rax, [rax+{}]", offset).unwrap();
+        writeln!(self.writer, "    mov rdi, {}", expected).unwrap();
+        writeln!(self.writer, "    cmp rax, rdi").unwrap();
+        writeln!(self.writer, "    jne {}", fail).unwrap();
+
+        if let Pattern::Variant {
+            ty: Type::Enum { variants, .. },
+            index,
+            fields,
+        } = pattern
+        {
+            let def = &variants[*index];
+            for (i, field) in fields.iter().enumerate() {
+                let field_offset = offset + 8 + def.field_offset(i);
+                self.gen_pattern_test(field, &def.fields[i].ty, base, field_offset, fail);
+            }
+        }
+    }
+
+    /// copies the parts of a matched value into the bindings of `pattern`
+    fn gen_pattern_bind(&mut self, pattern: &Pattern, ty: &Type, base: usize, offset: usize) {
+        match pattern {
+            Pattern::Binding { local, .. } => {
+                let slot = self.alloc(local.ty.size());
+                self.offset_table.insert(local.name.clone(), slot);
+                let flag = self.own(local, slot);
+                if let Some(flag) = flag {
+                    self.gen_drop_owned(&local.ty, slot, flag);
+                }
+
+                writeln!(self.writer, "    lea rax, [rbp-{}]", slot).unwrap();
+                writeln!(self.writer, "    push rax").unwrap();
+                writeln!(self.writer, "    mov rax, [rbp-{}]", base).unwrap();
+                writeln!(self.writer, "    add rax, {}", offset).unwrap();
+                writeln!(self.writer, "    push rax").unwrap();
+                self.gen_load(&local.ty);
+                self.gen_store(&local.ty);
+                if let Some(flag) = flag {
+                    writeln!(self.writer, "    mov qword ptr [rbp-{}], 1", flag).unwrap();
+                }
+            }
+            Pattern::Variant {
+                ty: Type::Enum { variants, .. },
+                index,
+                fields,
+            } => {
+                let def = &variants[*index];
+                for (i, field) in fields.iter().enumerate() {
+                    let field_offset = offset + 8 + def.field_offset(i);
+                    self.gen_pattern_bind(field, &def.fields[i].ty, base, field_offset);
+                }
+            }
+            Pattern::Tuple(fields) | Pattern::Struct(fields) => {
+                for (i, field) in fields.iter().enumerate() {
+                    let (member_offset, member_ty) = ty.member(i);
+                    self.gen_pattern_bind(field, &member_ty, base, offset + member_offset);
+                }
+            }
+            Pattern::Ref(inner) => {
+                if inner.owns() {
+                    panic!("cannot move out of {:?} behind a reference", ty)
+                }
+                let (to, base) = self.gen_deref_place(ty, base, offset);
+                self.gen_pattern_bind(inner, &to, base, 0);
+            }
+            _ => {}
+        }
+    }
+
+    /// keeps the pointer at `offset` from the address in `[rbp-base]` in a new slot,
+    /// returns the pointee type and the slot
+    fn gen_deref_place(&mut self, ty: &Type, base: usize, offset: usize) -> (Type, usize) {
+        let to = match ty {
+            Type::Ptr { to, .. } => *to.clone(),
+            _ => panic!("only pointer type can be dereferenced"),
+        };
+        let slot = self.alloc(8);
+        writeln!(self.writer, "    mov rax, [rbp-{}]", base).unwrap();
+        writeln!(self.writer, "    mov rax, [rax+{}]", offset).unwrap();
+        writeln!(self.writer, "    mov [rbp-{}], rax", slot).unwrap();
+        (to, slot)
+    }
+
     fn gen_address(&mut self, expr: &Expr) {
         match expr {
             Expr::Local(local) => {
@@ -273,21 +1428,125 @@ impl<W: Write> SofaGenerater<W> {
                 writeln!(self.writer, "    sub rax, {}", offset).unwrap(); // local stored at offset from rbp
                 writeln!(self.writer, "    push rax").unwrap(); // return local's address
             }
+            Expr::Static(Static { name, .. }) => {
+                writeln!(self.writer, "    lea rax, [rip+{}]", name).unwrap();
+                writeln!(self.writer, "    push rax").unwrap();
+            }
             Expr::UnOp(UnOp {
                 kind: UnOpKind::Deref,
                 expr,
-            }) => match &**expr {
-                Expr::UnOp(_) => self.gen_address(expr),
-                Expr::Local(_) => {
-                    self.gen_expr(expr);
+            }) => {
+                // the address of `*p` is the value of `p`
+                self.gen_borrowed(expr);
+            }
+            Expr::Member(Member { expr, index }) => {
+                // aggregates evaluate to their address
+                let (offset, _) = expr.ty().member(*index);
+                self.gen_borrowed(expr);
+                writeln!(self.writer, "    pop rax").unwrap();
+                writeln!(self.writer, "    add rax, {}", offset).unwrap();
+                writeln!(self.writer, "    push rax").unwrap();
+            }
+            Expr::Index(Index { expr, index, pos }) => {
+                let element = match expr.ty() {
+                    Type::Array { element, .. } | Type::Ptr { to: element, .. } => {
+                        self.gen_borrowed(expr);
+                        element
+                    }
+                    Type::Slice { element, .. } | Type::Vec { element, .. } => {
+                        // the pointer and length lead both
+                        self.gen_borrowed(expr);
+                        writeln!(self.writer, "    pop rax").unwrap();
+                        writeln!(self.writer, "    push [rax]").unwrap();
+                        writeln!(self.writer, "    push [rax+8]").unwrap();
+                        element
+                    }
+                    ty => panic!("cannot index into {:?}", ty),
+                };
+                self.gen_expr(index);
+                writeln!(self.writer, "    pop rdi").unwrap();
+
+                let len = match expr.ty() {
+                    Type::Array { len, .. } => Some(len.to_usize().to_string()),
+                    Type::Slice { .. } | Type::Vec { .. } => {
+                        writeln!(self.writer, "    pop rsi").unwrap();
+                        Some("rsi".to_string())
+                    }
+                    // pointers do not know how far they reach
+                    _ => None,
+                };
+                if let (true, Some(len)) = (self.bounds_checks, len) {
+                    let label = format!(".L{}_in_bounds", self.label_id);
+                    self.label_id += 1;
+
+                    // unsigned, so negative indices are out of bounds too
+                    writeln!(self.writer, "    cmp rdi, {}", len).unwrap();
+                    writeln!(self.writer, "    jb {}", label).unwrap();
+                    writeln!(self.writer, "    mov rdx, rdi").unwrap();
+                    writeln!(self.writer, "    mov rcx, {}", len).unwrap();
+                    self.gen_panic("sofa_panic_bounds", *pos);
+                    writeln!(self.writer, "{}:", label).unwrap();
                 }
-                _ => panic!("invalid lval {:?}", expr),
-            },
+
+                writeln!(self.writer, "    pop rax").unwrap();
+                writeln!(self.writer, "    imul rdi, {}", element.size()).unwrap();
+                writeln!(self.writer, "    add rax, rdi").unwrap();
+                writeln!(self.writer, "    push rax").unwrap();
+            }
+            // a temporary aggregate evaluates to the address of its slot
+            e if e.ty().is_aggregate() => self.gen_expr(e),
             _ => panic!("invalid lval {:?}", expr),
         }
     }
 
-    fn gen_binop(&mut self, BinOp { op, lhs, rhs }: &BinOp) {
+    /// calls the panic `routine` with the location of `pos` in the first two arguments,
+    /// it does not return
+    fn gen_panic(&mut self, routine: &str, pos: usize) {
+        let (file, line, col) = locate(&self.sources, pos);
+        let message = format!("panicked at {}:{}:{}:\n", file, line, col);
+
+        let id = self.string(&message);
+        writeln!(self.writer, "    lea rdi, [rip+.Lstr{}]", id).unwrap();
+        writeln!(self.writer, "    mov rsi, {}", message.len()).unwrap();
+        writeln!(self.writer, "    call {}", routine).unwrap();
+    }
+
+    /// index of `s` in the string literals, shared between its uses
+    fn string(&mut self, s: &str) -> usize {
+        self.strings.iter().position(|t| t == s).unwrap_or_else(|| {
+            self.strings.push(s.to_string());
+            self.strings.len() - 1
+        })
+    }
+
+    /// continues if the flags satisfy `jump`, panics at `pos` with `message` otherwise
+    fn gen_check(&mut self, jump: &str, message: &str, pos: usize) {
+        let label = format!(".L{}_ok", self.label_id);
+        self.label_id += 1;
+
+        writeln!(self.writer, "    {} {}", jump, label).unwrap();
+        self.gen_panic_message(message, pos);
+        writeln!(self.writer, "{}:", label).unwrap();
+    }
+
+    /// panics at `pos` with `message`
+    fn gen_panic_message(&mut self, message: &str, pos: usize) {
+        let id = self.string(message);
+        writeln!(self.writer, "    lea rdx, [rip+.Lstr{}]", id).unwrap();
+        writeln!(self.writer, "    mov rcx, {}", message.len()).unwrap();
+        self.gen_panic("sofa_panic", pos);
+    }
+
+    fn gen_binop(
+        &mut self,
+        BinOp {
+            op,
+            lhs,
+            rhs,
+            pos,
+            wrapping,
+        }: &BinOp,
+    ) {
         match (op, lhs.ty(), rhs.ty()) {
             (
                 BinOpKind::Add
@@ -297,10 +1556,12 @@ impl<W: Write> SofaGenerater<W> {
                 | BinOpKind::Rem
                 | BinOpKind::BitAnd
                 | BinOpKind::BitOr
-                | BinOpKind::BitXor,
+                | BinOpKind::BitXor
+                | BinOpKind::Shl
+                | BinOpKind::Shr,
                 Type::I64,
                 Type::I64,
-            ) => self.gen_math(op, lhs, rhs),
+            ) => self.gen_math(op, lhs, rhs, *pos, *wrapping),
 
             (
                 BinOpKind::Eq
@@ -311,10 +1572,20 @@ impl<W: Write> SofaGenerater<W> {
                 | BinOpKind::Gt,
                 Type::I64,
                 Type::I64,
+            )
+            | (
+                BinOpKind::Eq
+                | BinOpKind::Neq
+                | BinOpKind::LeEq
+                | BinOpKind::Le
+                | BinOpKind::GtEq
+                | BinOpKind::Gt,
+                Type::U8,
+                Type::U8,
             ) => self.gen_cmp(op, lhs, rhs),
 
-            (BinOpKind::Add, Type::Ptr { to }, Type::I64) => {
-                self.gen_expr(lhs);
+            (BinOpKind::Add, Type::Ptr { to, .. }, Type::I64) => {
+                self.gen_borrowed(lhs);
                 self.gen_expr(rhs);
 
                 writeln!(self.writer, "    pop rax").unwrap();
@@ -326,8 +1597,8 @@ impl<W: Write> SofaGenerater<W> {
                 writeln!(self.writer, "    add rax, rdi").unwrap();
             }
 
-            (BinOpKind::Sub, Type::Ptr { to }, Type::I64) => {
-                self.gen_expr(lhs);
+            (BinOpKind::Sub, Type::Ptr { to, .. }, Type::I64) => {
+                self.gen_borrowed(lhs);
                 self.gen_expr(rhs);
 
                 writeln!(self.writer, "    pop rax").unwrap();
@@ -340,7 +1611,7 @@ impl<W: Write> SofaGenerater<W> {
             }
 
             (BinOpKind::Add, Type::Array { element, len: _ }, Type::I64) => {
-                self.gen_expr(lhs);
+                self.gen_borrowed(lhs);
                 self.gen_expr(rhs);
 
                 writeln!(self.writer, "    pop rax").unwrap();
@@ -411,29 +1682,94 @@ impl<W: Write> SofaGenerater<W> {
         writeln!(self.writer, "    push rax").unwrap();
     }
 
-    fn gen_math(&mut self, op: &BinOpKind, lhs: &Expr, rhs: &Expr) {
+    fn gen_math(&mut self, op: &BinOpKind, lhs: &Expr, rhs: &Expr, pos: usize, wrapping: bool) {
         self.gen_expr(lhs);
         self.gen_expr(rhs);
 
         writeln!(self.writer, "    pop rdi").unwrap();
         writeln!(self.writer, "    pop rax").unwrap();
+        self.gen_arith(op, pos, wrapping);
+    }
 
+    /// computes `rax op rdi` into rax, panicking at `pos` when it overflows unless `wrapping`
+    fn gen_arith(&mut self, op: &BinOpKind, pos: usize, wrapping: bool) {
+        let checked = self.overflow_checks && !wrapping;
         match op {
-            BinOpKind::Add => writeln!(self.writer, "    add rax, rdi").unwrap(),
-            BinOpKind::Sub => writeln!(self.writer, "    sub rax, rdi").unwrap(),
-            BinOpKind::Mul => writeln!(self.writer, "    imul rax, rdi").unwrap(),
-            BinOpKind::Div => {
-                writeln!(self.writer, "    cqo").unwrap();
-                writeln!(self.writer, "    idiv rdi").unwrap();
+            BinOpKind::Add | BinOpKind::Sub | BinOpKind::Mul => {
+                let (instr, verb) = match op {
+                    BinOpKind::Add => ("add", "add"),
+                    BinOpKind::Sub => ("sub", "subtract"),
+                    _ => ("imul", "multiply"),
+                };
+                writeln!(self.writer, "    {} rax, rdi", instr).unwrap();
+                if checked {
+                    self.gen_check("jno", &format!("attempt to {} with overflow", verb), pos);
+                }
             }
-            BinOpKind::Rem => {
+            BinOpKind::Div | BinOpKind::Rem => {
+                let div = *op == BinOpKind::Div;
+                if self.overflow_checks {
+                    writeln!(self.writer, "    test rdi, rdi").unwrap();
+                    self.gen_check(
+                        "jnz",
+                        if div {
+                            "attempt to divide by zero"
+                        } else {
+                            "attempt to calculate the remainder with a divisor of zero"
+                        },
+                        pos,
+                    );
+                }
+
+                // idiv faults on `i64::MIN / -1`
+                let label_idiv = format!(".L{}_idiv", self.label_id);
+                self.label_id += 1;
+                let label_end = format!(".L{}_idiv_end", self.label_id);
+                self.label_id += 1;
+                if wrapping || checked {
+                    writeln!(self.writer, "    cmp rdi, -1").unwrap();
+                    writeln!(self.writer, "    jne {}", label_idiv).unwrap();
+                }
+                if wrapping {
+                    if div {
+                        writeln!(self.writer, "    neg rax").unwrap();
+                    } else {
+                        writeln!(self.writer, "    xor eax, eax").unwrap();
+                    }
+                    writeln!(self.writer, "    jmp {}", label_end).unwrap();
+                } else if checked {
+                    writeln!(self.writer, "    mov rdx, {}", i64::MIN).unwrap();
+                    writeln!(self.writer, "    cmp rax, rdx").unwrap();
+                    self.gen_check(
+                        "jne",
+                        if div {
+                            "attempt to divide with overflow"
+                        } else {
+                            "attempt to calculate the remainder with overflow"
+                        },
+                        pos,
+                    );
+                }
+                writeln!(self.writer, "{}:", label_idiv).unwrap();
                 writeln!(self.writer, "    cqo").unwrap();
                 writeln!(self.writer, "    idiv rdi").unwrap();
-                writeln!(self.writer, "    mov rax, rdx").unwrap();
+                if !div {
+                    writeln!(self.writer, "    mov rax, rdx").unwrap();
+                }
+                writeln!(self.writer, "{}:", label_end).unwrap();
             }
             BinOpKind::BitAnd => writeln!(self.writer, "    and rax, rdi").unwrap(),
             BinOpKind::BitOr => writeln!(self.writer, "    or rax, rdi").unwrap(),
             BinOpKind::BitXor => writeln!(self.writer, "    xor rax, rdi").unwrap(),
+            BinOpKind::Shl => {
+                writeln!(self.writer, "    mov rcx, rdi").unwrap();
+                writeln!(self.writer, "    sal rax, cl").unwrap();
+            }
+            BinOpKind::Shr => {
+                // i64 is signed, so shift arithmetically
+                writeln!(self.writer, "    mov rcx, rdi").unwrap();
+                writeln!(self.writer, "    sar rax, cl").unwrap();
+            }
             _ => unreachable!(),
         };
     }
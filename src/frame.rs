@@ -0,0 +1,293 @@
+//! stack frame sizes, computed before generating a function
+//!
+//! slots are never reused, so the frame is the sum of every slot the generator takes.
+//! each rule here mirrors an allocation in `codegen`.
+
+use crate::{
+    ast::{
+        Assert, Assign, BinOp, Block, BoxNew, Call, Closure, CompoundAssign, Enclosed, Expr,
+        FnCall, FnDef, IfElse, Index, Init, Local, Loop, Match, Member, Pattern, Return, Slice,
+        Stmt, Struct, ToDyn, Tuple, UnOp, Variant, VecConcat, VecNew, VecPop, VecPush,
+    },
+    codegen::{returns_in_memory, CALLEE_SAVED_REGS},
+    ty::Type,
+};
+
+/// bytes below rbp used by `f`, rounded up to keep rsp 16-byte aligned
+pub fn size(f: &FnDef) -> usize {
+    used(f).next_multiple_of(16)
+}
+
+/// bytes below rbp used by `f`
+pub fn used(f: &FnDef) -> usize {
+    let mut size = 0;
+    if f.exported {
+        size += 8 * CALLEE_SAVED_REGS.len();
+    }
+    if let Type::Fn { ret, .. } = &f.fn_type {
+        if returns_in_memory(ret) {
+            size += 8; // hidden return address
+        }
+    }
+
+    for arg in f.args.iter() {
+        if let Pattern::Binding { local, .. } = &arg.pattern {
+            size += flag(local);
+        }
+        if arg.ty.is_aggregate() {
+            size += 8; // address of the caller's value
+            size += match arg.pattern {
+                Pattern::Binding { .. } => arg.ty.size(), // own copy
+                _ => bind(&arg.pattern, &arg.ty),
+            };
+        } else {
+            size += arg.ty.size();
+            if !matches!(arg.pattern, Pattern::Binding { .. }) {
+                size += 8 + bind(&arg.pattern, &arg.ty);
+            }
+        }
+    }
+
+    size + block(&f.body)
+}
+
+/// whether `f` keeps drop flags, which must start cleared
+pub fn has_drop_flags(f: &FnDef) -> bool {
+    f.args.iter().any(|arg| arg.pattern.owns()) || block_owns(&f.body)
+}
+
+fn block_owns(block: &Block) -> bool {
+    block.exprs.iter().any(owns)
+}
+
+fn owns(e: &Expr) -> bool {
+    match e {
+        Expr::Stmt(Stmt { expr: e })
+        | Expr::Return(Return { expr: e })
+        | Expr::UnOp(UnOp { expr: e, .. })
+        | Expr::Enclosed(Enclosed { expr: e })
+        | Expr::Member(Member { expr: e, .. })
+        | Expr::BoxNew(BoxNew { value: e })
+        | Expr::ToDyn(ToDyn { value: e, .. })
+        | Expr::Assert(Assert { cond: e, .. })
+        | Expr::VecPop(VecPop { vec: e, .. }) => owns(e),
+        Expr::Slice(Slice { expr, lo, hi, .. }) => {
+            owns(expr) || lo.as_deref().is_some_and(owns) || hi.as_deref().is_some_and(owns)
+        }
+        Expr::Block(b) | Expr::Loop(Loop { body: b }) => block_owns(b),
+        Expr::IfElse(IfElse {
+            cond,
+            if_body,
+            else_body,
+        }) => owns(cond) || block_owns(if_body) || else_body.as_ref().is_some_and(block_owns),
+        Expr::Match(Match { scrutinee, arms }) => {
+            owns(scrutinee) || arms.iter().any(|arm| arm.pattern.owns() || owns(&arm.body))
+        }
+        Expr::Init(Init { pattern, value }) => pattern.owns() || value.as_deref().is_some_and(owns),
+        Expr::Assign(Assign { lhs, rhs })
+        | Expr::CompoundAssign(CompoundAssign { lhs, rhs, .. })
+        | Expr::BinOp(BinOp { lhs, rhs, .. })
+        | Expr::Index(Index {
+            expr: lhs,
+            index: rhs,
+            ..
+        })
+        | Expr::VecPush(VecPush {
+            vec: lhs,
+            value: rhs,
+        })
+        | Expr::VecConcat(VecConcat { lhs, rhs, .. }) => owns(lhs) || owns(rhs),
+        Expr::Call(Call { callee, args }) => owns(callee) || args.iter().any(owns),
+        Expr::FnCall(FnCall { args: members, .. })
+        | Expr::Tuple(Tuple { elements: members })
+        | Expr::Struct(Struct {
+            fields: members, ..
+        })
+        | Expr::Variant(Variant {
+            fields: members, ..
+        }) => members.iter().any(owns),
+        Expr::VecNew(_)
+        | Expr::Panic(_)
+        | Expr::Bool(_)
+        | Expr::Local(_)
+        | Expr::Static(_)
+        | Expr::Closure(_)
+        | Expr::FnRef(_)
+        | Expr::ConstExpr(_)
+        | Expr::Number(_) => false,
+    }
+}
+
+fn block(block: &Block) -> usize {
+    let exprs = block.exprs.iter().map(expr).sum::<usize>();
+    match block.ty() {
+        // the value moves out of the locals dropped at the end
+        ty if block.owns_locals() && ty.is_aggregate() => exprs + ty.size(),
+        _ => exprs,
+    }
+}
+
+fn expr(e: &Expr) -> usize {
+    match e {
+        Expr::Stmt(Stmt { expr: e })
+        | Expr::Return(Return { expr: e })
+        | Expr::UnOp(UnOp { expr: e, .. })
+        | Expr::Enclosed(Enclosed { expr: e })
+        | Expr::Member(Member { expr: e, .. })
+        | Expr::BoxNew(BoxNew { value: e })
+        | Expr::Assert(Assert { cond: e, .. }) => expr(e),
+        Expr::VecPop(VecPop { vec, .. }) => {
+            let ty = e.ty();
+            expr(vec) + if ty.is_aggregate() { ty.size() } else { 0 }
+        }
+        Expr::Slice(Slice {
+            expr: e, lo, hi, ..
+        }) => 16 + expr(e) + lo.as_deref().map_or(0, expr) + hi.as_deref().map_or(0, expr),
+        Expr::VecNew(VecNew { ty }) => ty.size(),
+        Expr::ToDyn(ToDyn { value, .. }) => 16 + expr(value),
+        Expr::Block(b) | Expr::Loop(Loop { body: b }) => block(b),
+        Expr::IfElse(IfElse {
+            cond,
+            if_body,
+            else_body,
+        }) => expr(cond) + block(if_body) + else_body.as_ref().map_or(0, block),
+        Expr::Match(Match { scrutinee, arms }) => {
+            let ty = scrutinee.ty();
+            expr(scrutinee)
+                + place(&ty)
+                + arms
+                    .iter()
+                    .map(|arm| test(&arm.pattern, &ty) + bind(&arm.pattern, &ty) + expr(&arm.body))
+                    .sum::<usize>()
+        }
+        Expr::FnCall(FnCall { args, fn_type, .. }) => {
+            let ret = match fn_type {
+                Type::Fn { ret, .. } => *ret.clone(),
+                _ => Type::Unknown,
+            };
+            call(args, &ret)
+        }
+        Expr::Call(Call { callee, args }) => expr(callee) + call(args, &e.ty()),
+        // the addresses of the captures, then the code and environment
+        Expr::Closure(Closure { captures, .. }) => 8 * captures.len() + 16,
+        Expr::Init(Init {
+            pattern: Pattern::Binding { local, .. },
+            value,
+        }) => local.ty.size() + flag(local) + value.as_deref().map_or(0, expr),
+        Expr::Init(Init { pattern, value }) => {
+            let value = value.as_ref().expect("destructuring needs a value");
+            let ty = value.ty();
+            expr(value) + place(&ty) + bind(pattern, &ty)
+        }
+        Expr::Assign(Assign { lhs, rhs })
+        | Expr::CompoundAssign(CompoundAssign { lhs, rhs, .. })
+        | Expr::BinOp(BinOp { lhs, rhs, .. })
+        | Expr::Index(Index {
+            expr: lhs,
+            index: rhs,
+            ..
+        })
+        | Expr::VecPush(VecPush {
+            vec: lhs,
+            value: rhs,
+        }) => expr(lhs) + expr(rhs),
+        // the moved `lhs` grows in a slot of its own
+        Expr::VecConcat(VecConcat { lhs, rhs, ty }) => ty.size() + expr(lhs) + expr(rhs),
+        Expr::Tuple(Tuple { elements }) if elements.is_empty() => 0,
+        Expr::Tuple(Tuple { elements: members })
+        | Expr::Struct(Struct {
+            fields: members, ..
+        })
+        | Expr::Variant(Variant {
+            fields: members, ..
+        }) => e.ty().size() + members.iter().map(expr).sum::<usize>(),
+        Expr::Panic(_)
+        | Expr::Bool(_)
+        | Expr::Local(_)
+        | Expr::Static(_)
+        | Expr::FnRef(_)
+        | Expr::ConstExpr(_)
+        | Expr::Number(_) => 0,
+    }
+}
+
+/// slots of `gen_call`, the arguments and the result
+fn call(args: &[Expr], ret: &Type) -> usize {
+    let result = if returns_in_memory(ret) {
+        ret.size()
+    } else if ret.is_aggregate() {
+        ret.size().max(8).next_multiple_of(8)
+    } else {
+        0
+    };
+    args.iter().map(expr).sum::<usize>() + result
+}
+
+/// slot of the drop flag of `local`, if it owns a value that needs drop
+fn flag(local: &Local) -> usize {
+    if local.ty.needs_drop() {
+        8
+    } else {
+        0
+    }
+}
+
+/// slots of `gen_place`
+fn place(ty: &Type) -> usize {
+    if !ty.is_aggregate() && *ty != Type::Unit {
+        ty.size() + 8
+    } else {
+        8
+    }
+}
+
+/// slots of `gen_pattern_test`
+fn test(pattern: &Pattern, ty: &Type) -> usize {
+    match pattern {
+        Pattern::Ref(inner) => match ty {
+            Type::Ptr { to, .. } => 8 + test(inner, to),
+            _ => panic!("only pointer type can be dereferenced"),
+        },
+        Pattern::Tuple(fields) | Pattern::Struct(fields) => fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| test(field, &ty.member(i).1))
+            .sum(),
+        Pattern::Variant {
+            ty: Type::Enum { variants, .. },
+            index,
+            fields,
+        } => fields
+            .iter()
+            .zip(variants[*index].fields.iter())
+            .map(|(field, def)| test(field, &def.ty))
+            .sum(),
+        _ => 0,
+    }
+}
+
+/// slots of `gen_pattern_bind`
+fn bind(pattern: &Pattern, ty: &Type) -> usize {
+    match pattern {
+        Pattern::Binding { local, .. } => local.ty.size() + flag(local),
+        Pattern::Ref(inner) => match ty {
+            Type::Ptr { to, .. } => 8 + bind(inner, to),
+            _ => panic!("only pointer type can be dereferenced"),
+        },
+        Pattern::Tuple(fields) | Pattern::Struct(fields) => fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| bind(field, &ty.member(i).1))
+            .sum(),
+        Pattern::Variant {
+            ty: Type::Enum { variants, .. },
+            index,
+            fields,
+        } => fields
+            .iter()
+            .zip(variants[*index].fields.iter())
+            .map(|(field, def)| bind(field, &def.ty))
+            .sum(),
+        _ => 0,
+    }
+}
@@ -0,0 +1,73 @@
+//! C declarations of the functions exported with `pub extern "C" fn`
+
+use std::io::{BufWriter, Write};
+
+use crate::{
+    ast::{Ast, Pattern},
+    ty::Type,
+};
+
+pub fn gen<W: Write>(ast: &Ast, writer: W) {
+    let mut writer = BufWriter::new(writer);
+
+    writeln!(writer, "#pragma once").unwrap();
+    writeln!(writer).unwrap();
+    writeln!(writer, "#include <stdbool.h>").unwrap();
+    writeln!(writer, "#include <stdint.h>").unwrap();
+    writeln!(writer).unwrap();
+
+    for f in ast.node.definitions.iter().filter(|f| f.exported) {
+        let ret = match &f.fn_type {
+            Type::Fn { ret, .. } => c_type(ret),
+            _ => panic!("function's type must be Fn"),
+        };
+        let args = f
+            .args
+            .iter()
+            .enumerate()
+            .map(|(i, arg)| match &arg.pattern {
+                Pattern::Binding { local, .. } => c_decl(&arg.ty, &local.name),
+                _ => c_decl(&arg.ty, &format!("arg{}", i)),
+            })
+            .collect::<Vec<_>>();
+        let args = if args.is_empty() {
+            "void".to_string()
+        } else {
+            args.join(", ")
+        };
+
+        writeln!(writer, "{} {}({});", ret, f.name, args).unwrap();
+    }
+}
+
+/// `int64_t x` or `int64_t *p`
+fn c_decl(ty: &Type, name: &str) -> String {
+    let ty = c_type(ty);
+    if ty.ends_with('*') {
+        format!("{}{}", ty, name)
+    } else {
+        format!("{} {}", ty, name)
+    }
+}
+
+fn c_type(ty: &Type) -> String {
+    match ty {
+        Type::I64 => "int64_t".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::U8 => "uint8_t".to_string(),
+        Type::Unit => "void".to_string(),
+        Type::Ptr { to, mutable } => {
+            let to = match &**to {
+                // `void *` cannot be written through either way
+                Type::Unit => "void".to_string(),
+                to => c_type(to),
+            };
+            if *mutable {
+                format!("{} *", to)
+            } else {
+                format!("const {} *", to)
+            }
+        }
+        _ => panic!("{:?} has no C counterpart", ty),
+    }
+}
@@ -47,6 +47,26 @@ pub enum TokenKind {
     Comma,
     /// .
     Dot,
+    /// +=
+    PlusEq,
+    /// -=
+    MinusEq,
+    /// *=
+    StarEq,
+    /// /=
+    SlashEq,
+    /// %=
+    PercentEq,
+    /// &=
+    AndEq,
+    /// |=
+    OrEq,
+    /// ^=
+    CaretEq,
+    /// <<=
+    ShlEq,
+    /// >>=
+    ShrEq,
 
     // keywords
     Fn,
@@ -55,6 +75,20 @@ pub enum TokenKind {
     Else,
     Loop,
     Return,
+    Match,
+    Enum,
+    Struct,
+    Mut,
+    Const,
+    Static,
+    Extern,
+    Pub,
+    Impl,
+    Trait,
+    For,
+    Mod,
+    Use,
+    Type,
 
     /// boolean
     True,
@@ -64,6 +98,8 @@ pub enum TokenKind {
     Ident,
     /// number literal
     Number,
+    /// string literal, without quotes
+    Str,
 
     /// whitespace
     Whitespace,
@@ -92,6 +128,20 @@ const KEYWORDS: &[(&str, TokenKind)] = &[
     ("else", TokenKind::Else),
     ("loop", TokenKind::Loop),
     ("return", TokenKind::Return),
+    ("match", TokenKind::Match),
+    ("enum", TokenKind::Enum),
+    ("struct", TokenKind::Struct),
+    ("mut", TokenKind::Mut),
+    ("const", TokenKind::Const),
+    ("static", TokenKind::Static),
+    ("extern", TokenKind::Extern),
+    ("pub", TokenKind::Pub),
+    ("impl", TokenKind::Impl),
+    ("trait", TokenKind::Trait),
+    ("for", TokenKind::For),
+    ("mod", TokenKind::Mod),
+    ("use", TokenKind::Use),
+    ("type", TokenKind::Type),
     ("true", TokenKind::True),
     ("false", TokenKind::False),
 ];
@@ -218,6 +268,24 @@ impl<'a> Cursor<'a> {
                 }
             }
 
+            // string literal
+            '"' => {
+                self.bump();
+                let mut s = String::new();
+                while self.next() != &'"' {
+                    if self.is_eof() {
+                        panic!("unterminated string literal at {}", current_pos)
+                    }
+                    s.push(self.bump().unwrap());
+                }
+                self.bump();
+                Token {
+                    kind: TokenKind::Str,
+                    value: Some(s),
+                    pos: self.update_pos(),
+                }
+            }
+
             // punctuations
             '=' => {
                 self.bump();
@@ -229,24 +297,42 @@ impl<'a> Cursor<'a> {
             }
             '+' => {
                 self.bump();
+                let kind = if self.next() == &'=' {
+                    self.bump();
+                    TokenKind::PlusEq
+                } else {
+                    TokenKind::Plus
+                };
                 Token {
-                    kind: TokenKind::Plus,
+                    kind,
                     value: None,
                     pos: self.update_pos(),
                 }
             }
             '-' => {
                 self.bump();
+                let kind = if self.next() == &'=' {
+                    self.bump();
+                    TokenKind::MinusEq
+                } else {
+                    TokenKind::Minus
+                };
                 Token {
-                    kind: TokenKind::Minus,
+                    kind,
                     value: None,
                     pos: self.update_pos(),
                 }
             }
             '*' => {
                 self.bump();
+                let kind = if self.next() == &'=' {
+                    self.bump();
+                    TokenKind::StarEq
+                } else {
+                    TokenKind::Star
+                };
                 Token {
-                    kind: TokenKind::Star,
+                    kind,
                     value: None,
                     pos: self.update_pos(),
                 }
@@ -264,6 +350,13 @@ impl<'a> Cursor<'a> {
                         value: None,
                         pos: self.update_pos(),
                     }
+                } else if self.next() == &'=' {
+                    self.bump();
+                    Token {
+                        kind: TokenKind::SlashEq,
+                        value: None,
+                        pos: self.update_pos(),
+                    }
                 } else {
                     Token {
                         kind: TokenKind::Slash,
@@ -274,50 +367,92 @@ impl<'a> Cursor<'a> {
             }
             '%' => {
                 self.bump();
+                let kind = if self.next() == &'=' {
+                    self.bump();
+                    TokenKind::PercentEq
+                } else {
+                    TokenKind::Percent
+                };
                 Token {
-                    kind: TokenKind::Percent,
+                    kind,
                     value: None,
                     pos: self.update_pos(),
                 }
             }
             '&' => {
                 self.bump();
+                let kind = if self.next() == &'=' {
+                    self.bump();
+                    TokenKind::AndEq
+                } else {
+                    TokenKind::And
+                };
                 Token {
-                    kind: TokenKind::And,
+                    kind,
                     value: None,
                     pos: self.update_pos(),
                 }
             }
             '|' => {
                 self.bump();
+                let kind = if self.next() == &'=' {
+                    self.bump();
+                    TokenKind::OrEq
+                } else {
+                    TokenKind::Or
+                };
                 Token {
-                    kind: TokenKind::Or,
+                    kind,
                     value: None,
                     pos: self.update_pos(),
                 }
             }
             '^' => {
                 self.bump();
+                let kind = if self.next() == &'=' {
+                    self.bump();
+                    TokenKind::CaretEq
+                } else {
+                    TokenKind::Caret
+                };
                 Token {
-                    kind: TokenKind::Caret,
+                    kind,
                     value: None,
                     pos: self.update_pos(),
                 }
             }
             '<' => {
-                self.bump();
-                Token {
-                    kind: TokenKind::Lt,
-                    value: None,
-                    pos: self.update_pos(),
+                if self.chars.clone().take(3).eq("<<=".chars()) {
+                    self.consume("<<=");
+                    Token {
+                        kind: TokenKind::ShlEq,
+                        value: None,
+                        pos: self.update_pos(),
+                    }
+                } else {
+                    self.bump();
+                    Token {
+                        kind: TokenKind::Lt,
+                        value: None,
+                        pos: self.update_pos(),
+                    }
                 }
             }
             '>' => {
-                self.bump();
-                Token {
-                    kind: TokenKind::Gt,
-                    value: None,
-                    pos: self.update_pos(),
+                if self.chars.clone().take(3).eq(">>=".chars()) {
+                    self.consume(">>=");
+                    Token {
+                        kind: TokenKind::ShrEq,
+                        value: None,
+                        pos: self.update_pos(),
+                    }
+                } else {
+                    self.bump();
+                    Token {
+                        kind: TokenKind::Gt,
+                        value: None,
+                        pos: self.update_pos(),
+                    }
                 }
             }
             '(' => {
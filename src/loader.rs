@@ -0,0 +1,129 @@
+//! loads the files of a crate, `mod foo;` is replaced by `mod foo { .. }` holding the tokens of
+//! `foo.sofa`
+//!
+//! the files of the modules of `foo` are in the directory `foo/` next to it, like in rust.
+//! positions count on across files, so every token still points into exactly one of them.
+
+use std::path::{Path, PathBuf};
+
+use crate::lexer::{self, Token, TokenKind};
+
+/// a file of the crate, its tokens start at `base`
+#[derive(Debug, Clone)]
+pub struct Source {
+    pub file: String,
+    pub text: String,
+    pub base: usize,
+}
+
+/// file and `line:col` of `pos`
+pub fn locate(sources: &[Source], pos: usize) -> (&str, usize, usize) {
+    let source = sources
+        .iter()
+        .rev()
+        .find(|s| s.base <= pos)
+        .expect("position before the first source");
+    let (mut line, mut col) = (1, 1);
+    for c in source.text.chars().take(pos - source.base) {
+        if c == '\n' {
+            line += 1;
+            col = 1;
+        } else {
+            col += 1;
+        }
+    }
+    (&source.file, line, col)
+}
+
+/// tokens of the crate rooted at `file` with the text `source`, and the files they come from
+pub fn load(file: &str, source: &str) -> (Vec<Token>, Vec<Source>) {
+    let dir = Path::new(file)
+        .parent()
+        .map_or_else(PathBuf::new, Path::to_path_buf);
+    let mut sources = vec![];
+    let tokens = load_file(file, source, &dir, &mut sources);
+    (tokens, sources)
+}
+
+/// tokens of one file, whose modules are in `dir`
+fn load_file(file: &str, text: &str, dir: &Path, sources: &mut Vec<Source>) -> Vec<Token> {
+    let base = sources
+        .last()
+        .map_or(0, |s| s.base + s.text.chars().count());
+    sources.push(Source {
+        file: file.to_string(),
+        text: text.to_string(),
+        base,
+    });
+    let tokens: Vec<_> = lexer::tokenize(text)
+        .into_iter()
+        .map(|t| Token {
+            pos: (t.pos.0 + base, t.pos.1 + base),
+            ..t
+        })
+        .collect();
+
+    let mut res = vec![];
+    // inline modules being inside, with the depth of braces they opened at
+    let mut inline: Vec<(String, usize)> = vec![];
+    let mut depth = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = &tokens[i];
+        match token.kind {
+            TokenKind::Mod if kinds(&tokens[i..], &[TokenKind::Ident, TokenKind::LBrace]) => {
+                inline.push((tokens[i + 1].value.clone().unwrap(), depth));
+            }
+            TokenKind::Mod if kinds(&tokens[i..], &[TokenKind::Ident, TokenKind::Semi]) => {
+                let name = tokens[i + 1].value.clone().unwrap();
+                let dir = inline.iter().fold(dir.to_path_buf(), |d, (m, _)| d.join(m));
+                let path = dir.join(format!("{}.sofa", name));
+                let text = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+                    panic!(
+                        "file not found for module `{}`, expected {}",
+                        name,
+                        path.display()
+                    )
+                });
+                let inner = load_file(&path.to_string_lossy(), &text, &dir.join(&name), sources);
+
+                let semi = &tokens[i + 2];
+                res.push(token.clone());
+                res.push(tokens[i + 1].clone());
+                res.push(Token {
+                    kind: TokenKind::LBrace,
+                    value: None,
+                    pos: semi.pos,
+                });
+                res.extend(inner);
+                res.push(Token {
+                    kind: TokenKind::RBrace,
+                    value: None,
+                    pos: semi.pos,
+                });
+                i += 3;
+                continue;
+            }
+            TokenKind::LBrace => depth += 1,
+            TokenKind::RBrace => {
+                depth -= 1;
+                if inline.last().is_some_and(|(_, d)| *d == depth) {
+                    inline.pop();
+                }
+            }
+            _ => {}
+        }
+        res.push(token.clone());
+        i += 1;
+    }
+    res
+}
+
+/// whether the tokens after the first are of `kinds`
+fn kinds(tokens: &[Token], kinds: &[TokenKind]) -> bool {
+    tokens.len() > kinds.len()
+        && tokens[1..=kinds.len()]
+            .iter()
+            .zip(kinds)
+            .all(|(t, k)| t.kind == *k)
+}
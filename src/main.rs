@@ -1,14 +1,22 @@
 mod ast;
 mod cli;
+mod closure;
 mod codegen;
+mod frame;
+mod header;
 mod lexer;
+mod loader;
+mod mono;
+mod moves;
+mod mutability;
 mod parser;
 mod ty;
+mod usefulness;
 
 use clap::Parser;
 use std::{
     fs::File,
-    io::{stdout, Read},
+    io::{stdout, Read, Write},
 };
 
 fn main() {
@@ -16,51 +24,84 @@ fn main() {
     let args = cli::SofaC::parse();
 
     // read input source
+    let file = args.file.clone().unwrap_or_else(|| "<console>".to_string());
     let source = args
         .console
         .or_else(|| {
-            let mut f = File::open(args.file.unwrap()).unwrap();
+            let mut f = File::open(&file).unwrap();
             let mut buf = String::new();
             f.read_to_string(&mut buf).unwrap();
             Some(buf)
         })
         .unwrap();
 
-    // tokenize source into tokens
-    let tokens = lexer::tokenize(&source);
+    // tokenize source into tokens, with the files of its modules
+    let (tokens, sources) = loader::load(&file, &source);
 
     // parse tokens
     let parser = parser::SofaParser::new(&tokens);
     let ast = parser.parse();
 
-    // generate assembly
-    if args.stdout {
-        let mut generater = codegen::SofaGenerater::new(stdout());
-        generater.gen(&ast);
+    // check patterns of match expressions
+    usefulness::check(&ast);
+
+    // check writes against mutability
+    mutability::check(&ast);
+
+    // check uses against moves
+    moves::check(&ast);
+
+    // copy generic functions for each instantiation
+    let ast = mono::monomorphize(ast);
+
+    // lift closures into functions of their own
+    let ast = closure::convert(ast);
+
+    let out: Box<dyn Write> = if args.stdout {
+        Box::new(stdout())
     } else {
-        let out = args.out.unwrap_or_else(|| "tmp.s".to_string());
-        let mut generater = codegen::SofaGenerater::new(
+        let out = args.out.unwrap_or_else(|| match args.emit {
+            cli::Emit::Asm => "tmp.s".to_string(),
+            cli::Emit::CHeader => "tmp.h".to_string(),
+        });
+        Box::new(
             std::fs::File::options()
                 .write(true)
                 .truncate(true)
                 .create(true)
                 .open(out)
                 .unwrap(),
-        );
-        generater.gen(&ast);
+        )
+    };
+
+    match args.emit {
+        // generate assembly
+        cli::Emit::Asm => {
+            let mut generater =
+                codegen::SofaGenerater::new(out, &sources, !args.no_bounds_checks, !args.release);
+            generater.gen(&ast);
+        }
+        // declare exported functions for C
+        cli::Emit::CHeader => header::gen(&ast, out),
     }
 }
 
 #[test]
 fn test_example() {
     let s = include_str!("../example/test.sofa");
-    let tokens = lexer::tokenize(s);
+    let (tokens, sources) = loader::load("example/test.sofa", s);
     // dbg!(&tokens);
 
     let parser = parser::SofaParser::new(&tokens);
     let ast = parser.parse();
     dbg!(&ast);
 
-    let mut generater = codegen::SofaGenerater::new(std::io::stdout());
+    usefulness::check(&ast);
+    mutability::check(&ast);
+    moves::check(&ast);
+    let ast = mono::monomorphize(ast);
+    let ast = closure::convert(ast);
+
+    let mut generater = codegen::SofaGenerater::new(std::io::stdout(), &sources, true, true);
     generater.gen(&ast);
 }
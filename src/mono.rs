@@ -0,0 +1,311 @@
+//! monomorphization, stamps out a copy of each generic function per instantiation
+//!
+//! copies are named after their type and const arguments like `max.i64` or `sum.const3`,
+//! and calls to generic functions are redirected to them.
+//! items in modules are named by their path, `geometry$area` for `geometry::area`.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{
+        Arm, Assert, Assign, Ast, BinOp, Block, BoxNew, Call, Closure, CompoundAssign, ConstExpr,
+        Enclosed, Expr, FnCall, FnDef, IfElse, Index, Init, Loop, Match, Member, Number, Pattern,
+        Return, Slice, Stmt, Struct, ToDyn, Tuple, UnOp, Variant, VecConcat, VecNew, VecPop,
+        VecPush,
+    },
+    ty::Type,
+};
+
+/// replaces the generic functions by their instantiations
+pub fn monomorphize(mut ast: Ast) -> Ast {
+    // methods are plain functions from here on
+    let methods = ast.node.impls.drain(..).flat_map(|i| i.methods);
+    let (generic, definitions): (Vec<_>, Vec<_>) = ast
+        .node
+        .definitions
+        .into_iter()
+        .chain(methods)
+        .partition(|f| !f.generics.is_empty());
+    if let Some(f) = generic.iter().find(|f| f.name == "main") {
+        panic!(
+            "`{}` function is not allowed to have generic parameters",
+            f.name
+        )
+    }
+
+    let mut mono = Mono {
+        generic: generic.into_iter().map(|f| (f.name.clone(), f)).collect(),
+        instantiated: HashSet::new(),
+        definitions: vec![],
+    };
+    let map = HashMap::new();
+    for mut f in definitions {
+        mono.block(&mut f.body, &map);
+        mono.definitions.push(f);
+    }
+
+    ast.node.definitions = mono.definitions;
+    ast
+}
+
+struct Mono {
+    /// generic functions by name
+    generic: HashMap<String, FnDef>,
+    /// names of the copies made so far
+    instantiated: HashSet<String>,
+    /// functions ready for codegen
+    definitions: Vec<FnDef>,
+}
+
+impl Mono {
+    /// copy of the generic `name` for `type_args`, made once, returns its name
+    fn instantiate(&mut self, name: &str, type_args: &[Type]) -> String {
+        let mangled = mangle(name, type_args);
+        if !self.instantiated.insert(mangled.clone()) {
+            return mangled;
+        }
+
+        let mut f = self.generic[name].clone();
+        let map: HashMap<_, _> = f
+            .generics
+            .drain(..)
+            .zip(type_args.iter().cloned())
+            .collect();
+        f.name = mangled.clone();
+        f.fn_type = f.fn_type.subst(&map);
+        for arg in f.args.iter_mut() {
+            arg.ty = arg.ty.subst(&map);
+            pattern(&mut arg.pattern, &map);
+        }
+        self.block(&mut f.body, &map);
+        self.definitions.push(f);
+
+        mangled
+    }
+
+    fn block(&mut self, block: &mut Block, map: &HashMap<String, Type>) {
+        for e in block.exprs.iter_mut() {
+            self.expr(e, map);
+        }
+    }
+
+    /// substitutes `map` into the types in `e` and redirects its generic calls
+    fn expr(&mut self, e: &mut Expr, map: &HashMap<String, Type>) {
+        match e {
+            Expr::Stmt(Stmt { expr: e })
+            | Expr::Return(Return { expr: e })
+            | Expr::UnOp(UnOp { expr: e, .. })
+            | Expr::Enclosed(Enclosed { expr: e })
+            | Expr::Member(Member { expr: e, .. })
+            | Expr::BoxNew(BoxNew { value: e })
+            | Expr::ToDyn(ToDyn { value: e, .. })
+            | Expr::Assert(Assert { cond: e, .. })
+            | Expr::VecPop(VecPop { vec: e, .. }) => self.expr(e, map),
+            Expr::Slice(Slice { expr, lo, hi, .. }) => {
+                self.expr(expr, map);
+                for bound in lo.iter_mut().chain(hi.iter_mut()) {
+                    self.expr(bound, map);
+                }
+            }
+            Expr::Block(b) | Expr::Loop(Loop { body: b }) => self.block(b, map),
+            Expr::IfElse(IfElse {
+                cond,
+                if_body,
+                else_body,
+            }) => {
+                self.expr(cond, map);
+                self.block(if_body, map);
+                if let Some(else_body) = else_body {
+                    self.block(else_body, map);
+                }
+            }
+            Expr::Match(Match { scrutinee, arms }) => {
+                self.expr(scrutinee, map);
+                for Arm { pattern: p, body } in arms.iter_mut() {
+                    pattern(p, map);
+                    self.expr(body, map);
+                }
+            }
+            Expr::FnCall(FnCall {
+                name,
+                args,
+                fn_type,
+                type_args,
+                ..
+            }) => {
+                for arg in args.iter_mut() {
+                    self.expr(arg, map);
+                }
+                *fn_type = fn_type.subst(map);
+                if !type_args.is_empty() {
+                    let type_args: Vec<_> = type_args.drain(..).map(|ty| ty.subst(map)).collect();
+                    *name = if self.generic.contains_key(name) {
+                        self.instantiate(name, &type_args)
+                    } else {
+                        // a trait method on a type parameter, now known to be implemented
+                        mangle(name, &type_args)
+                    };
+                }
+            }
+            Expr::Call(Call { callee, args }) => {
+                self.expr(callee, map);
+                for arg in args.iter_mut() {
+                    self.expr(arg, map);
+                }
+            }
+            Expr::Closure(Closure {
+                params, body, ty, ..
+            }) => {
+                for param in params.iter_mut() {
+                    param.ty = param.ty.subst(map);
+                    pattern(&mut param.pattern, map);
+                }
+                self.expr(body, map);
+                *ty = ty.subst(map);
+            }
+            Expr::Init(Init { pattern: p, value }) => {
+                if let Some(value) = value {
+                    self.expr(value, map);
+                }
+                pattern(p, map);
+            }
+            Expr::Assign(Assign { lhs, rhs })
+            | Expr::CompoundAssign(CompoundAssign { lhs, rhs, .. })
+            | Expr::BinOp(BinOp { lhs, rhs, .. })
+            | Expr::Index(Index {
+                expr: lhs,
+                index: rhs,
+                ..
+            })
+            | Expr::VecPush(VecPush {
+                vec: lhs,
+                value: rhs,
+            }) => {
+                self.expr(lhs, map);
+                self.expr(rhs, map);
+            }
+            Expr::VecConcat(VecConcat { lhs, rhs, ty }) => {
+                self.expr(lhs, map);
+                self.expr(rhs, map);
+                *ty = ty.subst(map);
+            }
+            Expr::Tuple(Tuple { elements: members })
+            | Expr::Struct(Struct {
+                fields: members, ..
+            })
+            | Expr::Variant(Variant {
+                fields: members, ..
+            }) => {
+                for member in members.iter_mut() {
+                    self.expr(member, map);
+                }
+            }
+            Expr::VecNew(VecNew { ty }) => *ty = ty.subst(map),
+            Expr::Local(local) => local.ty = local.ty.subst(map),
+            Expr::ConstExpr(ConstExpr { value }) => {
+                *value = value.subst(map);
+                if let Some(value) = value.eval() {
+                    *e = Expr::Number(Number {
+                        value: value.to_string(),
+                    });
+                }
+            }
+            Expr::Panic(_) | Expr::Bool(_) | Expr::Static(_) | Expr::FnRef(_) | Expr::Number(_) => {
+            }
+        }
+    }
+}
+
+fn pattern(p: &mut Pattern, map: &HashMap<String, Type>) {
+    match p {
+        Pattern::Binding { local, .. } => local.ty = local.ty.subst(map),
+        Pattern::Tuple(fields) | Pattern::Struct(fields) | Pattern::Variant { fields, .. } => {
+            for field in fields.iter_mut() {
+                pattern(field, map);
+            }
+        }
+        Pattern::Ref(inner) => pattern(inner, map),
+        Pattern::Wildcard | Pattern::Int(_) | Pattern::Range { .. } | Pattern::Bool(_) => {}
+    }
+}
+
+/// symbol of `name` instantiated with `type_args`, like `max.i64` or `swap.tuple2.i64.bool`
+pub fn mangle(name: &str, type_args: &[Type]) -> String {
+    let mut res = name.to_string();
+    for ty in type_args.iter() {
+        res.push('.');
+        mangle_ty(ty, &mut res);
+    }
+    res
+}
+
+/// symbol of the item at `path`, `geometry::area` is `geometry$area`
+pub fn symbol(path: &str) -> String {
+    path.replace("::", "$")
+}
+
+/// appends a name for `ty`, each part leads with what follows, so no two types share one
+fn mangle_ty(ty: &Type, res: &mut String) {
+    let (head, parts): (String, Vec<&Type>) = match ty {
+        Type::I64 => ("i64".to_string(), vec![]),
+        Type::Bool => ("bool".to_string(), vec![]),
+        Type::U8 => ("u8".to_string(), vec![]),
+        Type::Unit => ("unit".to_string(), vec![]),
+        Type::Never => ("never".to_string(), vec![]),
+        Type::Ptr { to, mutable } => (ref_head("ref", *mutable), vec![to]),
+        Type::Box { to } => ("box".to_string(), vec![to]),
+        Type::Array { element, len } => (format!("array{}", len.to_usize()), vec![element]),
+        Type::Slice { element, mutable } => (ref_head("slice", *mutable), vec![element]),
+        Type::Vec { element, len: None } => ("vec".to_string(), vec![element]),
+        Type::Vec {
+            element,
+            len: Some(len),
+        } => (format!("vec{}", len.to_usize()), vec![element]),
+        Type::Dyn {
+            name,
+            mutable,
+            boxed,
+        } => {
+            let head = if *boxed {
+                "boxdyn".to_string()
+            } else {
+                ref_head("dyn", *mutable)
+            };
+            (format!("{}.{}", head, symbol(name)), vec![])
+        }
+        Type::Tuple { elements } => (
+            format!("tuple{}", elements.len()),
+            elements.iter().collect(),
+        ),
+        Type::Struct { name, .. } | Type::Enum { name, .. } => (symbol(name), vec![]),
+        Type::Fn { args, ret } => (
+            format!("fn{}", args.len()),
+            args.iter().chain([&**ret]).collect(),
+        ),
+        Type::Closure { args, ret } => (
+            format!("closure{}", args.len()),
+            args.iter().chain([&**ret]).collect(),
+        ),
+        // `-` is no symbol character
+        Type::Const { value } => match value.eval() {
+            Some(v) if v < 0 => (format!("constneg{}", v.unsigned_abs()), vec![]),
+            Some(v) => (format!("const{}", v), vec![]),
+            None => panic!("{:?} is not a concrete value", value),
+        },
+        Type::Param { .. } | Type::Unknown => panic!("{:?} is not a concrete type", ty),
+    };
+
+    res.push_str(&head);
+    for part in parts {
+        res.push('.');
+        mangle_ty(part, res);
+    }
+}
+
+fn ref_head(head: &str, mutable: bool) -> String {
+    if mutable {
+        format!("{}mut", head)
+    } else {
+        head.to_string()
+    }
+}
@@ -0,0 +1,293 @@
+//! uses of locals after their value has been moved out
+//!
+//! a local whose type needs drop moves when it is used as a value, as codegen clears its drop
+//! flag there. reading it afterwards would read memory that was freed by its new owner.
+
+use crate::ast::{
+    Assert, Assign, Ast, BinOp, Block, BoxNew, Call, Closure, CompoundAssign, Enclosed, Expr,
+    FnCall, IfElse, Index, Init, Local, Loop, Match, Member, Pattern, Return, Slice, Stmt, Struct,
+    ToDyn, Tuple, UnOp, UnOpKind, Variant, VecConcat, VecPop, VecPush,
+};
+
+/// reports uses of moved locals as errors
+pub fn check(ast: &Ast) {
+    for f in ast.node.functions() {
+        let mut checker = Checker { bindings: vec![] };
+        for arg in f.args.iter() {
+            checker.declare(&arg.pattern);
+        }
+        checker.check_block(&f.body);
+    }
+}
+
+struct Binding {
+    name: String,
+    moved: bool,
+}
+
+struct Checker {
+    /// bindings in scope, innermost last
+    bindings: Vec<Binding>,
+}
+
+impl Checker {
+    fn declare(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Binding {
+                local: Local { name, .. },
+                ..
+            } => self.bindings.push(Binding {
+                name: name.clone(),
+                moved: false,
+            }),
+            Pattern::Tuple(patterns)
+            | Pattern::Struct(patterns)
+            | Pattern::Variant {
+                fields: patterns, ..
+            } => {
+                for pattern in patterns.iter() {
+                    self.declare(pattern);
+                }
+            }
+            Pattern::Ref(inner) => self.declare(inner),
+            Pattern::Wildcard | Pattern::Int(_) | Pattern::Range { .. } | Pattern::Bool(_) => {}
+        }
+    }
+
+    fn binding(&mut self, name: &str) -> &mut Binding {
+        self.bindings
+            .iter_mut()
+            .rev()
+            .find(|b| b.name == name)
+            .unwrap_or_else(|| panic!("found undefined local {}", name))
+    }
+
+    /// which bindings are moved, to go down one branch and come back
+    fn state(&self) -> Vec<bool> {
+        self.bindings.iter().map(|b| b.moved).collect()
+    }
+
+    fn restore(&mut self, state: &[bool]) {
+        for (binding, moved) in self.bindings.iter_mut().zip(state) {
+            binding.moved = *moved;
+        }
+    }
+
+    /// bindings declared in `block` end with it
+    fn check_block(&mut self, block: &Block) {
+        let outer = self.bindings.len();
+        for expr in block.exprs.iter() {
+            self.check_expr(expr);
+        }
+        self.bindings.truncate(outer);
+    }
+
+    /// a binding is moved after branches that started from `before` if any of them moved it,
+    /// a branch that never comes back is left out
+    fn join(&mut self, before: &[bool], branches: Vec<Option<Vec<bool>>>) {
+        let after = branches
+            .into_iter()
+            .flatten()
+            .reduce(|a, b| a.iter().zip(b).map(|(a, b)| *a || b).collect());
+        self.restore(after.as_deref().unwrap_or(before));
+    }
+
+    /// `expr` is evaluated as a value, a local that needs drop moves
+    fn check_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Local(Local { name, ty }) => {
+                let binding = self.binding(name);
+                if binding.moved {
+                    panic!("use of moved value `{}`", name)
+                }
+                if ty.needs_drop() {
+                    binding.moved = true;
+                }
+            }
+            Expr::Stmt(Stmt { expr })
+            | Expr::Return(Return { expr })
+            | Expr::Enclosed(Enclosed { expr })
+            | Expr::BoxNew(BoxNew { value: expr })
+            | Expr::ToDyn(ToDyn { value: expr, .. })
+            | Expr::Assert(Assert { cond: expr, .. })
+            | Expr::UnOp(UnOp {
+                kind: UnOpKind::Neg { .. } | UnOpKind::Not,
+                expr,
+            }) => self.check_expr(expr),
+            // fields, elements and pointees are read where they are
+            Expr::Member(_)
+            | Expr::Index(_)
+            | Expr::UnOp(UnOp {
+                kind: UnOpKind::Deref | UnOpKind::Ref | UnOpKind::RefMut,
+                ..
+            }) => self.check_place(expr),
+            Expr::Block(block) => self.check_block(block),
+            // the body runs again after moving
+            Expr::Loop(Loop { body }) => {
+                self.check_block(body);
+                self.check_block(body);
+            }
+            Expr::IfElse(IfElse {
+                cond,
+                if_body,
+                else_body,
+            }) => {
+                self.check_expr(cond);
+                let before = self.state();
+                self.check_block(if_body);
+                let taken = (!if_body.exprs.iter().any(diverges)).then(|| self.state());
+                self.restore(&before);
+                let mut not_taken = Some(before.clone());
+                if let Some(else_body) = else_body {
+                    self.check_block(else_body);
+                    not_taken = (!else_body.exprs.iter().any(diverges)).then(|| self.state());
+                }
+                self.join(&before, vec![taken, not_taken]);
+            }
+            Expr::Match(Match { scrutinee, arms }) => {
+                if arms.iter().any(|arm| arm.pattern.owns()) {
+                    self.check_expr(scrutinee);
+                } else {
+                    // nothing is moved out, the scrutinee keeps its value
+                    self.check_place(scrutinee);
+                }
+                let before = self.state();
+                let mut branches = vec![];
+                for arm in arms.iter() {
+                    self.restore(&before);
+                    let outer = self.bindings.len();
+                    self.declare(&arm.pattern);
+                    self.check_expr(&arm.body);
+                    self.bindings.truncate(outer);
+                    branches.push((!diverges(&arm.body)).then(|| self.state()));
+                }
+                self.join(&before, branches);
+            }
+            // the receiver of a `dyn Trait` method stays where it is
+            Expr::FnCall(FnCall {
+                args,
+                dispatch: Some(_),
+                ..
+            }) => {
+                self.check_place(&args[0]);
+                for expr in args[1..].iter() {
+                    self.check_expr(expr);
+                }
+            }
+            Expr::FnCall(FnCall { args: exprs, .. })
+            | Expr::Tuple(Tuple { elements: exprs })
+            | Expr::Struct(Struct { fields: exprs, .. })
+            | Expr::Variant(Variant { fields: exprs, .. }) => {
+                for expr in exprs.iter() {
+                    self.check_expr(expr);
+                }
+            }
+            Expr::Call(Call { callee, args }) => {
+                self.check_expr(callee);
+                for expr in args.iter() {
+                    self.check_expr(expr);
+                }
+            }
+            // the body runs later, with the captures borrowed
+            Expr::Closure(Closure { params, body, .. }) => {
+                let before = self.state();
+                let outer = self.bindings.len();
+                for param in params.iter() {
+                    self.declare(&param.pattern);
+                }
+                self.check_expr(body);
+                self.bindings.truncate(outer);
+                self.restore(&before);
+            }
+            Expr::Init(Init { pattern, value }) => {
+                if let Some(value) = value {
+                    self.check_expr(value);
+                }
+                self.declare(pattern);
+            }
+            Expr::Assign(Assign { lhs, rhs }) => {
+                self.check_expr(rhs);
+                match &**lhs {
+                    // a new value for the local
+                    Expr::Local(Local { name, .. }) => self.binding(name).moved = false,
+                    lhs => self.check_place(lhs),
+                }
+            }
+            Expr::CompoundAssign(CompoundAssign { lhs, rhs, .. }) => {
+                self.check_place(lhs);
+                self.check_expr(rhs);
+            }
+            // operators compare and compute on their operands where they are
+            Expr::BinOp(BinOp { lhs, rhs, .. }) => {
+                self.check_place(lhs);
+                self.check_place(rhs);
+            }
+            Expr::VecConcat(VecConcat { lhs, rhs, .. }) => {
+                self.check_expr(lhs);
+                self.check_expr(rhs);
+            }
+            Expr::Slice(Slice { expr, lo, hi, .. }) => {
+                self.check_place(expr);
+                for bound in lo.iter().chain(hi.iter()) {
+                    self.check_expr(bound);
+                }
+            }
+            // methods taking `&mut self`
+            Expr::VecPush(VecPush { vec, value }) => {
+                self.check_place(vec);
+                self.check_expr(value);
+            }
+            Expr::VecPop(VecPop { vec, .. }) => self.check_place(vec),
+            Expr::VecNew(_)
+            | Expr::Panic(_)
+            | Expr::Bool(_)
+            | Expr::Static(_)
+            | Expr::FnRef(_)
+            | Expr::ConstExpr(_)
+            | Expr::Number(_) => {}
+        }
+    }
+
+    /// `place` is read, written or borrowed where it is, without moving out of it
+    fn check_place(&mut self, place: &Expr) {
+        match place {
+            Expr::Local(Local { name, .. }) => {
+                if self.binding(name).moved {
+                    panic!("use of moved value `{}`", name)
+                }
+            }
+            Expr::Member(Member { expr, .. })
+            | Expr::Enclosed(Enclosed { expr })
+            | Expr::UnOp(UnOp {
+                kind: UnOpKind::Deref | UnOpKind::Ref | UnOpKind::RefMut,
+                expr,
+            }) => self.check_place(expr),
+            Expr::Index(Index { expr, index, .. }) => {
+                self.check_place(expr);
+                self.check_expr(index);
+            }
+            _ => self.check_expr(place),
+        }
+    }
+}
+
+/// whether control never comes back from `e`
+fn diverges(e: &Expr) -> bool {
+    match e {
+        Expr::Return(_) | Expr::Panic(_) | Expr::Loop(_) => true,
+        Expr::Stmt(Stmt { expr }) | Expr::Enclosed(Enclosed { expr }) => diverges(expr),
+        Expr::Block(Block { exprs }) => exprs.iter().any(diverges),
+        Expr::IfElse(IfElse {
+            cond,
+            if_body,
+            else_body: Some(else_body),
+        }) => {
+            diverges(cond)
+                || (if_body.exprs.iter().any(diverges) && else_body.exprs.iter().any(diverges))
+        }
+        Expr::Match(Match { scrutinee, arms }) => {
+            diverges(scrutinee) || (!arms.is_empty() && arms.iter().all(|arm| diverges(&arm.body)))
+        }
+        _ => false,
+    }
+}
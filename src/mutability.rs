@@ -0,0 +1,314 @@
+//! assignments and `&mut` borrows against the mutability of their place
+
+use std::collections::HashMap;
+
+use crate::{
+    ast::{
+        Assert, Assign, Ast, BinOp, BinOpKind, Block, BoxNew, Call, Closure, CompoundAssign,
+        Enclosed, Expr, FnCall, IfElse, Index, Init, Local, Loop, Match, Member, Pattern, Return,
+        Slice, Static, Stmt, Struct, ToDyn, Tuple, UnOp, UnOpKind, Variant, VecConcat, VecPop,
+        VecPush,
+    },
+    ty::Type,
+};
+
+/// reports writes to immutable places as errors,
+/// and `mut` bindings that are never written as warnings
+pub fn check(ast: &Ast) {
+    let statics: HashMap<_, _> = ast
+        .node
+        .statics
+        .iter()
+        .map(|s| (s.name.clone(), s.mutable))
+        .collect();
+
+    for f in ast.node.functions() {
+        let mut checker = Checker {
+            bindings: vec![],
+            statics: &statics,
+        };
+        for arg in f.args.iter() {
+            checker.declare(&arg.pattern);
+        }
+        checker.check_block(&f.body);
+        checker.leave(0);
+    }
+}
+
+struct Binding {
+    name: String,
+    mutable: bool,
+    mutated: bool,
+}
+
+struct Checker<'a> {
+    /// bindings in scope, innermost last
+    bindings: Vec<Binding>,
+    /// mutability of `static` items
+    statics: &'a HashMap<String, bool>,
+}
+
+#[derive(Clone, Copy)]
+enum Access {
+    Assign,
+    Borrow,
+}
+
+impl Checker<'_> {
+    fn declare(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Binding {
+                local: Local { name, .. },
+                mutable,
+            } => self.bindings.push(Binding {
+                name: name.clone(),
+                mutable: *mutable,
+                mutated: false,
+            }),
+            Pattern::Tuple(patterns)
+            | Pattern::Struct(patterns)
+            | Pattern::Variant {
+                fields: patterns, ..
+            } => {
+                for pattern in patterns.iter() {
+                    self.declare(pattern);
+                }
+            }
+            Pattern::Ref(inner) => self.declare(inner),
+            Pattern::Wildcard | Pattern::Int(_) | Pattern::Range { .. } | Pattern::Bool(_) => {}
+        }
+    }
+
+    /// drops the bindings declared after the first `len`
+    fn leave(&mut self, len: usize) {
+        for binding in self.bindings.drain(len..) {
+            if binding.mutable && !binding.mutated {
+                eprintln!(
+                    "warning: variable `{}` does not need to be mutable",
+                    binding.name
+                );
+            }
+        }
+    }
+
+    /// bindings declared in `block` end with it
+    fn check_block(&mut self, block: &Block) {
+        let outer = self.bindings.len();
+        for expr in block.exprs.iter() {
+            self.check_expr(expr);
+        }
+        self.leave(outer);
+    }
+
+    fn check_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Stmt(Stmt { expr })
+            | Expr::Return(Return { expr })
+            | Expr::Enclosed(Enclosed { expr })
+            | Expr::Member(Member { expr, .. })
+            | Expr::BoxNew(BoxNew { value: expr })
+            | Expr::ToDyn(ToDyn { value: expr, .. })
+            | Expr::Assert(Assert { cond: expr, .. }) => self.check_expr(expr),
+            Expr::UnOp(UnOp { kind, expr }) => {
+                if let UnOpKind::RefMut = kind {
+                    self.check_place(expr, Access::Borrow);
+                }
+                self.check_expr(expr);
+            }
+            Expr::Block(block) | Expr::Loop(Loop { body: block }) => self.check_block(block),
+            Expr::IfElse(IfElse {
+                cond,
+                if_body,
+                else_body,
+            }) => {
+                self.check_expr(cond);
+                self.check_block(if_body);
+                if let Some(else_body) = else_body {
+                    self.check_block(else_body);
+                }
+            }
+            Expr::Match(Match { scrutinee, arms }) => {
+                self.check_expr(scrutinee);
+                for arm in arms.iter() {
+                    let outer = self.bindings.len();
+                    self.declare(&arm.pattern);
+                    self.check_expr(&arm.body);
+                    self.leave(outer);
+                }
+            }
+            Expr::FnCall(FnCall {
+                args,
+                fn_type: Type::Fn { args: params, .. },
+                dispatch: Some(_),
+                ..
+            }) => {
+                // `&mut self` of a boxed `dyn Trait` borrows the box
+                if let (Type::Dyn { boxed: true, .. }, Type::Ptr { mutable: true, .. }) =
+                    (args[0].ty(), &params[0])
+                {
+                    self.check_place(&args[0], Access::Borrow);
+                }
+                for expr in args.iter() {
+                    self.check_expr(expr);
+                }
+            }
+            Expr::FnCall(FnCall { args: exprs, .. })
+            | Expr::Tuple(Tuple { elements: exprs })
+            | Expr::Struct(Struct { fields: exprs, .. })
+            | Expr::Variant(Variant { fields: exprs, .. }) => {
+                for expr in exprs.iter() {
+                    self.check_expr(expr);
+                }
+            }
+            Expr::Call(Call { callee, args }) => {
+                self.check_expr(callee);
+                for expr in args.iter() {
+                    self.check_expr(expr);
+                }
+            }
+            // captured locals are checked where the closure is written
+            Expr::Closure(Closure { params, body, .. }) => {
+                let outer = self.bindings.len();
+                for param in params.iter() {
+                    self.declare(&param.pattern);
+                }
+                self.check_expr(body);
+                self.leave(outer);
+            }
+            Expr::Init(Init { pattern, value }) => {
+                if let Some(value) = value {
+                    self.check_expr(value);
+                }
+                self.declare(pattern);
+            }
+            Expr::Assign(Assign { lhs, rhs })
+            | Expr::CompoundAssign(CompoundAssign { lhs, rhs, .. }) => {
+                self.check_expr(rhs);
+                self.check_place(lhs, Access::Assign);
+                self.check_expr(lhs);
+            }
+            Expr::BinOp(BinOp { lhs, rhs, .. })
+            | Expr::Index(Index {
+                expr: lhs,
+                index: rhs,
+                ..
+            })
+            | Expr::VecConcat(VecConcat { lhs, rhs, .. }) => {
+                self.check_expr(lhs);
+                self.check_expr(rhs);
+            }
+            Expr::Slice(Slice {
+                expr,
+                lo,
+                hi,
+                mutable,
+                ..
+            }) => {
+                if *mutable {
+                    match expr.ty() {
+                        Type::Slice { mutable: true, .. } => {}
+                        Type::Slice { mutable: false, .. } => {
+                            panic!("cannot borrow data behind a `&` reference as mutable")
+                        }
+                        _ => self.check_place(expr, Access::Borrow),
+                    }
+                }
+                self.check_expr(expr);
+                for bound in lo.iter().chain(hi.iter()) {
+                    self.check_expr(bound);
+                }
+            }
+            // methods taking `&mut self`
+            Expr::VecPush(VecPush { vec, value }) => {
+                self.check_expr(value);
+                self.check_place(vec, Access::Borrow);
+                self.check_expr(vec);
+            }
+            Expr::VecPop(VecPop { vec, .. }) => {
+                self.check_place(vec, Access::Borrow);
+                self.check_expr(vec);
+            }
+            Expr::VecNew(_)
+            | Expr::Panic(_)
+            | Expr::Bool(_)
+            | Expr::Local(_)
+            | Expr::Static(_)
+            | Expr::FnRef(_)
+            | Expr::ConstExpr(_)
+            | Expr::Number(_) => {}
+        }
+    }
+
+    /// `place` is about to be written or borrowed mutably
+    fn check_place(&mut self, place: &Expr, access: Access) {
+        match place {
+            Expr::Local(Local { name, .. }) => {
+                let binding = self
+                    .bindings
+                    .iter_mut()
+                    .rev()
+                    .find(|b| b.name == *name)
+                    .unwrap_or_else(|| panic!("found undefined local {}", name));
+                if !binding.mutable {
+                    match access {
+                        Access::Assign => panic!(
+                            "cannot assign to `{}`, as it is not declared as mutable",
+                            name
+                        ),
+                        Access::Borrow => panic!(
+                            "cannot borrow `{}` as mutable, as it is not declared as mutable",
+                            name
+                        ),
+                    }
+                }
+                binding.mutated = true;
+            }
+            Expr::Static(Static { name, .. }) if !self.statics[name] => match access {
+                Access::Assign => panic!("cannot assign to immutable static item `{}`", name),
+                Access::Borrow => {
+                    panic!("cannot borrow immutable static item `{}` as mutable", name)
+                }
+            },
+            Expr::Member(Member { expr, .. }) | Expr::Enclosed(Enclosed { expr }) => {
+                self.check_place(expr, access)
+            }
+            Expr::Index(Index { expr, .. }) => match expr.ty() {
+                // the elements belong to the array or vec
+                Type::Array { .. } | Type::Vec { .. } => self.check_place(expr, access),
+                Type::Slice { mutable: false, .. } | Type::Ptr { mutable: false, .. } => {
+                    match access {
+                        Access::Assign => panic!("cannot assign to data behind a `&` reference"),
+                        Access::Borrow => {
+                            panic!("cannot borrow data behind a `&` reference as mutable")
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Expr::UnOp(UnOp {
+                kind: UnOpKind::Deref,
+                expr,
+            }) => match &**expr {
+                // `*(a + i)` writes into the array itself
+                Expr::BinOp(BinOp {
+                    op: BinOpKind::Add,
+                    lhs,
+                    ..
+                }) if matches!(lhs.ty(), Type::Array { .. }) => self.check_place(lhs, access),
+                _ => match expr.ty() {
+                    // a box owns its contents
+                    Type::Array { .. } | Type::Box { .. } => self.check_place(expr, access),
+                    Type::Ptr { mutable: false, .. } => match access {
+                        Access::Assign => panic!("cannot assign to data behind a `&` reference"),
+                        Access::Borrow => {
+                            panic!("cannot borrow data behind a `&` reference as mutable")
+                        }
+                    },
+                    _ => {}
+                },
+            },
+            // temporaries can be freely written
+            _ => {}
+        }
+    }
+}
@@ -1,14 +1,46 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::{
     ast::{
-        Assign, Ast, BinOp, BinOpKind, Block, Bool, Enclosed, Expr, FnCall, FnDef, Global, IfElse,
-        Init, Local, Loop, Number, Return, Stmt, UnOp, UnOpKind,
+        Arm, Assert, Assign, Ast, BinOp, BinOpKind, Block, Bool, BoxNew, Call, Closure,
+        CompoundAssign, ConstExpr, Enclosed, Expr, FnCall, FnDef, FnRef, Global, IfElse, Impl,
+        Index, Init, Local, Loop, Match, Member, Number, Panic, Param, Pattern, Return, Slice,
+        Static, StaticDef, Stmt, Struct, ToDyn, Tuple, UnOp, UnOpKind, Variant, VecConcat, VecNew,
+        VecPop, VecPush,
     },
     lexer::{Token, TokenKind},
-    ty::Type,
+    mono::{mangle, symbol},
+    ty::{Const, Field, FieldsKind, Type, VariantDef},
 };
 
+/// a function in the namespace of a type, called as `x.name()` or `T::name()`
+#[derive(Clone)]
+struct Method {
+    name: String,
+    /// symbol of the function, or of the trait method when declared by a trait
+    symbol: String,
+    fn_type: Type,
+    /// takes `self` first, so it can be called with a receiver
+    receiver: bool,
+    /// declared `pub`, or by a trait
+    public: bool,
+    /// module of the `impl` block
+    module: String,
+}
+
+/// a parameter of a generic function, `T: Shape + Eq` or `const N: i64`
+#[derive(Clone)]
+struct Generic {
+    name: String,
+    /// traits bounding a type parameter
+    bounds: Vec<String>,
+    /// instantiated by a value rather than a type
+    is_const: bool,
+}
+
+/// parameters of a generic function, `<T: Shape, U, const N: i64>`
+type Generics = Vec<Generic>;
+
 pub struct SofaParser<'ctx> {
     head: usize,
     tokens: &'ctx [Token],
@@ -16,14 +48,102 @@ pub struct SofaParser<'ctx> {
     // TODO:
     // id -> (name?, type, scope)
     signatures: HashMap<String, Type>,
+    /// functions by name, locals shadow them
+    functions: HashMap<String, Type>,
+    /// user defined types by name
+    types: HashMap<String, Type>,
+    /// `const` items, replaced by their value where used
+    consts: HashMap<String, (Type, i64)>,
+    /// `static` items by name
+    statics: HashMap<String, Type>,
+    /// type parameters of generic functions by symbol
+    generics: HashMap<String, Generics>,
+    /// traits bounding the type parameters in scope
+    bounds: HashMap<String, Vec<String>>,
+    /// methods declared by traits, `Self` is a type parameter in their types
+    traits: HashMap<String, Vec<Method>>,
+    /// methods and associated functions of types, from `impl` blocks
+    methods: Vec<(Type, Method)>,
+    /// traits implemented by types
+    impls: Vec<(String, Type)>,
+    /// the self type and trait of the `impl` block being parsed
+    in_impl: Option<(Type, Option<String>)>,
+    /// path of the module being parsed, empty for the crate root
+    module: String,
+    /// module the path before the current ident names, `geometry` in `geometry::area`
+    qualifier: Option<String>,
+    /// paths of the modules
+    modules: HashSet<String>,
+    /// paths of the items declared `pub`
+    public: HashSet<String>,
+    /// `use` declarations, the path they declare to the path of the item
+    uses: HashMap<String, String>,
+    /// whether each field of a struct is `pub`, by the path of the struct
+    pub_fields: HashMap<String, Vec<bool>>,
+    /// functions known to C by their name rather than their path
+    c_names: HashSet<String>,
+    /// bits and signedness of the C integer returned by a foreign function, when narrower than
+    /// i64, by its symbol
+    c_returns: HashMap<String, (u32, bool)>,
+    /// return type of the function or closure being parsed, what `return` gives a value of
+    ret: Type,
+    /// `type` aliases by path, with the position of their target and the module declaring them
+    aliases: HashMap<String, (usize, String)>,
+    /// aliases whose target is being parsed, innermost last
+    expanding: Vec<String>,
 }
 
 impl<'ctx> SofaParser<'ctx> {
     pub fn new(tokens: &'ctx [Token]) -> Self {
+        // the heap runtime, for raw memory
+        let functions = HashMap::from([
+            (
+                "sofa_alloc".to_string(),
+                Type::Fn {
+                    args: vec![Type::I64],
+                    ret: Box::new(Type::Ptr {
+                        to: Box::new(Type::I64),
+                        mutable: true,
+                    }),
+                },
+            ),
+            (
+                "sofa_free".to_string(),
+                Type::Fn {
+                    args: vec![Type::Ptr {
+                        to: Box::new(Type::I64),
+                        mutable: true,
+                    }],
+                    ret: Box::new(Type::Unit),
+                },
+            ),
+        ]);
+
         Self {
             head: 0,
             tokens,
             signatures: HashMap::new(),
+            functions,
+            types: HashMap::new(),
+            consts: HashMap::new(),
+            statics: HashMap::new(),
+            generics: HashMap::new(),
+            bounds: HashMap::new(),
+            traits: HashMap::new(),
+            methods: vec![],
+            impls: vec![],
+            in_impl: None,
+            module: String::new(),
+            qualifier: None,
+            modules: HashSet::new(),
+            public: HashSet::new(),
+            uses: HashMap::new(),
+            pub_fields: HashMap::new(),
+            c_names: HashSet::new(),
+            c_returns: HashMap::new(),
+            ret: Type::Unknown,
+            aliases: HashMap::new(),
+            expanding: vec![],
         }
     }
 
@@ -50,7 +170,11 @@ impl<'ctx> SofaParser<'ctx> {
 
     fn expect(&mut self, target: &[TokenKind]) {
         if !(self.consume(target)) {
-            panic!("found {:?}, not {:?}", self.tokens[self.head], target)
+            let found = &self.tokens[self.head];
+            panic!(
+                "found {:?} at {}, not {:?}",
+                found.kind, found.pos.0, target
+            )
         }
     }
 
@@ -59,6 +183,10 @@ impl<'ctx> SofaParser<'ctx> {
             Some(BinOpKind::Eq)
         } else if self.consume(&[TokenKind::Bang, TokenKind::Eq]) {
             Some(BinOpKind::Neq)
+        } else if self.consume(&[TokenKind::Lt, TokenKind::Lt]) {
+            Some(BinOpKind::Shl)
+        } else if self.consume(&[TokenKind::Gt, TokenKind::Gt]) {
+            Some(BinOpKind::Shr)
         } else if self.consume(&[TokenKind::Lt, TokenKind::Eq]) {
             Some(BinOpKind::LeEq)
         } else if self.consume(&[TokenKind::Lt]) {
@@ -92,12 +220,36 @@ impl<'ctx> SofaParser<'ctx> {
         }
     }
 
+    fn consume_compound_op(&mut self) -> Option<BinOpKind> {
+        let op = match self.get().kind {
+            TokenKind::PlusEq => BinOpKind::Add,
+            TokenKind::MinusEq => BinOpKind::Sub,
+            TokenKind::StarEq => BinOpKind::Mul,
+            TokenKind::SlashEq => BinOpKind::Div,
+            TokenKind::PercentEq => BinOpKind::Rem,
+            TokenKind::AndEq => BinOpKind::BitAnd,
+            TokenKind::OrEq => BinOpKind::BitOr,
+            TokenKind::CaretEq => BinOpKind::BitXor,
+            TokenKind::ShlEq => BinOpKind::Shl,
+            TokenKind::ShrEq => BinOpKind::Shr,
+            _ => return None,
+        };
+        self.head += 1;
+        Some(op)
+    }
+
     fn expect_ident(&mut self) -> String {
         let id = self.tokens[self.head].value.clone();
         self.expect(&[TokenKind::Ident]);
         id.unwrap()
     }
 
+    fn expect_str(&mut self) -> String {
+        let s = self.tokens[self.head].value.clone();
+        self.expect(&[TokenKind::Str]);
+        s.unwrap()
+    }
+
     fn expect_number(&mut self) -> String {
         let id = self.tokens[self.head].value.clone();
         self.expect(&[TokenKind::Number]);
@@ -115,52 +267,996 @@ impl<'ctx> SofaParser<'ctx> {
     fn global(&mut self) -> Global {
         let mut res = Global {
             definitions: vec![],
+            statics: vec![],
+            impls: vec![],
         };
+        self.collect_aliases();
+        self.items(&mut res);
+        res
+    }
+
+    /// finds the `type` items of every module, an alias can be named before it is declared
+    fn collect_aliases(&mut self) {
+        // paths of the modules around the current token, with the depth of their items
+        let mut modules = vec![(String::new(), 0)];
+        let mut depth = 0;
+        for (i, token) in self.tokens.iter().enumerate() {
+            let (module, items) = modules.last().unwrap();
+            match token.kind {
+                TokenKind::LBrace => depth += 1,
+                TokenKind::RBrace => {
+                    depth -= 1;
+                    if depth < *items {
+                        modules.pop();
+                    }
+                }
+                TokenKind::Mod if depth == *items => {
+                    let name = self.tokens[i + 1].value.as_deref().unwrap();
+                    modules.push((qualify(module, name), depth + 1));
+                }
+                TokenKind::Type if depth == *items => {
+                    let name = self.tokens[i + 1].value.as_deref().unwrap();
+                    let path = qualify(module, name);
+                    if i > 0 && self.tokens[i - 1].kind == TokenKind::Pub {
+                        self.public.insert(path.clone());
+                    }
+                    self.aliases.insert(path, (i + 3, module.clone()));
+                }
+                _ => {}
+            }
+        }
+    }
 
+    /// items up to the end of the crate root, or of the module being parsed
+    fn items(&mut self, res: &mut Global) {
         loop {
             if self.is_eof() {
-                break res;
+                if !self.module.is_empty() {
+                    panic!("this file contains an unclosed delimiter")
+                }
+                break;
+            } else if !self.module.is_empty() && self.consume(&[TokenKind::RBrace]) {
+                break;
+            }
+
+            let public = self.consume(&[TokenKind::Pub]);
+            if public
+                && [
+                    TokenKind::Fn,
+                    TokenKind::Struct,
+                    TokenKind::Enum,
+                    TokenKind::Const,
+                    TokenKind::Static,
+                    TokenKind::Trait,
+                    TokenKind::Mod,
+                    TokenKind::Type,
+                    TokenKind::Extern,
+                ]
+                .contains(&self.get().kind)
+            {
+                // the name is the first ident, also after `static mut`
+                let name = self.tokens[self.head..]
+                    .iter()
+                    .find_map(|t| (t.kind == TokenKind::Ident).then(|| t.value.clone()))
+                    .flatten()
+                    .unwrap();
+                self.public.insert(qualify(&self.module, &name));
+            }
+
+            if self.peek(&[TokenKind::Enum]) {
+                self.enum_def();
+            } else if self.peek(&[TokenKind::Struct]) {
+                self.struct_def();
+            } else if self.peek(&[TokenKind::Const]) {
+                self.const_def();
+            } else if self.peek(&[TokenKind::Static]) {
+                res.statics.push(self.static_def());
+            } else if self.peek(&[TokenKind::Extern]) && !public {
+                self.extern_block();
+            } else if self.peek(&[TokenKind::Trait]) {
+                self.trait_def();
+            } else if self.peek(&[TokenKind::Impl]) {
+                res.impls.push(self.impl_block());
+            } else if self.peek(&[TokenKind::Mod]) {
+                self.mod_def(res);
+            } else if self.peek(&[TokenKind::Use]) {
+                self.use_decl(public);
+            } else if self.peek(&[TokenKind::Type]) {
+                self.type_alias();
+            } else if public && self.consume(&[TokenKind::Extern]) {
+                self.expect_abi();
+                // C calls it by its name, wherever it is
+                let name = self.tokens[self.head + 1].value.clone().unwrap();
+                self.c_names.insert(qualify(&self.module, &name));
+                let f = self.fn_def();
+                check_c_signature(&f.name, &f.fn_type);
+                res.definitions.push(FnDef {
+                    exported: true,
+                    ..f
+                });
             } else {
                 res.definitions.push(self.fn_def());
             }
         }
     }
 
+    /// `mod geometry { .. }`, the loader puts the items of `mod geometry;` in braces too
+    fn mod_def(&mut self, res: &mut Global) {
+        self.expect(&[TokenKind::Mod]);
+        let name = self.expect_ident();
+        let path = qualify(&self.module, &name);
+        if !self.modules.insert(path.clone()) {
+            panic!("the name `{}` is defined multiple times", name)
+        }
+
+        self.expect(&[TokenKind::LBrace]);
+        let outer = std::mem::replace(&mut self.module, path);
+        self.items(res);
+        self.module = outer;
+    }
+
+    /// `use geometry::area;` or `use geometry::{area, Rect as R};`
+    fn use_decl(&mut self, public: bool) {
+        self.expect(&[TokenKind::Use]);
+        self.qualifier();
+        let module = self.qualifier.take().unwrap_or_else(|| self.module.clone());
+
+        let mut names = vec![];
+        if self.consume(&[TokenKind::LBrace]) {
+            while !self.consume(&[TokenKind::RBrace]) {
+                names.push(self.use_name());
+                self.consume(&[TokenKind::Comma]);
+            }
+        } else {
+            names.push(self.use_name());
+        }
+        self.expect(&[TokenKind::Semi]);
+
+        for (name, alias) in names {
+            let path = qualify(&module, &name);
+            let target = self.uses.get(&path).cloned().unwrap_or(path);
+            if self.kind(&target).is_none() {
+                panic!("unresolved import `{}`", target)
+            }
+            self.check_visible(&target);
+
+            let alias = qualify(&self.module, &alias);
+            if public {
+                self.public.insert(alias.clone());
+            }
+            self.uses.insert(alias, target);
+        }
+    }
+
+    /// `area` or `area as a` in a `use` declaration, with the name it declares
+    fn use_name(&mut self) -> (String, String) {
+        let name = self.expect_ident();
+        if self.get().value.as_deref() == Some("as") {
+            self.head += 1;
+            (name, self.expect_ident())
+        } else {
+            (name.clone(), name)
+        }
+    }
+
+    /// `crate::`, `super::`, `self::` or `geometry::shapes::` before the ident of an item,
+    /// consumed into `qualifier`
+    fn qualifier(&mut self) {
+        let mut module: Option<String> = None;
+        while self.peek(&[TokenKind::Ident, TokenKind::Colon, TokenKind::Colon]) {
+            let name = self.get().value.clone().unwrap();
+            let base = module.clone().unwrap_or_else(|| self.module.clone());
+            let next = match name.as_str() {
+                "crate" if module.is_none() => String::new(),
+                "self" if module.is_none() => base,
+                "super" => {
+                    if base.is_empty() {
+                        panic!("there are too many leading `super` keywords")
+                    }
+                    parent(&base).to_string()
+                }
+                _ => {
+                    let path = qualify(&base, &name);
+                    let target = self.uses.get(&path).cloned().unwrap_or(path.clone());
+                    if !self.modules.contains(&target) {
+                        // a type, like `Rect::new` or `Shape::Circle`
+                        break;
+                    }
+                    if target != path {
+                        self.check_visible(&path);
+                    }
+                    self.check_visible(&target);
+                    target
+                }
+            };
+            self.head += 3;
+            module = Some(next);
+        }
+        self.qualifier = module;
+    }
+
+    /// path of the item `name` in `map`, in the module of the qualifier or else in the current
+    /// one, also through `use` declarations
+    fn resolve<T>(&self, map: &HashMap<String, T>, name: &str) -> Option<String> {
+        let module = self.qualifier.as_deref().unwrap_or(&self.module);
+        let path = qualify(module, name);
+        let target = self.uses.get(&path).unwrap_or(&path);
+        if !map.contains_key(target) {
+            return None;
+        }
+        if *target != path {
+            self.check_visible(&path);
+        }
+        self.check_visible(target);
+        Some(target.clone())
+    }
+
+    /// the type `name` names, type parameters and `Self` are in scope of their item only
+    fn lookup_type(&self, name: &str) -> Option<Type> {
+        if self.qualifier.is_none() {
+            match self.types.get(name) {
+                Some(ty @ Type::Param { .. }) => return Some(ty.clone()),
+                Some(ty) if name == "Self" => return Some(ty.clone()),
+                _ => {}
+            }
+        }
+        let path = self.resolve(&self.types, name)?;
+        Some(self.types[&path].clone())
+    }
+
+    /// what the item at `path` is, for messages
+    fn kind(&self, path: &str) -> Option<&'static str> {
+        let path = self.uses.get(path).map_or(path, String::as_str);
+        if self.modules.contains(path) {
+            Some("module")
+        } else if self.functions.contains_key(path) {
+            Some("function")
+        } else if let Some(ty) = self.types.get(path) {
+            Some(match ty {
+                Type::Struct { name, .. } if name == path => "struct",
+                Type::Enum { name, .. } if name == path => "enum",
+                _ => "type alias",
+            })
+        } else if self.traits.contains_key(path) {
+            Some("trait")
+        } else if self.consts.contains_key(path) {
+            Some("constant")
+        } else if self.statics.contains_key(path) {
+            Some("static")
+        } else {
+            None
+        }
+    }
+
+    /// panics unless the item at `path` is public or the current module is inside its module
+    fn check_visible(&self, path: &str) {
+        if self.public.contains(path) || within(&self.module, parent(path)) {
+            return;
+        }
+        panic!(
+            "{} `{}` is private",
+            self.kind(path).unwrap_or("item"),
+            last(path)
+        )
+    }
+
+    /// panics unless the field `index` of the struct at `path` can be used here,
+    /// fields of variants are as public as their enum
+    fn check_field(&self, path: &str, index: usize) {
+        match self.pub_fields.get(path) {
+            Some(public) if !public[index] && !within(&self.module, parent(path)) => {
+                let field = match self.types.get(path) {
+                    Some(Type::Struct { fields, .. }) => &fields[index].name,
+                    _ => unreachable!(),
+                };
+                panic!("field `{}` of struct `{}` is private", field, last(path))
+            }
+            _ => {}
+        }
+    }
+
+    /// panics unless `method` is public or the current module is inside the one of its `impl`
+    fn check_method(&self, method: &Method) {
+        if !method.public && !within(&self.module, &method.module) {
+            let kind = if method.receiver {
+                "method"
+            } else {
+                "associated function"
+            };
+            panic!("{} `{}` is private", kind, method.name)
+        }
+    }
+
+    /// symbol of the function at `path`
+    fn fn_symbol(&self, path: &str) -> String {
+        if self.c_names.contains(path) {
+            last(path).to_string()
+        } else {
+            symbol(path)
+        }
+    }
+
     fn fn_def(&mut self) -> FnDef {
         self.expect(&[TokenKind::Fn]);
         let name = self.expect_ident();
 
+        // locals and type parameters are only visible inside their function
+        let outer = self.signatures.clone();
+        let outer_types = self.types.clone();
+        let outer_bounds = self.bounds.clone();
+
+        let generics = self.generic_params();
+        let args = self.params();
+        let fn_type = Type::Fn {
+            args: args.iter().map(|x| x.ty.clone()).collect(),
+            ret: Box::new(self.ret()),
+        };
+
+        if let Type::Fn { ret, .. } = &fn_type {
+            if ret.holds_closure() {
+                panic!(
+                    "{} cannot return a closure, it borrows from the frame creating it",
+                    name
+                )
+            }
+        }
+
+        // methods live in the namespace of their type
+        let symbol = if self.in_impl.is_some() {
+            self.method_def(&name, &fn_type, &args)
+        } else {
+            let path = qualify(&self.module, &name);
+            self.functions.insert(path.clone(), fn_type.clone());
+            self.fn_symbol(&path)
+        };
+        if !generics.is_empty() {
+            self.generics.insert(symbol.clone(), generics.clone());
+        }
+        let Type::Fn { ret, .. } = &fn_type else {
+            unreachable!()
+        };
+        // `fn main() { return 0; }` leaves out the `-> i64` of the value it gives
+        let expected = match **ret {
+            Type::Unit => Type::Unknown,
+            ref ret => ret.clone(),
+        };
+        let outer_ret = std::mem::replace(&mut self.ret, expected);
+        let body = self.block();
+        if let Some(value) = body.exprs.last() {
+            if let Type::Vec { len: Some(_), .. } = **ret {
+                check_len(ret, &value.ty());
+            }
+            // a body ending in `return x;` has given its value already
+            if !matches!(value, Expr::Stmt(_)) {
+                check_type(&self.ret, &value.ty());
+            }
+        }
+        self.ret = outer_ret;
+
+        self.signatures = outer;
+        self.types = outer_types;
+        self.bounds = outer_bounds;
+
+        FnDef {
+            name: symbol,
+            generics: generics.into_iter().map(|param| param.name).collect(),
+            args,
+            fn_type,
+            body,
+            exported: false,
+        }
+    }
+
+    /// `<T: Shape + Eq, U, const N: i64>`, declares the type and const parameters
+    fn generic_params(&mut self) -> Generics {
+        let mut generics = vec![];
+        if self.consume(&[TokenKind::Lt]) {
+            while !self.consume(&[TokenKind::Gt]) {
+                if self.consume(&[TokenKind::Const]) {
+                    let name = self.expect_ident();
+                    self.expect(&[TokenKind::Colon]);
+                    if self.ty() != Type::I64 {
+                        panic!("const parameter {} must be i64", name)
+                    }
+                    self.types.insert(
+                        name.clone(),
+                        Type::Const {
+                            value: Const::param(&name),
+                        },
+                    );
+                    generics.push(Generic {
+                        name,
+                        bounds: vec![],
+                        is_const: true,
+                    });
+                    self.consume(&[TokenKind::Comma]);
+                    continue;
+                }
+
+                let param = self.expect_ident();
+                let mut bounds = vec![];
+                if self.consume(&[TokenKind::Colon]) {
+                    loop {
+                        self.qualifier();
+                        let bound = self.expect_ident();
+                        let bound = self
+                            .resolve(&self.traits, &bound)
+                            .unwrap_or_else(|| panic!("cannot find trait `{}`", bound));
+                        bounds.push(bound);
+                        if !self.consume(&[TokenKind::Plus]) {
+                            break;
+                        }
+                    }
+                }
+                self.types.insert(
+                    param.clone(),
+                    Type::Param {
+                        name: param.clone(),
+                    },
+                );
+                self.bounds.insert(param.clone(), bounds.clone());
+                generics.push(Generic {
+                    name: param,
+                    bounds,
+                    is_const: false,
+                });
+                self.consume(&[TokenKind::Comma]);
+            }
+        }
+        generics
+    }
+
+    /// `(self, a: i64, (b, c): (i64, i64))`, declares the arguments as locals
+    fn params(&mut self) -> Vec<Param> {
         self.expect(&[TokenKind::LParen]);
         let mut args = vec![];
+        if let Some(param) = self.self_param() {
+            args.push(param);
+        }
         while !self.consume(&[TokenKind::RParen]) {
-            let name = self.expect_ident();
+            // the pattern is typed by the annotation after it
+            let pattern_head = self.head;
+            self.skip_pattern();
             self.expect(&[TokenKind::Colon]);
             let ty = self.ty();
             self.consume(&[TokenKind::Comma]);
 
-            self.signatures.insert(name.clone(), ty.clone());
-            args.push(Local { name, ty });
+            let rest = self.head;
+            self.head = pattern_head;
+            let pattern = self.pattern(&ty);
+            self.head = rest;
+
+            args.push(Param { pattern, ty });
+        }
+        args
+    }
+
+    /// `self`, `mut self`, `&self` or `&mut self` in a trait or `impl` block
+    fn self_param(&mut self) -> Option<Param> {
+        let self_ty = self.types.get("Self")?.clone();
+        let mut i = self.head;
+        if self.tokens[i].kind == TokenKind::And {
+            i += 1;
+        }
+        if self.tokens[i].kind == TokenKind::Mut {
+            i += 1;
         }
+        if self.tokens[i].value.as_deref() != Some("self") {
+            return None;
+        }
+
+        let ty = if self.consume(&[TokenKind::And]) {
+            Type::Ptr {
+                to: Box::new(self_ty),
+                mutable: self.consume(&[TokenKind::Mut]),
+            }
+        } else {
+            self_ty
+        };
+        let pattern = self.pattern(&ty);
+        self.consume(&[TokenKind::Comma]);
+        Some(Param { pattern, ty })
+    }
 
-        let ret = if self.consume(&[TokenKind::Minus, TokenKind::Gt]) {
+    /// `-> ty`, unit when omitted
+    fn ret(&mut self) -> Type {
+        if self.consume(&[TokenKind::Minus, TokenKind::Gt]) {
             self.ty()
         } else {
-            // default void
-            Type::Void
+            Type::Unit
+        }
+    }
+
+    /// registers the method `name` of the `impl` block being parsed, returns its symbol
+    fn method_def(&mut self, name: &str, fn_type: &Type, args: &[Param]) -> String {
+        let (self_ty, trait_name) = self.in_impl.clone().unwrap();
+        let symbol = match &trait_name {
+            Some(trait_name) => {
+                let declared = self.traits[trait_name]
+                    .iter()
+                    .find(|m| m.name == name)
+                    .unwrap_or_else(|| {
+                        panic!(
+                            "method `{}` is not a member of trait `{}`",
+                            name, trait_name
+                        )
+                    });
+                let expected = declared
+                    .fn_type
+                    .subst(&HashMap::from([("Self".to_string(), self_ty.clone())]));
+                if *fn_type != expected {
+                    panic!(
+                        "method `{}` has an incompatible type for trait `{}`, expected {:?} but found {:?}",
+                        name, trait_name, expected, fn_type
+                    )
+                }
+                mangle(&declared.symbol, std::slice::from_ref(&self_ty))
+            }
+            None => mangle(&format!("impl.{}", name), std::slice::from_ref(&self_ty)),
         };
 
-        let fn_type = Type::Fn {
-            args: args.iter().map(|x| x.ty.clone()).collect(),
-            ret: Box::new(ret),
+        if self
+            .methods
+            .iter()
+            .any(|(ty, m)| *ty == self_ty && m.name == name)
+        {
+            panic!(
+                "duplicate definitions with name `{}` for {:?}",
+                name, self_ty
+            )
+        }
+        self.methods.push((
+            self_ty,
+            Method {
+                name: name.to_string(),
+                symbol: symbol.clone(),
+                fn_type: fn_type.clone(),
+                receiver: takes_self(args),
+                // trait methods are as public as the trait
+                public: trait_name.is_some(),
+                module: self.module.clone(),
+            },
+        ));
+        symbol
+    }
+
+    /// `trait Shape { fn area(&self) -> i64; }`, registers the method signatures
+    fn trait_def(&mut self) {
+        self.expect(&[TokenKind::Trait]);
+        let name = self.expect_ident();
+        let name = qualify(&self.module, &name);
+
+        let outer = self.signatures.clone();
+        let outer_types = self.types.clone();
+        self.types.insert(
+            "Self".to_string(),
+            Type::Param {
+                name: "Self".to_string(),
+            },
+        );
+
+        self.expect(&[TokenKind::LBrace]);
+        let mut methods = vec![];
+        while !self.consume(&[TokenKind::RBrace]) {
+            self.expect(&[TokenKind::Fn]);
+            let method = self.expect_ident();
+            let args = self.params();
+            let fn_type = Type::Fn {
+                args: args.iter().map(|x| x.ty.clone()).collect(),
+                ret: Box::new(self.ret()),
+            };
+            self.expect(&[TokenKind::Semi]);
+
+            methods.push(Method {
+                symbol: format!("impl.{}.{}", symbol(&name), method),
+                name: method,
+                fn_type,
+                receiver: takes_self(&args),
+                public: true,
+                module: self.module.clone(),
+            });
+        }
+
+        self.signatures = outer;
+        self.types = outer_types;
+        self.traits.insert(name, methods);
+    }
+
+    /// `impl Rect { .. }` or `impl Shape for Rect { .. }`
+    fn impl_block(&mut self) -> Impl {
+        self.expect(&[TokenKind::Impl]);
+        let start = self.head;
+        self.qualifier();
+        let trait_name = if self.peek(&[TokenKind::Ident, TokenKind::For]) {
+            let name = self.expect_ident();
+            self.expect(&[TokenKind::For]);
+            let path = self
+                .resolve(&self.traits, &name)
+                .unwrap_or_else(|| panic!("cannot find trait `{}`", name));
+            Some(path)
+        } else {
+            // the path is of the self type
+            self.head = start;
+            None
         };
-        self.signatures.insert(name.clone(), fn_type.clone());
+        let self_ty = self.ty();
+        if let Some(trait_name) = &trait_name {
+            if self.implements(&self_ty, trait_name) {
+                panic!(
+                    "conflicting implementations of trait `{}` for {:?}",
+                    trait_name, self_ty
+                )
+            }
+            self.impls.push((trait_name.clone(), self_ty.clone()));
+        }
 
-        FnDef {
-            name,
-            args,
-            fn_type,
-            body: self.block(),
+        let outer_types = self.types.clone();
+        self.types.insert("Self".to_string(), self_ty.clone());
+        self.in_impl = Some((self_ty.clone(), trait_name.clone()));
+
+        self.expect(&[TokenKind::LBrace]);
+        let mut methods = vec![];
+        while !self.consume(&[TokenKind::RBrace]) {
+            let public = self.consume(&[TokenKind::Pub]);
+            methods.push(self.fn_def());
+            if public {
+                self.methods.last_mut().unwrap().1.public = true;
+            }
+        }
+
+        self.in_impl = None;
+        self.types = outer_types;
+
+        if let Some(trait_name) = &trait_name {
+            let missing: Vec<_> = self.traits[trait_name]
+                .iter()
+                .filter(|m| {
+                    !self
+                        .methods
+                        .iter()
+                        .any(|(ty, n)| *ty == self_ty && n.name == m.name)
+                })
+                .map(|m| format!("`{}`", m.name))
+                .collect();
+            if !missing.is_empty() {
+                panic!(
+                    "not all trait items implemented, missing: {}",
+                    missing.join(", ")
+                )
+            }
+        }
+
+        Impl { methods }
+    }
+
+    /// whether `ty` implements `trait_name`, by an `impl` or a bound on the type parameter
+    fn implements(&self, ty: &Type, trait_name: &str) -> bool {
+        match ty {
+            Type::Param { name } => self
+                .bounds
+                .get(name)
+                .is_some_and(|bounds| bounds.iter().any(|b| b == trait_name)),
+            _ => self
+                .impls
+                .iter()
+                .any(|(t, implementor)| t == trait_name && implementor == ty),
+        }
+    }
+
+    /// the method or associated function `name` of `ty`, with its type parameters
+    fn method(&self, ty: &Type, name: &str) -> Option<(Method, Generics)> {
+        if let Type::Param { name: param } = ty {
+            // declared by a trait bounding the parameter, resolved once it is instantiated
+            return self.bounds.get(param)?.iter().find_map(|bound| {
+                let method = self.traits[bound].iter().find(|m| m.name == name)?;
+                Some((
+                    method.clone(),
+                    vec![Generic {
+                        name: "Self".to_string(),
+                        bounds: vec![bound.clone()],
+                        is_const: false,
+                    }],
+                ))
+            });
+        }
+        let (_, method) = self
+            .methods
+            .iter()
+            .find(|(implementor, m)| implementor == ty && m.name == name)?;
+        let generics = self
+            .generics
+            .get(&method.symbol)
+            .cloned()
+            .unwrap_or_default();
+        Some((method.clone(), generics))
+    }
+
+    /// `extern "C" { fn labs(x: i64) -> i64; }`, registers the signatures,
+    /// the symbols are left to the linker
+    fn extern_block(&mut self) {
+        self.expect(&[TokenKind::Extern]);
+        self.expect_abi();
+
+        self.expect(&[TokenKind::LBrace]);
+        while !self.consume(&[TokenKind::RBrace]) {
+            self.expect(&[TokenKind::Fn]);
+            let name = self.expect_ident();
+
+            self.expect(&[TokenKind::LParen]);
+            let mut args = vec![];
+            while !self.consume(&[TokenKind::RParen]) {
+                // variadic rest like `printf(fmt: &i64, ...)`, takes anything
+                if self.consume(&[TokenKind::Dot, TokenKind::Dot, TokenKind::Dot]) {
+                    self.expect(&[TokenKind::RParen]);
+                    break;
+                }
+                self.expect_ident();
+                self.expect(&[TokenKind::Colon]);
+                args.push(self.c_ty().0);
+                self.consume(&[TokenKind::Comma]);
+            }
+
+            let ret = if self.consume(&[TokenKind::Minus, TokenKind::Gt]) {
+                let (ret, narrow) = self.c_ty();
+                if let Some(narrow) = narrow {
+                    self.c_returns.insert(name.clone(), narrow);
+                }
+                ret
+            } else {
+                Type::Unit
+            };
+            self.expect(&[TokenKind::Semi]);
+
+            let path = qualify(&self.module, &name);
+            self.c_names.insert(path.clone());
+            self.functions.insert(
+                path,
+                Type::Fn {
+                    args,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+    }
+
+    /// a type of a foreign signature, where C integers like `i32` or `u64` are i64 in sofa,
+    /// `&u8` points at bytes
+    ///
+    /// arguments are passed in full registers, the callee only reads the bits it declares.
+    /// a narrower return leaves the upper bits undefined, so it is widened at the call.
+    fn c_ty(&mut self) -> (Type, Option<(u32, bool)>) {
+        if self.peek(&[TokenKind::And, TokenKind::Ident])
+            || self.peek(&[TokenKind::And, TokenKind::Mut, TokenKind::Ident])
+        {
+            let to = self.tokens[self.head + 1..]
+                .iter()
+                .find(|t| t.kind == TokenKind::Ident)
+                .and_then(|t| t.value.as_deref());
+            if let Some(to) = to.filter(|to| *to != "u8" && c_int(to).is_some()) {
+                panic!(
+                    "`&{}` cannot cross the C ABI, sofa only points at i64 and u8",
+                    to
+                )
+            }
+        }
+        let int = self
+            .peek(&[TokenKind::Ident])
+            .then(|| c_int(self.get().value.as_deref().unwrap()))
+            .flatten();
+        match int {
+            Some(int) => {
+                self.head += 1;
+                (Type::I64, (int.0 < 64).then_some(int))
+            }
+            None => (self.ty(), None),
+        }
+    }
+
+    /// `"C"` after `extern`, the only ABI we speak
+    fn expect_abi(&mut self) {
+        let abi = self.expect_str();
+        if abi != "C" {
+            panic!("unsupported ABI {:?}", abi)
+        }
+    }
+
+    /// `const N: i64 = 10;`, registers the value, nothing is left in the ast
+    fn const_def(&mut self) {
+        self.expect(&[TokenKind::Const]);
+        let name = self.expect_ident();
+        let (ty, value) = self.global_value(&name);
+        self.consts
+            .insert(qualify(&self.module, &name), (ty, value));
+    }
+
+    /// `static mut COUNTER: i64 = 0;`
+    fn static_def(&mut self) -> StaticDef {
+        self.expect(&[TokenKind::Static]);
+        let mutable = self.consume(&[TokenKind::Mut]);
+        let name = self.expect_ident();
+        let (ty, value) = self.global_value(&name);
+        let path = qualify(&self.module, &name);
+        self.statics.insert(path.clone(), ty.clone());
+
+        StaticDef {
+            name: symbol(&path),
+            ty,
+            mutable,
+            value,
+        }
+    }
+
+    /// `: ty = value;` of a global item, evaluated at compile time
+    fn global_value(&mut self, name: &str) -> (Type, i64) {
+        self.expect(&[TokenKind::Colon]);
+        let ty = self.ty();
+        if !matches!(ty, Type::I64 | Type::Bool) {
+            panic!("{} must be i64 or bool, found {:?}", name, ty)
+        }
+        self.expect(&[TokenKind::Eq]);
+        let expr = self.expr();
+        self.expect(&[TokenKind::Semi]);
+
+        if expr.ty() != ty {
+            panic!(
+                "mismatched types, expected {:?} but found {:?}",
+                ty,
+                expr.ty()
+            )
+        }
+        let value = expr
+            .eval()
+            .unwrap_or_else(|| panic!("initializer of {} is not a constant", name));
+        (ty, value)
+    }
+
+    /// registers the enum as a type, nothing is left in the ast
+    fn enum_def(&mut self) {
+        self.expect(&[TokenKind::Enum]);
+        let name = self.expect_ident();
+        let name = qualify(&self.module, &name);
+        self.expect(&[TokenKind::LBrace]);
+
+        let mut variants: Vec<VariantDef> = vec![];
+        while !self.consume(&[TokenKind::RBrace]) {
+            let variant_name = self.expect_ident();
+            if variants.iter().any(|v| v.name == variant_name) {
+                panic!(
+                    "variant {} is defined multiple times in {}",
+                    variant_name, name
+                )
+            }
+
+            let mut fields = vec![];
+            let kind = if self.consume(&[TokenKind::LParen]) {
+                while !self.consume(&[TokenKind::RParen]) {
+                    fields.push(Field {
+                        name: fields.len().to_string(),
+                        ty: self.ty(),
+                    });
+                    self.consume(&[TokenKind::Comma]);
+                }
+                FieldsKind::Tuple
+            } else if self.consume(&[TokenKind::LBrace]) {
+                // fields of variants are as public as the enum
+                fields = self.named_fields_def().0;
+                FieldsKind::Named
+            } else {
+                FieldsKind::Unit
+            };
+            self.consume(&[TokenKind::Comma]);
+
+            variants.push(VariantDef {
+                name: variant_name,
+                kind,
+                fields,
+            });
+        }
+
+        self.types
+            .insert(name.clone(), Type::Enum { name, variants });
+    }
+
+    /// registers the struct as a type, nothing is left in the ast
+    fn struct_def(&mut self) {
+        self.expect(&[TokenKind::Struct]);
+        let name = self.expect_ident();
+        let name = qualify(&self.module, &name);
+        let (fields, public) = if self.consume(&[TokenKind::LParen]) {
+            // a tuple struct like `struct Meters(i64);`, its fields are named by index
+            let mut fields: Vec<Field> = vec![];
+            let mut public = vec![];
+            while !self.consume(&[TokenKind::RParen]) {
+                public.push(self.consume(&[TokenKind::Pub]));
+                fields.push(Field {
+                    name: fields.len().to_string(),
+                    ty: self.ty(),
+                });
+                self.consume(&[TokenKind::Comma]);
+            }
+            self.expect(&[TokenKind::Semi]);
+            (fields, public)
+        } else {
+            self.expect(&[TokenKind::LBrace]);
+            self.named_fields_def()
+        };
+
+        self.pub_fields.insert(name.clone(), public);
+        self.types
+            .insert(name.clone(), Type::Struct { name, fields });
+    }
+
+    /// `type Index = i64;`, the name stands for the type wherever it is used
+    fn type_alias(&mut self) {
+        self.expect(&[TokenKind::Type]);
+        let name = self.expect_ident();
+        let path = qualify(&self.module, &name);
+        self.expect(&[TokenKind::Eq]);
+        let ty = self.alias_target(&path);
+        self.expect(&[TokenKind::Semi]);
+
+        self.types.insert(path, ty);
+    }
+
+    /// the target of the alias at `path`, starting at the current token
+    fn alias_target(&mut self, path: &str) -> Type {
+        if self.expanding.iter().any(|p| p == path) {
+            panic!("cycle detected when expanding type alias `{}`", path)
+        }
+        self.expanding.push(path.to_string());
+        let ty = self.ty();
+        self.expanding.pop();
+        ty
+    }
+
+    /// declares the alias `name` when it is named before its `type` item
+    fn forward_alias(&mut self, name: &str) {
+        if self.lookup_type(name).is_some() {
+            return;
+        }
+        let Some(path) = self.resolve(&self.aliases, name) else {
+            return;
+        };
+        let (target, module) = self.aliases[&path].clone();
+        let head = std::mem::replace(&mut self.head, target);
+        let outer = std::mem::replace(&mut self.module, module);
+        let qualifier = self.qualifier.take();
+        let ty = self.alias_target(&path);
+        self.head = head;
+        self.module = outer;
+        self.qualifier = qualifier;
+
+        self.types.insert(path, ty);
+    }
+
+    /// `name: ty, pub name: ty, ..` up to the closing brace, with whether each is `pub`
+    fn named_fields_def(&mut self) -> (Vec<Field>, Vec<bool>) {
+        let mut fields: Vec<Field> = vec![];
+        let mut public = vec![];
+        while !self.consume(&[TokenKind::RBrace]) {
+            public.push(self.consume(&[TokenKind::Pub]));
+            let name = self.expect_ident();
+            if fields.iter().any(|f| f.name == name) {
+                panic!("field {} is defined multiple times", name)
+            }
+            self.expect(&[TokenKind::Colon]);
+            fields.push(Field {
+                name,
+                ty: self.ty(),
+            });
+            self.consume(&[TokenKind::Comma]);
         }
+        (fields, public)
+    }
+
+    /// whether the current token names a struct, followed by its fields
+    fn peek_struct(&mut self) -> bool {
+        (self.peek(&[TokenKind::Ident, TokenKind::LBrace])
+            || self.peek(&[TokenKind::Ident, TokenKind::LParen]))
+            && matches!(
+                self.lookup_type(self.get().value.as_deref().unwrap()),
+                Some(Type::Struct { .. })
+            )
     }
 
     fn block(&mut self) -> Block {
@@ -177,72 +1273,213 @@ impl<'ctx> SofaParser<'ctx> {
                 expr
             });
         }
+        infer_vecs(&mut res.exprs);
         res
     }
 
     fn expr(&mut self) -> Expr {
+        let pos = self.get().pos.0;
         let a = self.expr1();
-        self.binop(a)
+        self.binop(a, pos)
     }
 
     fn expr1(&mut self) -> Expr {
+        let pos = self.get().pos.0;
+        let start = self.head;
+        self.qualifier();
         let res = if self.peek(&[TokenKind::LBrace]) {
             Expr::Block(self.block())
         } else if self.consume(&[TokenKind::Return]) {
+            let mut value = self.expr();
+            infer(&mut value, &self.ret);
+            let value = self.coerce(value, &self.ret);
+            check_type(&self.ret, &value.ty());
             Expr::Return(Return {
-                expr: Box::new(self.expr()),
+                expr: Box::new(value),
             })
         } else if self.consume(&[TokenKind::Loop]) {
             Expr::Loop(Loop { body: self.block() })
         } else if self.peek(&[TokenKind::If]) {
             Expr::IfElse(self.ifelse())
-        } else if self.peek(&[TokenKind::Ident, TokenKind::LParen]) {
+        } else if self.peek(&[TokenKind::Match]) {
+            Expr::Match(self.match_expr())
+        } else if self.peek(&[TokenKind::Ident, TokenKind::Colon, TokenKind::Colon])
+            && self.get().value.as_deref() == Some("Box")
+        {
+            Expr::BoxNew(self.box_new())
+        } else if self.peek(&[TokenKind::Ident, TokenKind::Colon, TokenKind::Colon])
+            && self.get().value.as_deref() == Some("Vec")
+        {
+            Expr::VecNew(self.vec_new())
+        } else if self.peek(&[
+            TokenKind::Ident,
+            TokenKind::Colon,
+            TokenKind::Colon,
+            TokenKind::Lt,
+        ]) && self
+            .resolve(&self.functions, self.get().value.as_deref().unwrap())
+            .is_some_and(|path| self.generics.contains_key(&self.fn_symbol(&path)))
+        {
             Expr::FnCall(self.fn_call())
+        } else if self.peek_assoc_call() {
+            // the type is parsed with its path
+            self.head = start;
+            Expr::FnCall(self.assoc_call())
+        } else if self.peek(&[TokenKind::Ident, TokenKind::Colon, TokenKind::Colon]) {
+            Expr::Variant(self.variant())
+        } else if self.peek_struct() {
+            Expr::Struct(self.struct_lit())
+        } else if self.peek(&[TokenKind::Ident, TokenKind::LParen])
+            && matches!(self.get().value.as_deref(), Some("panic" | "assert"))
+        {
+            self.builtin(pos)
+        } else if self.peek(&[TokenKind::Ident, TokenKind::LParen])
+            && (self.qualifier.is_some()
+                || !self
+                    .signatures
+                    .contains_key(self.get().value.as_deref().unwrap()))
+        {
+            let call = self.fn_call();
+            match self.c_returns.get(&call.name) {
+                Some(&int) => widen(Expr::FnCall(call), int, pos),
+                None => Expr::FnCall(call),
+            }
+        } else if self.peek(&[TokenKind::Or]) {
+            Expr::Closure(self.closure(&[]))
         } else if self.peek(&[TokenKind::Let]) {
             Expr::Init(self.init())
         } else if self.peek(&[TokenKind::And])
             || self.peek(&[TokenKind::Star])
             || self.peek(&[TokenKind::Minus])
+            || self.peek(&[TokenKind::Bang])
         {
             self.unary()
         } else if self.consume(&[TokenKind::LParen]) {
-            let res = Expr::Enclosed(Enclosed {
-                expr: Box::new(self.expr()),
-            });
-            self.expect(&[TokenKind::RParen]);
-            res
+            self.parenthesized()
         } else if self.consume(&[TokenKind::True]) {
             Expr::Bool(Bool::True)
         } else if self.consume(&[TokenKind::False]) {
             Expr::Bool(Bool::False)
         } else if self.peek(&[TokenKind::Ident]) {
-            Expr::Local(self.local())
+            self.path()
         } else if self.peek(&[TokenKind::Number]) {
             Expr::Number(self.number())
+        } else if self.peek(&[TokenKind::Str]) {
+            panic!("string literals are not supported yet")
         } else {
             panic!("found {:?}", self.get())
         };
 
-        // postfix unary
-        if self.consume(&[TokenKind::LBlanket]) {
-            self.index(res)
+        self.postfix(res, pos)
+    }
+
+    /// `()`, `(e)` or `(e0, e1, ..)`, after the opening paren
+    fn parenthesized(&mut self) -> Expr {
+        let mut elements = vec![];
+        let mut trailing_comma = false;
+        while !self.consume(&[TokenKind::RParen]) {
+            elements.push(self.expr());
+            trailing_comma = self.consume(&[TokenKind::Comma]);
+            if !trailing_comma {
+                self.expect(&[TokenKind::RParen]);
+                break;
+            }
+        }
+
+        if elements.len() == 1 && !trailing_comma {
+            Expr::Enclosed(Enclosed {
+                expr: Box::new(elements.pop().unwrap()),
+            })
         } else {
-            res
+            Expr::Tuple(Tuple { elements })
         }
     }
 
-    /// infix binop
-    fn binop(&mut self, lhs: Expr) -> Expr {
+    /// postfix unary, on the operand starting at `pos`
+    fn postfix(&mut self, mut res: Expr, pos: usize) -> Expr {
+        loop {
+            if self.consume(&[TokenKind::LBlanket]) {
+                res = self.index(res, pos);
+            } else if self.peek(&[TokenKind::LParen])
+                && matches!(res.ty(), Type::Fn { .. } | Type::Closure { .. })
+            {
+                res = self.value_call(res);
+            } else if self.peek(&[TokenKind::Dot, TokenKind::Dot]) {
+                // a range, `a[lo..hi]`
+                break res;
+            } else if self.peek(&[TokenKind::Dot, TokenKind::Ident, TokenKind::LParen]) {
+                self.expect(&[TokenKind::Dot]);
+                res = self.method_call(res, pos);
+            } else if self.consume(&[TokenKind::Dot]) {
+                // fields are reached through references and boxes
+                while let Type::Ptr { .. } | Type::Box { .. } = res.ty() {
+                    res = Expr::UnOp(UnOp {
+                        kind: UnOpKind::Deref,
+                        expr: Box::new(res),
+                    });
+                }
+                let index = match res.ty() {
+                    Type::Tuple { elements } if self.peek(&[TokenKind::Number]) => {
+                        let index: usize = self.expect_number().parse().unwrap();
+                        if index >= elements.len() {
+                            panic!("{:?} has no member {}", res.ty(), index)
+                        }
+                        index
+                    }
+                    Type::Struct { name, fields } if self.peek(&[TokenKind::Number]) => {
+                        let index: usize = self.expect_number().parse().unwrap();
+                        if index >= fields.len() || !is_tuple(&fields) {
+                            panic!("{} has no field {}", name, index)
+                        }
+                        self.check_field(&name, index);
+                        index
+                    }
+                    Type::Struct { name, fields } => {
+                        let field = self.expect_ident();
+                        let index = fields
+                            .iter()
+                            .position(|f| f.name == field)
+                            .unwrap_or_else(|| panic!("{} has no field {}", name, field));
+                        self.check_field(&name, index);
+                        index
+                    }
+                    ty => panic!("{:?} has no members", ty),
+                };
+                res = Expr::Member(Member {
+                    expr: Box::new(res),
+                    index,
+                });
+            } else {
+                break res;
+            }
+        }
+    }
+
+    /// infix binop, on the lhs starting at `pos`
+    fn binop(&mut self, lhs: Expr, pos: usize) -> Expr {
         if let Some(op) = self.consume_binop() {
             Expr::BinOp(BinOp {
                 op,
                 lhs: Box::new(lhs),
                 rhs: Box::new(self.expr()),
+                pos,
+                wrapping: false,
             })
         } else if self.consume(&[TokenKind::Eq]) {
+            let mut rhs = self.expr();
+            infer(&mut rhs, &lhs.ty());
+            let rhs = self.coerce(rhs, &lhs.ty());
+            check_type(&lhs.ty(), &rhs.ty());
             Expr::Assign(Assign {
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            })
+        } else if let Some(op) = self.consume_compound_op() {
+            Expr::CompoundAssign(CompoundAssign {
+                op,
                 lhs: Box::new(lhs),
                 rhs: Box::new(self.expr()),
+                pos,
             })
         } else {
             lhs
@@ -257,34 +1494,428 @@ impl<'ctx> SofaParser<'ctx> {
                 expr: Box::new(self.unary()),
             })
         } else if self.consume(&[TokenKind::And]) {
+            let mutable = self.consume(&[TokenKind::Mut]);
+            match self.unary() {
+                Expr::Slice(slice) => Expr::Slice(Slice { mutable, ..slice }),
+                expr => Expr::UnOp(UnOp {
+                    kind: if mutable {
+                        UnOpKind::RefMut
+                    } else {
+                        UnOpKind::Ref
+                    },
+                    expr: Box::new(expr),
+                }),
+            }
+        } else if self.peek(&[TokenKind::Minus]) {
+            let pos = self.get().pos.0;
+            self.head += 1;
             Expr::UnOp(UnOp {
-                kind: UnOpKind::Ref,
-                expr: Box::new(self.unary()),
+                kind: UnOpKind::Neg { pos },
+                expr: Box::new(self.expr()),
             })
-        } else if self.consume(&[TokenKind::Minus]) {
+        } else if self.consume(&[TokenKind::Bang]) {
             Expr::UnOp(UnOp {
-                kind: UnOpKind::Neg,
-                expr: Box::new(self.expr()),
+                kind: UnOpKind::Not,
+                expr: Box::new(self.unary()),
             })
         } else {
             self.expr1()
         }
     }
 
-    fn index(&mut self, lhs: Expr) -> Expr {
-        let res = Expr::UnOp(UnOp {
-            kind: UnOpKind::Deref,
-            expr: Box::new(Expr::BinOp(BinOp {
-                op: BinOpKind::Add,
-                lhs: Box::new(lhs),
-                rhs: Box::new(self.expr()),
-            })),
-        });
+    /// `a[i]`, or a range `a[lo..hi]` with optional bounds, after the opening bracket
+    fn index(&mut self, lhs: Expr, pos: usize) -> Expr {
+        let lo = (!self.peek(&[TokenKind::Dot, TokenKind::Dot])).then(|| self.expr());
+        let res = if self.consume(&[TokenKind::Dot, TokenKind::Dot]) {
+            let hi = (!self.peek(&[TokenKind::RBlanket])).then(|| self.expr());
+            for bound in lo.iter().chain(hi.iter()) {
+                if bound.ty() != Type::I64 {
+                    panic!("range bounds must be i64, found {:?}", bound.ty())
+                }
+            }
+            Expr::Slice(Slice {
+                expr: Box::new(lhs),
+                lo: lo.map(Box::new),
+                hi: hi.map(Box::new),
+                mutable: false,
+                pos,
+            })
+        } else {
+            let index = lo.unwrap();
+            if index.ty() != Type::I64 {
+                panic!("index must be i64, found {:?}", index.ty())
+            }
+            Expr::Index(Index {
+                expr: Box::new(lhs),
+                index: Box::new(index),
+                pos,
+            })
+        };
 
         self.expect(&[TokenKind::RBlanket]);
         res
     }
 
+    /// `a.len()`, `v.push(x)` or `v.pop()`, after the dot
+    fn method_call(&mut self, receiver: Expr, pos: usize) -> Expr {
+        let name = self.expect_ident();
+
+        // methods from `impl` blocks, looked up through references and boxes
+        let mut ty = receiver.ty();
+        let mut derefs = 0;
+        loop {
+            if let Type::Dyn { .. } = ty {
+                return self.dyn_call(autoref(receiver, derefs, &ty), &name);
+            }
+            if let Some((method, generics)) = self.method(&ty, &name) {
+                if !method.receiver {
+                    panic!("`{}` is an associated function, not a method", name)
+                }
+                self.check_method(&method);
+                let receiver = match &method.fn_type {
+                    Type::Fn { args, .. } => autoref(receiver, derefs, &args[0]),
+                    _ => unreachable!(),
+                };
+                let map = HashMap::new();
+                return Expr::FnCall(self.call(
+                    method.symbol,
+                    method.fn_type,
+                    generics,
+                    vec![receiver],
+                    map,
+                ));
+            }
+            match ty {
+                Type::Ptr { to, .. } | Type::Box { to } => {
+                    ty = *to;
+                    derefs += 1;
+                }
+                _ => break,
+            }
+        }
+        // so are the builtin ones
+        let receiver = autoref(receiver, derefs, &ty);
+
+        self.expect(&[TokenKind::LParen]);
+        let mut args = vec![];
+        while !self.consume(&[TokenKind::RParen]) {
+            args.push(self.expr());
+            self.consume(&[TokenKind::Comma]);
+        }
+
+        // built into the compiler, there is no standard library written in sofa to hold `Vec`
+        match (name.as_str(), receiver.ty(), args.len()) {
+            ("len", Type::Array { len, .. } | Type::Vec { len: Some(len), .. }, 0) => {
+                match len.eval() {
+                    Some(len) => Expr::Number(Number {
+                        value: len.to_string(),
+                    }),
+                    None => Expr::ConstExpr(ConstExpr { value: len }),
+                }
+            }
+            ("len", Type::Slice { .. } | Type::Vec { .. }, 0) => Expr::Member(Member {
+                expr: Box::new(receiver),
+                index: 1,
+            }),
+            ("push", Type::Vec { element, len }, 1) => {
+                let mut value = args.pop().unwrap();
+                if *element == Type::Unknown {
+                    // `let mut v = Vec::new();` takes the type of its first element
+                    if let Expr::Local(Local { name, .. }) = &receiver {
+                        let ty = Type::Vec {
+                            element: Box::new(value.ty()),
+                            len: None,
+                        };
+                        self.signatures.insert(name.clone(), ty);
+                    }
+                    return Expr::VecPush(VecPush {
+                        vec: Box::new(receiver),
+                        value: Box::new(value),
+                    });
+                }
+                infer(&mut value, &element);
+                let value = self.coerce(value, &element);
+                if value.ty() != *element {
+                    panic!(
+                        "mismatched types, expected {:?} but found {:?}",
+                        element,
+                        value.ty()
+                    )
+                }
+                let Some(len) = len else {
+                    return Expr::VecPush(VecPush {
+                        vec: Box::new(receiver),
+                        value: Box::new(value),
+                    });
+                };
+
+                // a `Vec<T, n>` does not grow in place, it moves into a `Vec<T, n + 1>`
+                let single = Local {
+                    name: "vec.push".to_string(),
+                    ty: Type::Vec {
+                        element: element.clone(),
+                        len: None,
+                    },
+                };
+                let single = Expr::Block(Block {
+                    exprs: vec![
+                        Expr::Init(Init {
+                            pattern: Pattern::Binding {
+                                local: single.clone(),
+                                mutable: true,
+                            },
+                            value: Some(Box::new(Expr::VecNew(VecNew {
+                                ty: single.ty.clone(),
+                            }))),
+                        }),
+                        Expr::Stmt(Stmt {
+                            expr: Box::new(Expr::VecPush(VecPush {
+                                vec: Box::new(Expr::Local(single.clone())),
+                                value: Box::new(value),
+                            })),
+                        }),
+                        Expr::Local(single),
+                    ],
+                });
+                Expr::VecConcat(VecConcat {
+                    lhs: Box::new(receiver),
+                    rhs: Box::new(single),
+                    ty: Type::Vec {
+                        element,
+                        len: Some(len + Const::number(1)),
+                    },
+                })
+            }
+            ("pop", Type::Vec { len: None, .. }, 0) => Expr::VecPop(VecPop {
+                vec: Box::new(receiver),
+                pos,
+            }),
+            (
+                "concat",
+                Type::Vec {
+                    element,
+                    len: Some(len),
+                },
+                1,
+            ) => {
+                let rhs = args.pop().unwrap();
+                let rhs_len = match rhs.ty() {
+                    Type::Vec {
+                        element: e,
+                        len: Some(rhs_len),
+                    } if e == element => rhs_len,
+                    ty => panic!(
+                        "mismatched types, expected Vec<{:?}, _> but found {:?}",
+                        element, ty
+                    ),
+                };
+                Expr::VecConcat(VecConcat {
+                    lhs: Box::new(receiver),
+                    rhs: Box::new(rhs),
+                    ty: Type::Vec {
+                        element,
+                        len: Some(len + rhs_len),
+                    },
+                })
+            }
+            ("head", Type::Vec { len: Some(len), .. }, 0) => {
+                // `n + 1` for some length `n`
+                if !(len.clone() - Const::number(1)).is_natural() {
+                    panic!("cannot prove that the length {:?} is at least 1", len)
+                }
+                Expr::Index(Index {
+                    expr: Box::new(receiver),
+                    index: Box::new(Expr::Number(Number {
+                        value: "0".to_string(),
+                    })),
+                    pos,
+                })
+            }
+            (
+                "wrapping_add" | "wrapping_sub" | "wrapping_mul" | "wrapping_div" | "wrapping_rem",
+                Type::I64,
+                1,
+            ) => {
+                let rhs = args.pop().unwrap();
+                if rhs.ty() != Type::I64 {
+                    panic!("mismatched types, expected I64 but found {:?}", rhs.ty())
+                }
+                Expr::BinOp(BinOp {
+                    op: match name.as_str() {
+                        "wrapping_add" => BinOpKind::Add,
+                        "wrapping_sub" => BinOpKind::Sub,
+                        "wrapping_mul" => BinOpKind::Mul,
+                        "wrapping_div" => BinOpKind::Div,
+                        _ => BinOpKind::Rem,
+                    },
+                    lhs: Box::new(receiver),
+                    rhs: Box::new(rhs),
+                    pos,
+                    wrapping: true,
+                })
+            }
+            (_, ty, _) => panic!(
+                "{:?} has no method {} taking {} arguments",
+                ty,
+                name,
+                args.len()
+            ),
+        }
+    }
+
+    /// `x.name(..)` on a `dyn Trait` receiver, called through its vtable
+    fn dyn_call(&mut self, receiver: Expr, name: &str) -> Expr {
+        let ty = receiver.ty();
+        let (trait_name, mutable, boxed) = match &ty {
+            Type::Dyn {
+                name,
+                mutable,
+                boxed,
+            } => (name.clone(), *mutable, *boxed),
+            _ => unreachable!(),
+        };
+        let (slot, method) = self.traits[&trait_name]
+            .iter()
+            .enumerate()
+            .find(|(_, m)| m.name == name)
+            .map(|(slot, m)| (slot, m.clone()))
+            .unwrap_or_else(|| panic!("no method named `{}` found for {:?}", name, ty));
+
+        // `&self` is the receiver itself
+        let fn_type = method
+            .fn_type
+            .subst(&HashMap::from([("Self".to_string(), ty.clone())]));
+        if let Type::Fn { args, .. } = &fn_type {
+            if matches!(args[0], Type::Ptr { mutable: true, .. }) && !mutable && !boxed {
+                panic!("cannot borrow data behind a `&` reference as mutable")
+            }
+        }
+
+        let mut call = self.call(
+            method.symbol,
+            fn_type,
+            vec![],
+            vec![receiver],
+            HashMap::new(),
+        );
+        call.dispatch = Some(slot);
+        Expr::FnCall(call)
+    }
+
+    /// `f(a, b)` where `f` is a function pointer or a closure, after the callee
+    fn value_call(&mut self, callee: Expr) -> Expr {
+        let params = match callee.ty() {
+            Type::Fn { args, .. } | Type::Closure { args, .. } => args,
+            _ => unreachable!(),
+        };
+
+        self.expect(&[TokenKind::LParen]);
+        let mut args = vec![];
+        while !self.consume(&[TokenKind::RParen]) {
+            let arg = match params.get(args.len()) {
+                Some(param) => {
+                    let mut arg = self.expr_expecting(param);
+                    infer(&mut arg, param);
+                    self.coerce(arg, param)
+                }
+                None => self.expr(),
+            };
+            args.push(arg);
+            self.consume(&[TokenKind::Comma]);
+        }
+
+        if args.len() != params.len() {
+            panic!(
+                "this function takes {} arguments but {} were supplied",
+                params.len(),
+                args.len()
+            )
+        }
+        for (arg, param) in args.iter().zip(params.iter()) {
+            if arg.ty() != *param {
+                panic!(
+                    "mismatched types, expected {:?} but found {:?}",
+                    param,
+                    arg.ty()
+                )
+            }
+        }
+
+        Expr::Call(Call {
+            callee: Box::new(callee),
+            args,
+        })
+    }
+
+    /// an expression where a value of `expected` goes, a closure takes its parameter types
+    /// from it
+    fn expr_expecting(&mut self, expected: &Type) -> Expr {
+        match expected {
+            Type::Fn { args, .. } | Type::Closure { args, .. } if self.peek(&[TokenKind::Or]) => {
+                Expr::Closure(self.closure(args))
+            }
+            _ => self.expr(),
+        }
+    }
+
+    /// `|x, y: i64| body`, parameters without annotation take the types `expected`
+    fn closure(&mut self, expected: &[Type]) -> Closure {
+        // parameters are only visible inside the body, captured locals stay visible
+        let outer = self.signatures.clone();
+
+        self.expect(&[TokenKind::Or]);
+        let mut params = vec![];
+        while !self.consume(&[TokenKind::Or]) {
+            let pattern_head = self.head;
+            self.consume(&[TokenKind::Mut]);
+            let name = self.expect_ident();
+            let ty = if self.consume(&[TokenKind::Colon]) {
+                self.ty()
+            } else {
+                match expected.get(params.len()) {
+                    Some(ty) if *ty != Type::Unknown => ty.clone(),
+                    _ => panic!("type annotations needed for closure parameter {}", name),
+                }
+            };
+            self.consume(&[TokenKind::Comma]);
+
+            let rest = self.head;
+            self.head = pattern_head;
+            let pattern = self.pattern(&ty);
+            self.head = rest;
+
+            params.push(Param { pattern, ty });
+        }
+        let ret = self
+            .consume(&[TokenKind::Minus, TokenKind::Gt])
+            .then(|| self.ty());
+
+        let outer_ret = std::mem::replace(&mut self.ret, ret.clone().unwrap_or(Type::Unknown));
+        let body = self.expr();
+        self.ret = outer_ret;
+        if let Some(ret) = &ret {
+            if body.ty() != *ret && body.ty() != Type::Never {
+                panic!(
+                    "mismatched types, expected {:?} but found {:?}",
+                    ret,
+                    body.ty()
+                )
+            }
+        }
+        self.signatures = outer;
+
+        Closure {
+            ty: Type::Closure {
+                args: params.iter().map(|param| param.ty.clone()).collect(),
+                ret: Box::new(ret.unwrap_or_else(|| body.ty())),
+            },
+            params,
+            body: Box::new(body),
+            name: String::new(),
+            captures: vec![],
+        }
+    }
+
     fn ifelse(&mut self) -> IfElse {
         self.expect(&[TokenKind::If]);
         IfElse {
@@ -294,58 +1925,747 @@ impl<'ctx> SofaParser<'ctx> {
         }
     }
 
-    fn fn_call(&mut self) -> FnCall {
+    fn match_expr(&mut self) -> Match {
+        self.expect(&[TokenKind::Match]);
+        let scrutinee = self.expr();
+        let ty = scrutinee.ty();
+
+        self.expect(&[TokenKind::LBrace]);
+        let mut arms = vec![];
+        while !self.consume(&[TokenKind::RBrace]) {
+            // bindings are only visible inside their arm
+            let outer = self.signatures.clone();
+            let pattern = self.pattern(&ty);
+            self.expect(&[TokenKind::Eq, TokenKind::Gt]);
+            let body = self.expr();
+            self.consume(&[TokenKind::Comma]);
+            self.signatures = outer;
+
+            arms.push(Arm { pattern, body });
+        }
+
+        let mut tys = arms
+            .iter()
+            .map(|arm| arm.body.ty())
+            .filter(|ty| *ty != Type::Never);
+        if let Some(expected) = tys.next() {
+            for found in tys {
+                if found != expected {
+                    panic!(
+                        "match arms have incompatible types, expected {:?} but found {:?}",
+                        expected, found
+                    )
+                }
+            }
+        }
+
+        Match {
+            scrutinee: Box::new(scrutinee),
+            arms,
+        }
+    }
+
+    /// parses a pattern matching values of `ty`, and declares its bindings
+    fn pattern(&mut self, ty: &Type) -> Pattern {
+        self.qualifier();
+        if self.peek(&[TokenKind::Number])
+            || self.peek(&[TokenKind::Minus])
+            || self.peek_int_bound()
+        {
+            if *ty != Type::I64 {
+                panic!("expected pattern of {:?}, found integer", ty)
+            }
+            let lo = self.pattern_int();
+            if self.consume(&[TokenKind::Dot, TokenKind::Dot, TokenKind::Eq]) {
+                let hi = self.pattern_int();
+                if lo > hi {
+                    panic!(
+                        "lower range bound {} must not exceed upper bound {}",
+                        lo, hi
+                    )
+                }
+                Pattern::Range { lo, hi }
+            } else if self.consume(&[TokenKind::Dot, TokenKind::Dot]) {
+                let hi = self.pattern_int();
+                if lo >= hi {
+                    panic!(
+                        "lower range bound {} must be less than upper bound {}",
+                        lo, hi
+                    )
+                }
+                Pattern::Range { lo, hi: hi - 1 }
+            } else {
+                Pattern::Int(lo)
+            }
+        } else if self.consume(&[TokenKind::LParen]) {
+            let elements = match ty {
+                Type::Tuple { elements } => elements.clone(),
+                Type::Unit => vec![],
+                _ => panic!("expected pattern of {:?}, found tuple", ty),
+            };
+            let mut fields = vec![];
+            while !self.consume(&[TokenKind::RParen]) {
+                let ty = elements
+                    .get(fields.len())
+                    .unwrap_or_else(|| panic!("too many elements for {:?}", ty));
+                fields.push(self.pattern(ty));
+                self.consume(&[TokenKind::Comma]);
+            }
+            if fields.len() != elements.len() {
+                panic!("expected {} elements for {:?}", elements.len(), ty)
+            }
+            Pattern::Tuple(fields)
+        } else if self.consume(&[TokenKind::And]) {
+            let mutable = self.consume(&[TokenKind::Mut]);
+            match ty {
+                Type::Ptr { to, mutable: m } if *m == mutable => {
+                    Pattern::Ref(Box::new(self.pattern(to)))
+                }
+                _ => panic!("expected pattern of {:?}, found reference", ty),
+            }
+        } else if self.peek_struct() {
+            let name = self.expect_ident();
+            let struct_ty = self.lookup_type(&name).unwrap();
+            if struct_ty != *ty {
+                panic!("expected pattern of {:?}, found {:?}", ty, struct_ty)
+            }
+            let (path, fields) = match &struct_ty {
+                Type::Struct { name, fields } => (name.clone(), fields.clone()),
+                _ => unreachable!(),
+            };
+
+            Pattern::Struct(self.field_patterns(&path, &fields))
+        } else if self.peek(&[TokenKind::Ident, TokenKind::Colon, TokenKind::Colon]) {
+            let (enum_ty, index) = self.enum_variant();
+            if enum_ty != *ty {
+                panic!("expected pattern of {:?}, found {:?}", ty, enum_ty)
+            }
+            let def = match &enum_ty {
+                Type::Enum { variants, .. } => variants[index].clone(),
+                _ => unreachable!(),
+            };
+
+            let fields = match def.kind {
+                FieldsKind::Unit => vec![],
+                FieldsKind::Tuple | FieldsKind::Named => {
+                    self.field_patterns(&def.name, &def.fields)
+                }
+            };
+
+            Pattern::Variant {
+                ty: enum_ty,
+                index,
+                fields,
+            }
+        } else if self.peek(&[TokenKind::Ident]) || self.peek(&[TokenKind::Mut]) {
+            let mutable = self.consume(&[TokenKind::Mut]);
+            let name = self.expect_ident();
+            if name == "_" && !mutable {
+                Pattern::Wildcard
+            } else {
+                self.signatures.insert(name.clone(), ty.clone());
+                Pattern::Binding {
+                    local: Local {
+                        name,
+                        ty: ty.clone(),
+                    },
+                    mutable,
+                }
+            }
+        } else if self.peek(&[TokenKind::True]) || self.peek(&[TokenKind::False]) {
+            if *ty != Type::Bool {
+                panic!("expected pattern of {:?}, found boolean", ty)
+            }
+            if self.consume(&[TokenKind::True]) {
+                Pattern::Bool(Bool::True)
+            } else {
+                self.expect(&[TokenKind::False]);
+                Pattern::Bool(Bool::False)
+            }
+        } else {
+            panic!("expected pattern, found {:?}", self.get())
+        }
+    }
+
+    /// `(a, b)` or `{ x, y: pattern, .. }` of a struct or variant, in declaration order
+    fn field_patterns(&mut self, owner: &str, defs: &[Field]) -> Vec<Pattern> {
+        if !self.consume(&[TokenKind::LParen]) {
+            self.expect(&[TokenKind::LBrace]);
+            return self.named_field_patterns(owner, defs);
+        }
+        if !is_tuple(defs) {
+            panic!("expected tuple struct or tuple variant, found {}", owner)
+        }
+
+        let mut fields = vec![];
+        while !self.consume(&[TokenKind::RParen]) {
+            let field = defs
+                .get(fields.len())
+                .unwrap_or_else(|| panic!("too many fields for {}", owner));
+            self.check_field(owner, fields.len());
+            fields.push(self.pattern(&field.ty));
+            self.consume(&[TokenKind::Comma]);
+        }
+        if fields.len() != defs.len() {
+            panic!("expected {} fields for {}", defs.len(), owner)
+        }
+        fields
+    }
+
+    /// `{ x, y: pattern, .. }` after the opening brace, in declaration order
+    fn named_field_patterns(&mut self, owner: &str, defs: &[Field]) -> Vec<Pattern> {
+        let mut fields = vec![None; defs.len()];
+        let mut rest = false;
+        while !self.consume(&[TokenKind::RBrace]) {
+            if self.consume(&[TokenKind::Dot, TokenKind::Dot]) {
+                rest = true;
+                continue;
+            }
+
+            let name = self.expect_ident();
+            let i = defs
+                .iter()
+                .position(|f| f.name == name)
+                .unwrap_or_else(|| panic!("{} has no field {}", owner, name));
+            self.check_field(owner, i);
+            let ty = &defs[i].ty;
+            fields[i] = Some(if self.consume(&[TokenKind::Colon]) {
+                self.pattern(ty)
+            } else {
+                // shorthand `Rect { w, h }`
+                self.signatures.insert(name.clone(), ty.clone());
+                Pattern::Binding {
+                    local: Local {
+                        name,
+                        ty: ty.clone(),
+                    },
+                    mutable: false,
+                }
+            });
+            self.consume(&[TokenKind::Comma]);
+        }
+
+        fields
+            .into_iter()
+            .zip(defs.iter())
+            .map(|(pattern, field)| match pattern {
+                Some(pattern) => pattern,
+                None if rest => Pattern::Wildcard,
+                None => panic!("pattern does not mention field {}", field.name),
+            })
+            .collect()
+    }
+
+    /// `i64::MIN` or `i64::MAX`
+    fn peek_int_bound(&mut self) -> bool {
+        self.peek(&[TokenKind::Ident, TokenKind::Colon, TokenKind::Colon])
+            && self.get().value.as_deref() == Some("i64")
+    }
+
+    /// integer literal in a pattern, like `-1` or `i64::MAX`
+    fn pattern_int(&mut self) -> i64 {
+        if self.peek_int_bound() {
+            self.expect_ident();
+            self.expect(&[TokenKind::Colon, TokenKind::Colon]);
+            match self.expect_ident().as_str() {
+                "MIN" => i64::MIN,
+                "MAX" => i64::MAX,
+                id => panic!("found unknown constant i64::{}", id),
+            }
+        } else {
+            let neg = self.consume(&[TokenKind::Minus]);
+            let value = self.expect_number();
+            if neg {
+                format!("-{}", value).parse().unwrap()
+            } else {
+                value.parse().unwrap()
+            }
+        }
+    }
+
+    /// resolves a path `Enum::Variant` into the enum type and the variant index
+    fn enum_variant(&mut self) -> (Type, usize) {
+        let enum_name = self.expect_ident();
+        self.expect(&[TokenKind::Colon, TokenKind::Colon]);
+        let variant_name = self.expect_ident();
+
+        let ty = self
+            .lookup_type(&enum_name)
+            .unwrap_or_else(|| panic!("found unknown type {}", enum_name));
+        let index = match &ty {
+            Type::Enum { variants, .. } => variants
+                .iter()
+                .position(|v| v.name == variant_name)
+                .unwrap_or_else(|| panic!("{} has no variant {}", enum_name, variant_name)),
+            _ => panic!("{} is not an enum", enum_name),
+        };
+
+        (ty, index)
+    }
+
+    fn variant(&mut self) -> Variant {
+        let (ty, index) = self.enum_variant();
+        let def = match &ty {
+            Type::Enum { variants, .. } => variants[index].clone(),
+            _ => unreachable!(),
+        };
+
+        let fields = match def.kind {
+            FieldsKind::Unit => vec![],
+            FieldsKind::Tuple | FieldsKind::Named => self.field_exprs(&def.name, &def.fields),
+        };
+        self.check_fields(&def.name, &def.fields, &fields);
+
+        Variant { ty, index, fields }
+    }
+
+    fn struct_lit(&mut self) -> Struct {
+        let name = self.expect_ident();
+        let ty = self.lookup_type(&name).unwrap();
+        let (path, defs) = match &ty {
+            Type::Struct { name, fields } => (name.clone(), fields.clone()),
+            _ => unreachable!(),
+        };
+
+        let fields = self.field_exprs(&path, &defs);
+        self.check_fields(&path, &defs, &fields);
+
+        Struct { ty, fields }
+    }
+
+    /// `(a, b)` or `{ x: expr, y }` of a struct or variant, in declaration order
+    fn field_exprs(&mut self, owner: &str, defs: &[Field]) -> Vec<Expr> {
+        if !self.consume(&[TokenKind::LParen]) {
+            self.expect(&[TokenKind::LBrace]);
+            return self.named_field_exprs(owner, defs);
+        }
+        if !is_tuple(defs) {
+            panic!("expected tuple struct or tuple variant, found {}", owner)
+        }
+
+        let mut fields = vec![];
+        while !self.consume(&[TokenKind::RParen]) {
+            let value = self.expr();
+            fields.push(match defs.get(fields.len()) {
+                Some(field) => {
+                    self.check_field(owner, fields.len());
+                    self.coerce(value, &field.ty)
+                }
+                None => value,
+            });
+            self.consume(&[TokenKind::Comma]);
+        }
+        fields
+    }
+
+    /// `{ x: expr, y }` after the opening brace, in declaration order
+    fn named_field_exprs(&mut self, owner: &str, defs: &[Field]) -> Vec<Expr> {
+        let mut fields: Vec<Option<Expr>> =
+            std::iter::repeat_with(|| None).take(defs.len()).collect();
+        while !self.consume(&[TokenKind::RBrace]) {
+            let name = self.expect_ident();
+            let i = defs
+                .iter()
+                .position(|f| f.name == name)
+                .unwrap_or_else(|| panic!("{} has no field {}", owner, name));
+            self.check_field(owner, i);
+            fields[i] = Some(if self.consume(&[TokenKind::Colon]) {
+                let value = self.expr();
+                self.coerce(value, &defs[i].ty)
+            } else {
+                // shorthand `Point { x, y }`
+                let ty = self
+                    .signatures
+                    .get(&name)
+                    .unwrap_or_else(|| panic!("found undefined local {}", name))
+                    .clone();
+                Expr::Local(Local { name, ty })
+            });
+            self.consume(&[TokenKind::Comma]);
+        }
+
+        fields
+            .into_iter()
+            .zip(defs.iter())
+            .map(|(expr, field)| {
+                expr.unwrap_or_else(|| panic!("missing field {} in {}", field.name, owner))
+            })
+            .collect()
+    }
+
+    fn check_fields(&self, owner: &str, defs: &[Field], fields: &[Expr]) {
+        if fields.len() != defs.len() {
+            panic!("expected {} fields for {}", defs.len(), owner)
+        }
+        for (expr, field) in fields.iter().zip(defs.iter()) {
+            if expr.ty() != field.ty {
+                panic!(
+                    "field {} of {} expects {:?}, found {:?}",
+                    field.name,
+                    owner,
+                    field.ty,
+                    expr.ty()
+                )
+            }
+        }
+    }
+
+    /// `panic("message")` or `assert(cond)`, starting at `pos`
+    fn builtin(&mut self, pos: usize) -> Expr {
         let name = self.expect_ident();
         self.expect(&[TokenKind::LParen]);
+        let res = if name == "panic" {
+            Expr::Panic(Panic {
+                message: self.expect_str(),
+                pos,
+            })
+        } else {
+            let cond = self.expr();
+            if cond.ty() != Type::Bool {
+                panic!("mismatched types, expected Bool but found {:?}", cond.ty())
+            }
+            let message = self.consume(&[TokenKind::Comma]).then(|| self.expect_str());
+            Expr::Assert(Assert {
+                cond: Box::new(cond),
+                message,
+                pos,
+            })
+        };
+        self.expect(&[TokenKind::RParen]);
+        res
+    }
 
-        let mut args = vec![];
+    /// `Box::new(value)`
+    fn box_new(&mut self) -> BoxNew {
+        self.expect_ident();
+        self.expect(&[TokenKind::Colon, TokenKind::Colon]);
+        let name = self.expect_ident();
+        if name != "new" {
+            panic!("Box has no function {}", name)
+        }
+        self.expect(&[TokenKind::LParen]);
+        let value = self.expr();
+        self.expect(&[TokenKind::RParen]);
+
+        BoxNew {
+            value: Box::new(value),
+        }
+    }
+
+    /// `Vec::new()` or `Vec::<T>::new()`, the element type is inferred from its use otherwise
+    fn vec_new(&mut self) -> VecNew {
+        self.expect_ident();
+        self.expect(&[TokenKind::Colon, TokenKind::Colon]);
+        let element = if self.consume(&[TokenKind::Lt]) {
+            let element = self.ty();
+            self.expect(&[TokenKind::Gt]);
+            self.expect(&[TokenKind::Colon, TokenKind::Colon]);
+            element
+        } else {
+            Type::Unknown
+        };
+        let name = self.expect_ident();
+        if name != "new" {
+            panic!("Vec has no function {}", name)
+        }
+        self.expect(&[TokenKind::LParen]);
+        self.expect(&[TokenKind::RParen]);
+
+        VecNew {
+            ty: Type::Vec {
+                element: Box::new(element),
+                len: None,
+            },
+        }
+    }
+
+    /// `f(a, b)`, or `f::<T>(a, b)` naming the instantiation of a generic `f`
+    fn fn_call(&mut self) -> FnCall {
+        let name = self.expect_ident();
+        let (symbol, fn_type) = match self.resolve(&self.functions, &name) {
+            Some(path) => (self.fn_symbol(&path), self.functions[&path].clone()),
+            None => match &self.qualifier {
+                Some(module) => panic!(
+                    "cannot find function `{}` in module `{}`",
+                    name,
+                    display_module(module)
+                ),
+                // left to the linker
+                None => (name, Type::Unknown),
+            },
+        };
+        let generics = self.generics.get(&symbol).cloned().unwrap_or_default();
+        self.call(symbol, fn_type, generics, vec![], HashMap::new())
+    }
+
+    /// `Rect::new(1, 2)` or `T::new()`, an associated function of a type
+    fn assoc_call(&mut self) -> FnCall {
+        let ty = self.ty();
+        self.expect(&[TokenKind::Colon, TokenKind::Colon]);
+        let name = self.expect_ident();
+        let (method, generics) = self
+            .method(&ty, &name)
+            .unwrap_or_else(|| panic!("no function named `{}` found for {:?}", name, ty));
+        self.check_method(&method);
+
+        // `Self` of a trait method is the type it is called on
+        let map = HashMap::from([("Self".to_string(), ty)]);
+        self.call(method.symbol, method.fn_type, generics, vec![], map)
+    }
+
+    /// whether a call to an associated function follows
+    fn peek_assoc_call(&mut self) -> bool {
+        if !self.peek(&[
+            TokenKind::Ident,
+            TokenKind::Colon,
+            TokenKind::Colon,
+            TokenKind::Ident,
+            TokenKind::LParen,
+        ]) {
+            return false;
+        }
+        let ty = self.tokens[self.head].value.as_deref().unwrap();
+        let name = self.tokens[self.head + 3].value.as_deref().unwrap();
+        self.lookup_type(ty)
+            .is_some_and(|ty| self.method(&ty, name).is_some())
+    }
+
+    /// the rest of a call to `name`, after the `args` already known like the receiver of a method,
+    /// `map` holds the type parameters known beforehand
+    fn call(
+        &mut self,
+        name: String,
+        fn_type: Type,
+        generics: Generics,
+        mut args: Vec<Expr>,
+        mut map: HashMap<String, Type>,
+    ) -> FnCall {
+        // instantiation of the type parameters, inferred from the arguments unless named
+        if self.consume(&[TokenKind::Colon, TokenKind::Colon, TokenKind::Lt]) {
+            let params: Vec<_> = generics
+                .iter()
+                .filter(|param| !map.contains_key(&param.name))
+                .collect();
+            let mut type_args = vec![];
+            while !self.consume(&[TokenKind::Gt]) {
+                type_args.push(match params.get(type_args.len()) {
+                    Some(param) if param.is_const => Type::Const {
+                        value: self.const_arg(),
+                    },
+                    _ => self.ty(),
+                });
+                self.consume(&[TokenKind::Comma]);
+            }
+            let params: Vec<_> = params.into_iter().map(|param| param.name.clone()).collect();
+            if type_args.len() != params.len() {
+                panic!(
+                    "{} takes {} type arguments but {} were supplied",
+                    name,
+                    params.len(),
+                    type_args.len()
+                )
+            }
+            map.extend(params.into_iter().zip(type_args));
+        }
+
+        let params = match &fn_type {
+            Type::Fn { args, .. } => args.clone(),
+            _ => vec![],
+        };
+        for (arg, param) in args.iter().zip(params.iter()) {
+            param.unify(&arg.ty(), &mut map);
+        }
+        self.expect(&[TokenKind::LParen]);
         while !self.consume(&[TokenKind::RParen]) {
-            args.push(self.expr());
+            let mut arg = match params.get(args.len()) {
+                Some(param) => {
+                    // parameters not inferred yet cannot type a closure
+                    let unbound: Vec<_> = generics
+                        .iter()
+                        .filter(|param| !map.contains_key(&param.name))
+                        .collect();
+                    let expected = match param.subst(&map) {
+                        ty if unbound.iter().any(|param| ty.mentions(&param.name)) => Type::Unknown,
+                        ty => ty,
+                    };
+                    self.expr_expecting(&expected)
+                }
+                None => self.expr(),
+            };
+            if let Some(param) = params.get(args.len()) {
+                param.unify(&arg.ty(), &mut map);
+                infer(&mut arg, &param.subst(&map));
+                arg = self.coerce(arg, &param.subst(&map));
+                check_type(&param.subst(&map), &arg.ty());
+            }
+            args.push(arg);
             self.consume(&[TokenKind::Comma]);
         }
 
+        let mut type_args = vec![];
+        for Generic {
+            name: param,
+            bounds,
+            ..
+        } in generics.iter()
+        {
+            let ty = map.get(param).cloned().unwrap_or_else(|| {
+                panic!(
+                    "type annotations needed for {} in the call to {}",
+                    param, name
+                )
+            });
+            for bound in bounds.iter() {
+                if !self.implements(&ty, bound) {
+                    panic!("the trait bound `{:?}: {}` is not satisfied", ty, bound)
+                }
+            }
+            type_args.push(ty);
+        }
+
         FnCall {
-            fn_type: self.signatures.get(&name).unwrap_or(&Type::Unknown).clone(),
+            fn_type: fn_type.subst(&map),
             name,
             args,
+            type_args,
+            dispatch: None,
         }
     }
 
     fn init(&mut self) -> Init {
         self.expect(&[TokenKind::Let]);
-        let name = self.expect_ident();
 
-        let mut ty = if self.consume(&[TokenKind::Colon]) {
-            self.ty()
-        } else {
-            Type::Unknown
-        };
+        // the pattern can only be typed once the value is known
+        let pattern_head = self.head;
+        self.skip_pattern();
+
+        let annotation = self.consume(&[TokenKind::Colon]).then(|| self.ty());
 
         let value = if self.consume(&[TokenKind::Eq]) {
-            let expr = self.expr();
-            ty = expr.ty();
-            Some(Box::new(expr))
+            let mut value = self.expr_expecting(annotation.as_ref().unwrap_or(&Type::Unknown));
+            if let Some(ty) = &annotation {
+                infer(&mut value, ty);
+                value = self.coerce(value, ty);
+            }
+            Some(Box::new(value))
         } else {
             None
         };
 
-        self.signatures.insert(name.clone(), ty.clone());
+        if let (Some(ty), Some(value)) = (&annotation, &value) {
+            check_len(ty, &value.ty());
+        }
+        let ty = match (annotation, &value) {
+            (Some(ty), Some(value)) if ty != value.ty() => {
+                panic!(
+                    "mismatched types, expected {:?} but found {:?}",
+                    ty,
+                    value.ty()
+                )
+            }
+            (Some(ty), _) => ty,
+            (None, Some(value)) => value.ty(),
+            (None, None) => Type::Unknown,
+        };
 
-        Init {
-            name: Box::new(Expr::Local(Local { name, ty })),
-            value,
+        let rest = self.head;
+        self.head = pattern_head;
+        let pattern = self.pattern(&ty);
+        self.head = rest;
+
+        if value.is_none() && !matches!(pattern, Pattern::Binding { .. }) {
+            panic!("destructuring {:?} needs a value", pattern)
+        }
+        if value.is_none() && ty.needs_drop() {
+            // its parts could not be dropped before the whole is assigned
+            panic!("{:?} needs drop, declare it with a value", pattern)
+        }
+
+        Init { pattern, value }
+    }
+
+    /// skips a pattern up to its type annotation or value
+    fn skip_pattern(&mut self) {
+        let mut depth = 0;
+        loop {
+            if self.consume(&[TokenKind::Colon, TokenKind::Colon]) {
+                continue;
+            }
+            if depth == 0
+                && (self.peek(&[TokenKind::Colon])
+                    || self.peek(&[TokenKind::Eq])
+                    || self.peek(&[TokenKind::Semi]))
+            {
+                break;
+            }
+
+            match self.get().kind {
+                TokenKind::LParen | TokenKind::LBrace | TokenKind::LBlanket => depth += 1,
+                TokenKind::RParen | TokenKind::RBrace | TokenKind::RBlanket => depth -= 1,
+                _ => {}
+            }
+            self.head += 1;
         }
     }
 
     fn ty(&mut self) -> Type {
-        if self.consume(&[TokenKind::And]) {
-            Type::Ptr {
-                to: Box::new(self.ty()),
+        self.qualifier();
+        if self.consume(&[TokenKind::LParen]) {
+            let mut elements = vec![];
+            let mut trailing_comma = false;
+            while !self.consume(&[TokenKind::RParen]) {
+                elements.push(self.ty());
+                trailing_comma = self.consume(&[TokenKind::Comma]);
+                if !trailing_comma {
+                    self.expect(&[TokenKind::RParen]);
+                    break;
+                }
             }
+
+            match elements.len() {
+                0 => Type::Unit,
+                1 if !trailing_comma => elements.pop().unwrap(),
+                _ => Type::Tuple { elements },
+            }
+        } else if self.consume(&[TokenKind::And]) {
+            let mutable = self.consume(&[TokenKind::Mut]);
+            match self.ty() {
+                // `[T]` alone is no type, it is the unsized part of the slice
+                Type::Slice { element, .. } => Type::Slice { element, mutable },
+                Type::Dyn { name, .. } => Type::Dyn {
+                    name,
+                    mutable,
+                    boxed: false,
+                },
+                to => Type::Ptr {
+                    to: Box::new(to),
+                    mutable,
+                },
+            }
+        } else if self.consume(&[TokenKind::Fn]) {
+            let (args, ret) = self.fn_ty();
+            Type::Fn { args, ret }
+        } else if self.consume(&[TokenKind::Impl]) {
+            // closures are told apart by their signature alone, so `FnMut` is the same
+            let name = self.expect_ident();
+            if name != "Fn" && name != "FnMut" {
+                panic!("expected `Fn` or `FnMut` after `impl`, found {}", name)
+            }
+            let (args, ret) = self.fn_ty();
+            Type::Closure { args, ret }
         } else if self.consume(&[TokenKind::LBlanket]) {
             let ty = self.ty();
+            if self.consume(&[TokenKind::RBlanket]) {
+                return Type::Slice {
+                    element: Box::new(ty),
+                    mutable: false,
+                };
+            }
             self.expect(&[TokenKind::Semi]);
-            let len = self.expect_number().parse().unwrap();
+            let len = self.const_arg();
+            if len.eval().is_some_and(|v| v < 0) {
+                panic!("array length must be a non-negative i64 constant")
+            }
             self.expect(&[TokenKind::RBlanket]);
 
             Type::Array {
@@ -356,10 +2676,220 @@ impl<'ctx> SofaParser<'ctx> {
             let id = self.expect_ident();
             match id.as_str() {
                 "i64" => Type::I64,
-                "void" => Type::Void,
+                "bool" => Type::Bool,
+                "u8" => Type::U8,
                 "never" => Type::Never,
-                _ => panic!("found unknown type {}", id),
+                "dyn" => self.dyn_ty(),
+                "Box" => {
+                    self.expect(&[TokenKind::Lt]);
+                    let to = self.ty();
+                    self.expect(&[TokenKind::Gt]);
+                    match to {
+                        Type::Dyn { name, .. } => Type::Dyn {
+                            name,
+                            mutable: true,
+                            boxed: true,
+                        },
+                        to => Type::Box { to: Box::new(to) },
+                    }
+                }
+                "Vec" => {
+                    self.expect(&[TokenKind::Lt]);
+                    let element = self.ty();
+                    let len = self.consume(&[TokenKind::Comma]).then(|| self.const_arg());
+                    self.expect(&[TokenKind::Gt]);
+                    Type::Vec {
+                        element: Box::new(element),
+                        len,
+                    }
+                }
+                _ => {
+                    self.forward_alias(&id);
+                    match self.lookup_type(&id) {
+                        Some(Type::Const { .. }) => {
+                            panic!("expected type, found const parameter `{}`", id)
+                        }
+                        Some(ty) => ty,
+                        None => panic!("found unknown type {}", id),
+                    }
+                }
+            }
+        }
+    }
+
+    /// `(i64, bool) -> i64` of a function type, unit when the return is omitted
+    fn fn_ty(&mut self) -> (Vec<Type>, Box<Type>) {
+        self.expect(&[TokenKind::LParen]);
+        let mut args = vec![];
+        while !self.consume(&[TokenKind::RParen]) {
+            args.push(self.ty());
+            self.consume(&[TokenKind::Comma]);
+        }
+        (args, Box::new(self.ret()))
+    }
+
+    /// `Trait` after `dyn`, every method has to be callable through the vtable
+    fn dyn_ty(&mut self) -> Type {
+        self.qualifier();
+        let name = self.expect_ident();
+        let path = self
+            .resolve(&self.traits, &name)
+            .unwrap_or_else(|| panic!("cannot find trait `{}`", name));
+        let methods = &self.traits[&path];
+        let self_ty = Type::Param {
+            name: "Self".to_string(),
+        };
+        for m in methods.iter() {
+            let callable = match &m.fn_type {
+                Type::Fn { args, ret } => {
+                    matches!(args.first(), Some(Type::Ptr { to, .. }) if **to == self_ty)
+                        && !args[1..].iter().any(|ty| ty.mentions("Self"))
+                        && !ret.mentions("Self")
+                }
+                _ => false,
+            };
+            if !callable {
+                panic!(
+                    "the trait `{}` is not dyn compatible because of method `{}`",
+                    name, m.name
+                )
+            }
+        }
+
+        Type::Dyn {
+            name: path,
+            mutable: false,
+            boxed: false,
+        }
+    }
+
+    /// `value` where a value of `expected` goes, `&T` and `Box<T>` become a `dyn Trait`
+    /// implemented by `T`
+    fn coerce(&self, value: Expr, expected: &Type) -> Expr {
+        let (name, to) = match (expected, value.ty()) {
+            (
+                Type::Dyn {
+                    name,
+                    mutable,
+                    boxed: false,
+                },
+                Type::Ptr { to, mutable: m },
+            ) if m || !mutable => (name, to),
+            (
+                Type::Dyn {
+                    name, boxed: true, ..
+                },
+                Type::Box { to },
+            ) => (name, to),
+            _ => return value,
+        };
+        if !self.implements(&to, name) {
+            panic!("the trait bound `{:?}: {}` is not satisfied", to, name)
+        }
+
+        Expr::ToDyn(ToDyn {
+            value: Box::new(value),
+            ty: expected.clone(),
+            methods: self.traits[name].iter().map(|m| m.symbol.clone()).collect(),
+        })
+    }
+
+    /// a local, or a global item it does not shadow
+    fn path(&mut self) -> Expr {
+        let name = self.get().value.clone().unwrap();
+        if self.qualifier.is_none() && self.signatures.contains_key(&name) {
+            Expr::Local(self.local())
+        } else if let (None, Some(Type::Const { value })) = (&self.qualifier, self.types.get(&name))
+        {
+            let value = value.clone();
+            self.head += 1;
+            Expr::ConstExpr(ConstExpr { value })
+        } else if let Some(path) = self.resolve(&self.consts, &name) {
+            self.head += 1;
+            match self.consts[&path] {
+                (Type::Bool, 0) => Expr::Bool(Bool::False),
+                (Type::Bool, _) => Expr::Bool(Bool::True),
+                (_, value) => Expr::Number(Number {
+                    value: value.to_string(),
+                }),
             }
+        } else if let Some(path) = self.resolve(&self.statics, &name) {
+            self.head += 1;
+            Expr::Static(Static {
+                name: symbol(&path),
+                ty: self.statics[&path].clone(),
+            })
+        } else if let Some(path) = self.resolve(&self.functions, &name) {
+            self.head += 1;
+            let symbol = self.fn_symbol(&path);
+            if self.generics.contains_key(&symbol) {
+                panic!("type annotations needed for the generic function {}", name)
+            }
+            Expr::FnRef(FnRef {
+                name: symbol,
+                ty: self.functions[&path].clone(),
+            })
+        } else if let Some(module) = &self.qualifier {
+            panic!(
+                "cannot find `{}` in module `{}`",
+                name,
+                display_module(module)
+            )
+        } else {
+            panic!("found undefined local {}", name)
+        }
+    }
+
+    /// `3`, `N`, `2 * N + 1` or `{ N + 1 }` given for a const parameter or length,
+    /// unlike in expressions `*` binds tighter than `+` and `-`, which group from the left
+    fn const_arg(&mut self) -> Const {
+        let mut value = self.const_term();
+        loop {
+            if self.consume(&[TokenKind::Plus]) {
+                value = value + self.const_term();
+            } else if self.consume(&[TokenKind::Minus]) {
+                value = value - self.const_term();
+            } else {
+                return value;
+            }
+        }
+    }
+
+    /// a product like `2 * N` in a const argument
+    fn const_term(&mut self) -> Const {
+        let mut value = self.const_factor();
+        while self.consume(&[TokenKind::Star]) {
+            value = value * self.const_factor();
+        }
+        value
+    }
+
+    /// a number, a `const` item, a const parameter or a const argument in parentheses
+    /// or braces
+    fn const_factor(&mut self) -> Const {
+        if self.consume(&[TokenKind::Minus]) {
+            return -self.const_factor();
+        }
+        for (open, close) in [
+            (TokenKind::LParen, TokenKind::RParen),
+            (TokenKind::LBrace, TokenKind::RBrace),
+        ] {
+            if self.consume(&[open]) {
+                let value = self.const_arg();
+                self.expect(&[close]);
+                return value;
+            }
+        }
+
+        let value = if self.peek(&[TokenKind::Ident]) {
+            self.qualifier();
+            self.path()
+        } else {
+            Expr::Number(self.number())
+        };
+        match value.eval_const() {
+            Some(c) if value.ty() == Type::I64 => c,
+            _ => panic!("array lengths and const arguments must be i64 constants"),
         }
     }
 
@@ -375,3 +2905,263 @@ impl<'ctx> SofaParser<'ctx> {
         }
     }
 }
+
+/// `receiver` behind `derefs` references or boxes, adjusted to the `self` argument
+fn autoref(receiver: Expr, derefs: usize, self_arg: &Type) -> Expr {
+    let mut expr = receiver;
+    for _ in 0..derefs {
+        expr = Expr::UnOp(UnOp {
+            kind: UnOpKind::Deref,
+            expr: Box::new(expr),
+        });
+    }
+    match self_arg {
+        Type::Ptr { mutable, .. } => Expr::UnOp(UnOp {
+            kind: if *mutable {
+                UnOpKind::RefMut
+            } else {
+                UnOpKind::Ref
+            },
+            expr: Box::new(expr),
+        }),
+        _ => expr,
+    }
+}
+
+/// path of `name` in the module at `module`
+fn qualify(module: &str, name: &str) -> String {
+    if module.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}::{}", module, name)
+    }
+}
+
+/// module of the item at `path`
+fn parent(path: &str) -> &str {
+    path.rsplit_once("::").map_or("", |(module, _)| module)
+}
+
+/// name of the item at `path`
+fn last(path: &str) -> &str {
+    path.rsplit("::").next().unwrap()
+}
+
+/// whether `module` is `ancestor` or inside it
+fn within(module: &str, ancestor: &str) -> bool {
+    ancestor.is_empty()
+        || module == ancestor
+        || module
+            .strip_prefix(ancestor)
+            .is_some_and(|rest| rest.starts_with("::"))
+}
+
+/// `crate` for the root, the path otherwise
+fn display_module(module: &str) -> &str {
+    if module.is_empty() {
+        "crate"
+    } else {
+        module
+    }
+}
+
+/// a `Vec<T, m>` given for a `Vec<T, n>` needs `n` and `m` to be the same once normalised
+fn check_len(expected: &Type, found: &Type) {
+    if let (
+        Type::Vec {
+            len: Some(expected),
+            ..
+        },
+        Type::Vec {
+            len: Some(found), ..
+        },
+    ) = (expected, found)
+    {
+        if expected != found {
+            panic!(
+                "mismatched types, expected length {:?} but found {:?}",
+                expected, found
+            )
+        }
+    }
+}
+
+/// a value of `found` given where a value of `expected` goes, a newtype is not the type it wraps
+fn check_type(expected: &Type, found: &Type) {
+    // a mutable reference is also a shared one
+    let found = match (expected, found) {
+        (Type::Ptr { mutable: false, .. }, Type::Ptr { to, .. }) => Type::Ptr {
+            to: to.clone(),
+            mutable: false,
+        },
+        (Type::Slice { mutable: false, .. }, Type::Slice { element, .. }) => Type::Slice {
+            element: element.clone(),
+            mutable: false,
+        },
+        (Type::Dyn { mutable: false, .. }, Type::Dyn { name, boxed, .. }) => Type::Dyn {
+            name: name.clone(),
+            mutable: false,
+            boxed: *boxed,
+        },
+        (_, found) => found.clone(),
+    };
+    if *expected != Type::Unknown
+        && !matches!(found, Type::Never | Type::Unknown)
+        && *expected != found
+    {
+        panic!(
+            "mismatched types, expected {:?} but found {:?}",
+            expected, found
+        )
+    }
+}
+
+/// whether `fields` belong to a tuple struct or variant, named by their index
+fn is_tuple(fields: &[Field]) -> bool {
+    fields.first().is_some_and(|f| f.name == "0")
+}
+
+/// whether the first argument is `self`, making the function a method
+fn takes_self(args: &[Param]) -> bool {
+    matches!(
+        args.first(),
+        Some(Param {
+            pattern: Pattern::Binding { local, .. },
+            ..
+        }) if local.name == "self"
+    )
+}
+
+/// gives `Vec::new()` its element type from where the value goes,
+/// and the length 0 where a `Vec<T, 0>` goes
+fn infer(value: &mut Expr, expected: &Type) {
+    if let (
+        Expr::VecNew(VecNew {
+            ty: Type::Vec { element, len },
+        }),
+        Type::Vec {
+            element: expected,
+            len: expected_len,
+        },
+    ) = (value, expected)
+    {
+        if **element == Type::Unknown {
+            *element = expected.clone();
+        }
+        if len.is_none() && *expected_len == Some(Const::number(0)) {
+            *len = expected_len.clone();
+        }
+    }
+}
+
+/// gives each `let v = Vec::new();` of a block the element type of the first push to `v`
+fn infer_vecs(exprs: &mut [Expr]) {
+    for i in 0..exprs.len() {
+        let (init, rest) = exprs[i..].split_first_mut().unwrap();
+        let Expr::Stmt(Stmt { expr: init }) = init else {
+            continue;
+        };
+        let Expr::Init(Init {
+            pattern: Pattern::Binding { local, .. },
+            value: Some(value),
+        }) = &mut **init
+        else {
+            continue;
+        };
+        if !is_untyped_vec(&local.ty) {
+            continue;
+        }
+        let ty = rest
+            .iter_mut()
+            .find_map(|e| first_push(e, &local.name))
+            .unwrap_or_else(|| panic!("type annotations needed for `Vec::new()`"));
+        infer(value, &ty);
+        local.ty = ty;
+        for e in rest.iter_mut() {
+            retype(e, local);
+        }
+    }
+}
+
+/// `Vec<_>` of a `Vec::new()` whose element type is not known yet
+fn is_untyped_vec(ty: &Type) -> bool {
+    matches!(ty, Type::Vec { element, .. } if **element == Type::Unknown)
+}
+
+/// type of the vec `name` from the first element pushed to it in `e`
+fn first_push(e: &mut Expr, name: &str) -> Option<Type> {
+    if let Expr::VecPush(VecPush { vec, value }) = e {
+        if matches!(&**vec, Expr::Local(local) if local.name == name && is_untyped_vec(&local.ty)) {
+            return Some(Type::Vec {
+                element: Box::new(value.ty()),
+                len: None,
+            });
+        }
+    }
+    e.children()
+        .into_iter()
+        .find_map(|child| first_push(child, name))
+}
+
+/// the untyped uses of `local` in `e` take its type
+fn retype(e: &mut Expr, local: &Local) {
+    match e {
+        Expr::Local(Local { name, ty }) if *name == local.name && is_untyped_vec(ty) => {
+            *ty = local.ty.clone();
+        }
+        e => {
+            for child in e.children() {
+                retype(child, local);
+            }
+        }
+    }
+}
+
+/// bits and signedness of the C integer type `name`
+fn c_int(name: &str) -> Option<(u32, bool)> {
+    match name {
+        "i8" => Some((8, true)),
+        "i16" => Some((16, true)),
+        "i32" => Some((32, true)),
+        "u8" => Some((8, false)),
+        "u16" => Some((16, false)),
+        "u32" => Some((32, false)),
+        "u64" => Some((64, false)),
+        _ => None,
+    }
+}
+
+/// `e` returned as a C integer of `bits`, sign or zero extended to i64
+fn widen(e: Expr, (bits, signed): (u32, bool), pos: usize) -> Expr {
+    let binop = |op, lhs, rhs: i64| {
+        Expr::BinOp(BinOp {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(Expr::Number(Number {
+                value: rhs.to_string(),
+            })),
+            pos,
+            wrapping: true,
+        })
+    };
+    if signed {
+        let shift = 64 - i64::from(bits);
+        binop(BinOpKind::Shr, binop(BinOpKind::Shl, e, shift), shift)
+    } else {
+        binop(BinOpKind::BitAnd, e, (1 << bits) - 1)
+    }
+}
+
+/// aggregates are passed by address between sofa functions, which C does not expect
+fn check_c_signature(name: &str, fn_type: &Type) {
+    if let Type::Fn { args, ret } = fn_type {
+        for ty in args.iter() {
+            if ty.is_aggregate() || ty.needs_drop() || *ty == Type::Unit {
+                panic!("argument of {:?} in {} cannot cross the C ABI", ty, name)
+            }
+        }
+        if ret.is_aggregate() || ret.needs_drop() {
+            panic!("return of {:?} in {} cannot cross the C ABI", ret, name)
+        }
+    }
+}
@@ -0,0 +1,284 @@
+
+# heap runtime, linked into every program
+#
+# blocks are carved from arenas mapped with mmap and start with a 16 byte header
+# holding the payload size and, while the block is free, the next free block.
+# freed blocks are reused first fit, they are never split or returned to the system.
+
+.text
+
+# rdi: size in bytes, returns the address of the payload in rax
+sofa_alloc:
+    add rdi, 15
+    and rdi, -16
+    # first fit from the free list, rdx holds the address of the link to the block
+    lea rdx, [rip+sofa_free_list]
+1:
+    mov rax, [rdx]
+    test rax, rax
+    jz 2f
+    cmp [rax], rdi
+    jae 3f
+    lea rdx, [rax+8]
+    jmp 1b
+3:
+    mov rcx, [rax+8]
+    mov [rdx], rcx
+    add rax, 16
+    ret
+2:
+    # bump from the current arena
+    mov rax, [rip+sofa_heap_cur]
+    lea rcx, [rax+rdi+16]
+    cmp rcx, [rip+sofa_heap_end]
+    ja 4f
+    mov [rip+sofa_heap_cur], rcx
+    mov [rax], rdi
+    add rax, 16
+    ret
+4:
+    # map a new arena of at least 1 MiB, the rest of the current one is abandoned
+    push rdi
+    lea rsi, [rdi+16]
+    cmp rsi, 0x100000
+    jae 5f
+    mov rsi, 0x100000
+5:
+    add rsi, 4095
+    and rsi, -4096
+    push rsi
+    mov rax, 9 # mmap
+    xor edi, edi
+    mov rdx, 3 # PROT_READ | PROT_WRITE
+    mov r10, 0x22 # MAP_PRIVATE | MAP_ANONYMOUS
+    mov r8, -1
+    xor r9d, r9d
+    syscall
+    pop rsi
+    pop rdi
+    cmp rax, -4096
+    ja 6f # out of memory
+    mov [rip+sofa_heap_cur], rax
+    add rax, rsi
+    mov [rip+sofa_heap_end], rax
+    jmp 2b
+6:
+    # out of memory, there is no location to report
+    push rdi
+    lea rdi, [rip+.Lsofa_alloc_of]
+    mov rsi, 21
+    call sofa_write_err
+    pop rdi
+    call sofa_write_i64
+    lea rdi, [rip+.Lsofa_alloc_failed]
+    mov rsi, 14
+    call sofa_write_err
+    jmp sofa_exit_panic
+
+# rdi: address of a payload from sofa_alloc, or null
+sofa_free:
+    test rdi, rdi
+    jz 1f
+    sub rdi, 16
+    mov rax, [rip+sofa_free_list]
+    mov [rdi+8], rax
+    mov [rip+sofa_free_list], rdi
+1:
+    ret
+
+# rdi: address of a vec {ptr, len, cap}, rsi: size of an element
+# at least doubles the capacity, moving the elements to a new buffer
+sofa_vec_grow:
+    push rbx
+    push r12
+    push r13
+    mov rbx, rdi
+    mov r12, rsi
+    mov r13, [rbx+16]
+    shl r13, 1
+    cmp r13, 4
+    jae 1f
+    mov r13, 4
+1:
+    mov rdi, r13
+    imul rdi, r12
+    call sofa_alloc
+    mov rdi, rax
+    mov rsi, [rbx]
+    mov rcx, [rbx+8]
+    imul rcx, r12
+    rep movsb
+    mov rdi, [rbx]
+    mov [rbx], rax
+    mov [rbx+16], r13
+    call sofa_free
+    pop r13
+    pop r12
+    pop rbx
+    ret
+
+# rdi: address of a vec {ptr, len, cap}, rsi: address of another vec, rdx: size of an element
+# moves the elements of the other vec after those of the first, and frees its buffer
+sofa_vec_append:
+    push rbx
+    push r12
+    push r13
+    mov rbx, rdi
+    mov r12, rsi
+    mov r13, rdx
+1:
+    mov rax, [rbx+8]
+    add rax, [r12+8]
+    cmp rax, [rbx+16]
+    jbe 2f
+    mov rdi, rbx
+    mov rsi, r13
+    call sofa_vec_grow
+    jmp 1b
+2:
+    mov rdi, [rbx+8]
+    imul rdi, r13
+    add rdi, [rbx]
+    mov rsi, [r12]
+    mov rcx, [r12+8]
+    imul rcx, r13
+    rep movsb
+    mov rax, [r12+8]
+    add [rbx+8], rax
+    mov rdi, [r12]
+    call sofa_free
+    pop r13
+    pop r12
+    pop rbx
+    ret
+
+# rdi: address, rsi: length in bytes, written to stderr
+sofa_write_err:
+    mov rdx, rsi
+    mov rsi, rdi
+    mov rdi, 2
+    mov rax, 1 # write
+    syscall
+    ret
+
+# rdi: value, written to stderr in decimal
+sofa_write_i64:
+    sub rsp, 32
+    lea rsi, [rsp+32]
+    mov rax, rdi
+    mov r8, rdi
+    test rax, rax
+    jns 1f
+    neg rax
+1:
+    mov rcx, 10
+2:
+    xor edx, edx
+    div rcx
+    add dl, 48 # '0'
+    dec rsi
+    mov [rsi], dl
+    test rax, rax
+    jnz 2b
+    test r8, r8
+    jns 3f
+    dec rsi
+    mov byte ptr [rsi], 45 # '-'
+3:
+    mov rdi, rsi
+    lea rsi, [rsp+32]
+    sub rsi, rdi
+    call sofa_write_err
+    add rsp, 32
+    ret
+
+# exits as a panicking program does
+sofa_exit_panic:
+    mov rdi, 101
+    mov rax, 231 # exit_group
+    syscall
+
+# rdi, rsi: the message locating the panic, rdx, rcx: the message of the program
+sofa_panic:
+    push rcx
+    push rdx
+    call sofa_write_err
+    pop rdi
+    pop rsi
+    call sofa_write_err
+    lea rdi, [rip+.Lsofa_newline]
+    mov rsi, 1
+    call sofa_write_err
+    jmp sofa_exit_panic
+
+# rdi, rsi: the message locating the panic, rdx: the index, rcx: the length
+sofa_panic_bounds:
+    push rcx
+    push rdx
+    call sofa_write_err
+    lea rdi, [rip+.Lsofa_bounds_len]
+    mov rsi, 32
+    call sofa_write_err
+    mov rdi, [rsp+8]
+    call sofa_write_i64
+    lea rdi, [rip+.Lsofa_bounds_index]
+    mov rsi, 18
+    call sofa_write_err
+    mov rdi, [rsp]
+    call sofa_write_i64
+    lea rdi, [rip+.Lsofa_newline]
+    mov rsi, 1
+    call sofa_write_err
+    jmp sofa_exit_panic
+
+# rdi, rsi: the message locating the panic, rdx: the start, rcx: the end, r8: the length
+sofa_panic_range:
+    push r8
+    push rcx
+    push rdx
+    call sofa_write_err
+    lea rdi, [rip+.Lsofa_range]
+    mov rsi, 6
+    call sofa_write_err
+    mov rdi, [rsp]
+    call sofa_write_i64
+    lea rdi, [rip+.Lsofa_range_dots]
+    mov rsi, 2
+    call sofa_write_err
+    mov rdi, [rsp+8]
+    call sofa_write_i64
+    lea rdi, [rip+.Lsofa_range_len]
+    mov rsi, 26
+    call sofa_write_err
+    mov rdi, [rsp+16]
+    call sofa_write_i64
+    lea rdi, [rip+.Lsofa_newline]
+    mov rsi, 1
+    call sofa_write_err
+    jmp sofa_exit_panic
+
+.section .rodata
+.Lsofa_bounds_len:
+    .ascii "index out of bounds: the len is "
+.Lsofa_bounds_index:
+    .ascii " but the index is "
+.Lsofa_range:
+    .ascii "range "
+.Lsofa_range_dots:
+    .ascii ".."
+.Lsofa_range_len:
+    .ascii " out of bounds for length "
+.Lsofa_alloc_of:
+    .ascii "memory allocation of "
+.Lsofa_alloc_failed:
+    .ascii " bytes failed\n"
+.Lsofa_newline:
+    .ascii "\n"
+
+.bss
+sofa_heap_cur:
+    .zero 8
+sofa_heap_end:
+    .zero 8
+sofa_free_list:
+    .zero 8
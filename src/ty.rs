@@ -1,19 +1,79 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    ops::{Add, Mul, Neg, Sub},
+};
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Type {
     I64,
     Bool,
+    /// `u8`, a byte, what the `&u8` of C functions points at
+    U8,
     Ptr {
         to: Box<Type>,
+        /// `&mut T`, the pointee can be written through it
+        mutable: bool,
+    },
+    /// `Box<T>`, owns a heap allocation freed when the owner goes out of scope
+    Box {
+        to: Box<Type>,
     },
     Array {
         element: Box<Type>,
-        len: usize,
+        len: Const,
+    },
+    /// `&[T]` or `&mut [T]`, a pointer to the first element and the length
+    Slice {
+        element: Box<Type>,
+        mutable: bool,
+    },
+    /// `Vec<T>`, a growable heap buffer: pointer, length and capacity
+    Vec {
+        element: Box<Type>,
+        /// `Vec<T, n>`, the length is known to be `n`, so the vec does not grow in place
+        len: Option<Const>,
     },
+    Tuple {
+        elements: Vec<Type>,
+    },
+    Struct {
+        name: String,
+        fields: Vec<Field>,
+    },
+    Enum {
+        name: String,
+        variants: Vec<VariantDef>,
+    },
+    /// `fn(i64) -> i64`, the address of the code, also the type of a function itself
     Fn {
         args: Vec<Type>,
         ret: Box<Type>,
     },
-    Void,
+    /// `impl Fn(i64) -> i64`, a closure: the address of its code and of its environment,
+    /// which the code takes as the first argument
+    Closure {
+        args: Vec<Type>,
+        ret: Box<Type>,
+    },
+    /// `&dyn Trait`, `&mut dyn Trait` or `Box<dyn Trait>`,
+    /// a pointer to the value and to the vtable of its type
+    Dyn {
+        name: String,
+        mutable: bool,
+        /// owns the value like a box, dropped through the vtable
+        boxed: bool,
+    },
+    /// `T` in `fn max<T>(a: T, b: T) -> T`, replaced by its instantiation before codegen
+    Param {
+        name: String,
+    },
+    /// the value of a const parameter `N`, bound in instantiations like types are
+    Const {
+        value: Const,
+    },
+    /// `()`, takes no space
+    Unit,
     Never,
 
     #[allow(dead_code)]
@@ -25,12 +85,467 @@ impl Type {
         match self {
             Type::I64 => 8,
             Type::Ptr { .. } => 8,
-            Type::Array { element, len } => element.size() * len,
+            Type::Box { .. } => 8,
+            Type::Array { element, len } => element.size() * len.to_usize(),
+            Type::Slice { .. } => 16,
+            Type::Dyn { .. } => 16,
+            Type::Vec { .. } => 24,
+            Type::Tuple { elements } => elements.iter().map(Type::size).sum(),
+            Type::Struct { fields, .. } => fields.iter().map(|f| f.ty.size()).sum(),
+            // tag word followed by the largest payload
+            Type::Enum { variants, .. } => {
+                8 + variants
+                    .iter()
+                    .map(VariantDef::payload_size)
+                    .max()
+                    .unwrap_or(0)
+            }
             Type::Bool => 8,
-            Type::Fn { .. } => todo!(),
-            Type::Void => todo!(),
+            Type::U8 => 1,
+            Type::Fn { .. } => 8,
+            Type::Closure { .. } => 16,
+            Type::Param { name } => panic!("size of type parameter {} is not known", name),
+            Type::Const { value } => panic!("{:?} is a value, not a type", value),
+            Type::Unit => 0,
             Type::Never => todo!(),
             Type::Unknown => todo!(),
         }
     }
+
+    /// values of aggregate types are handled through their address
+    pub fn is_aggregate(&self) -> bool {
+        matches!(
+            self,
+            Type::Array { .. }
+                | Type::Slice { .. }
+                | Type::Dyn { .. }
+                | Type::Closure { .. }
+                | Type::Vec { .. }
+                | Type::Tuple { .. }
+                | Type::Struct { .. }
+                | Type::Enum { .. }
+        )
+    }
+
+    /// values owning heap memory, which has to be freed when they are dropped
+    pub fn needs_drop(&self) -> bool {
+        match self {
+            Type::Box { .. } | Type::Vec { .. } => true,
+            Type::Dyn { boxed, .. } => *boxed,
+            // it may be instantiated with a type that does
+            Type::Param { .. } => true,
+            Type::Array { element, .. } => element.needs_drop(),
+            Type::Tuple { elements } => elements.iter().any(Type::needs_drop),
+            Type::Struct { fields, .. } => fields.iter().any(|f| f.ty.needs_drop()),
+            Type::Enum { variants, .. } => variants
+                .iter()
+                .any(|v| v.fields.iter().any(|f| f.ty.needs_drop())),
+            _ => false,
+        }
+    }
+
+    /// whether a closure is part of a value of this type, its environment lives in a frame
+    pub fn holds_closure(&self) -> bool {
+        match self {
+            Type::Closure { .. } => true,
+            Type::Box { to } => to.holds_closure(),
+            Type::Array { element, .. } | Type::Vec { element, .. } => element.holds_closure(),
+            Type::Tuple { elements } => elements.iter().any(Type::holds_closure),
+            Type::Struct { fields, .. } => fields.iter().any(|f| f.ty.holds_closure()),
+            Type::Enum { variants, .. } => variants
+                .iter()
+                .any(|v| v.fields.iter().any(|f| f.ty.holds_closure())),
+            _ => false,
+        }
+    }
+
+    /// replaces the type parameters by their instantiation in `map`
+    pub fn subst(&self, map: &HashMap<String, Type>) -> Type {
+        let subst = |ty: &Type| Box::new(ty.subst(map));
+        match self {
+            Type::Param { name } => map.get(name).cloned().unwrap_or_else(|| self.clone()),
+            Type::Ptr { to, mutable } => Type::Ptr {
+                to: subst(to),
+                mutable: *mutable,
+            },
+            Type::Box { to } => Type::Box { to: subst(to) },
+            Type::Array { element, len } => Type::Array {
+                element: subst(element),
+                len: len.subst(map),
+            },
+            Type::Slice { element, mutable } => Type::Slice {
+                element: subst(element),
+                mutable: *mutable,
+            },
+            Type::Vec { element, len } => Type::Vec {
+                element: subst(element),
+                len: len.as_ref().map(|len| len.subst(map)),
+            },
+            Type::Tuple { elements } => Type::Tuple {
+                elements: elements.iter().map(|ty| ty.subst(map)).collect(),
+            },
+            Type::Fn { args, ret } => Type::Fn {
+                args: args.iter().map(|ty| ty.subst(map)).collect(),
+                ret: subst(ret),
+            },
+            Type::Closure { args, ret } => Type::Closure {
+                args: args.iter().map(|ty| ty.subst(map)).collect(),
+                ret: subst(ret),
+            },
+            Type::Const { value } => Type::Const {
+                value: value.subst(map),
+            },
+            // user defined types take no parameters
+            _ => self.clone(),
+        }
+    }
+
+    /// whether the type parameter `name` occurs in `self`
+    pub fn mentions(&self, name: &str) -> bool {
+        self.subst(&HashMap::from([(name.to_string(), Type::Unknown)])) != *self
+    }
+
+    /// binds the type parameters in `self` to the parts of `arg` they stand for
+    pub fn unify(&self, arg: &Type, map: &mut HashMap<String, Type>) {
+        match (self, arg) {
+            // `Vec::new()` is typed by where it goes
+            (_, Type::Unknown) => {}
+            (Type::Param { name }, _) => match map.get(name) {
+                Some(bound) if bound != arg => {
+                    panic!("mismatched types, expected {:?} but found {:?}", bound, arg)
+                }
+                Some(_) => {}
+                None => {
+                    map.insert(name.clone(), arg.clone());
+                }
+            },
+            (Type::Ptr { to: param, .. }, Type::Ptr { to: arg, .. })
+            | (Type::Box { to: param }, Type::Box { to: arg })
+            | (Type::Slice { element: param, .. }, Type::Slice { element: arg, .. }) => {
+                param.unify(arg, map)
+            }
+            (
+                Type::Vec {
+                    element: param,
+                    len: param_len,
+                },
+                Type::Vec { element: arg, len },
+            ) => {
+                param.unify(arg, map);
+                if let (Some(param_len), Some(len)) = (param_len, len) {
+                    param_len.unify(len, map);
+                }
+            }
+            (
+                Type::Array {
+                    element: param,
+                    len: param_len,
+                },
+                Type::Array { element: arg, len },
+            ) => {
+                param.unify(arg, map);
+                param_len.unify(len, map);
+            }
+            (Type::Tuple { elements: params }, Type::Tuple { elements: args })
+                if params.len() == args.len() =>
+            {
+                for (param, arg) in params.iter().zip(args.iter()) {
+                    param.unify(arg, map);
+                }
+            }
+            (
+                Type::Fn {
+                    args: params,
+                    ret: param_ret,
+                },
+                Type::Fn { args, ret },
+            )
+            | (
+                Type::Closure {
+                    args: params,
+                    ret: param_ret,
+                },
+                Type::Closure { args, ret },
+            ) if params.len() == args.len() => {
+                for (param, arg) in params.iter().zip(args.iter()) {
+                    param.unify(arg, map);
+                }
+                param_ret.unify(ret, map);
+            }
+            _ => {}
+        }
+    }
+
+    /// offset and type of the i-th element of a tuple or field of a struct,
+    /// or of the pointer, length and capacity of a slice or vec
+    pub fn member(&self, i: usize) -> (usize, Type) {
+        match self {
+            Type::Slice { element, mutable } if i == 0 => (
+                0,
+                Type::Ptr {
+                    to: element.clone(),
+                    mutable: *mutable,
+                },
+            ),
+            Type::Vec { element, .. } if i == 0 => (
+                0,
+                Type::Ptr {
+                    to: element.clone(),
+                    mutable: true,
+                },
+            ),
+            Type::Slice { .. } if i == 1 => (8, Type::I64),
+            Type::Vec { .. } if i < 3 => (8 * i, Type::I64),
+            Type::Tuple { elements } => (
+                elements[..i].iter().map(Type::size).sum(),
+                elements[i].clone(),
+            ),
+            Type::Struct { fields, .. } => (
+                fields[..i].iter().map(|f| f.ty.size()).sum(),
+                fields[i].ty.clone(),
+            ),
+            _ => panic!("{:?} has no members", self),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldsKind {
+    /// `Empty`
+    Unit,
+    /// `Circle(i64)`
+    Tuple,
+    /// `Rect { w: i64, h: i64 }`
+    Named,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    /// positional fields are named by their index
+    pub name: String,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariantDef {
+    pub name: String,
+    pub kind: FieldsKind,
+    pub fields: Vec<Field>,
+}
+
+impl VariantDef {
+    pub fn payload_size(&self) -> usize {
+        self.fields.iter().map(|f| f.ty.size()).sum()
+    }
+
+    /// offset of the i-th field from the start of the payload
+    pub fn field_offset(&self, i: usize) -> usize {
+        self.fields[..i].iter().map(|f| f.ty.size()).sum()
+    }
+}
+
+/// a compile-time i64 over const parameters, an array length like `2 * N + 1`
+///
+/// it is kept as a sum of products of parameters, so equal expressions compare equal:
+/// `N + N + 1` and `1 + 2 * N` are the same.
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct Const {
+    /// coefficient of each product of parameters, sorted, none of the coefficients is zero
+    terms: BTreeMap<Vec<String>, i64>,
+}
+
+impl Const {
+    pub fn number(value: i64) -> Const {
+        Const::term(vec![], value)
+    }
+
+    /// the const parameter `name` itself
+    pub fn param(name: &str) -> Const {
+        Const::term(vec![name.to_string()], 1)
+    }
+
+    fn term(params: Vec<String>, coefficient: i64) -> Const {
+        let mut terms = BTreeMap::new();
+        if coefficient != 0 {
+            terms.insert(params, coefficient);
+        }
+        Const { terms }
+    }
+
+    /// whether it is at least 0 for all parameters at least 0, as lengths are
+    pub fn is_natural(&self) -> bool {
+        self.terms.values().all(|c| *c >= 0)
+    }
+
+    /// the value, unless it depends on a parameter
+    pub fn eval(&self) -> Option<i64> {
+        match self.terms.iter().next() {
+            None => Some(0),
+            Some((params, value)) if params.is_empty() && self.terms.len() == 1 => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// the value as an array length, known once the const parameters are instantiated
+    pub fn to_usize(&self) -> usize {
+        match self.eval() {
+            Some(len) => usize::try_from(len)
+                .unwrap_or_else(|_| panic!("array length must be non-negative, found {}", len)),
+            None => panic!("length {:?} is not known", self),
+        }
+    }
+
+    /// replaces the const parameters by their instantiation in `map`
+    pub fn subst(&self, map: &HashMap<String, Type>) -> Const {
+        self.terms
+            .iter()
+            .map(|(params, coefficient)| {
+                params
+                    .iter()
+                    .fold(Const::number(*coefficient), |product, param| {
+                        product
+                            * match map.get(param) {
+                                Some(Type::Const { value }) => value.clone(),
+                                _ => Const::param(param),
+                            }
+                    })
+            })
+            .fold(Const::default(), Add::add)
+    }
+
+    /// binds the const parameter in `self` to the value making it equal `arg`,
+    /// which is found when it is the only one unbound and not multiplied by another
+    pub fn unify(&self, arg: &Const, map: &mut HashMap<String, Type>) {
+        let mut unbound: Vec<_> = self
+            .terms
+            .keys()
+            .flatten()
+            .filter(|param| !map.contains_key(*param))
+            .collect();
+        unbound.sort();
+        unbound.dedup();
+        if let [param] = unbound[..] {
+            let value = self.subst(map);
+            let linear: Vec<_> = value
+                .terms
+                .iter()
+                .filter(|(params, _)| params.contains(param))
+                .collect();
+            let coefficient = match linear[..] {
+                [(params, coefficient)] if params.len() == 1 => *coefficient,
+                _ => return,
+            };
+            // `a * N + rest == arg` for `N == (arg - rest) / a`
+            let rest = value.clone() - Const::param(param) * Const::number(coefficient);
+            let diff = arg.clone() - rest;
+            if diff.terms.values().any(|c| c % coefficient != 0) {
+                panic!(
+                    "mismatched types, expected length {:?} but found {:?}",
+                    value, arg
+                )
+            }
+            let terms = diff
+                .terms
+                .into_iter()
+                .map(|(params, c)| (params, c / coefficient))
+                .collect();
+            let solution = Const { terms };
+            // a const parameter is a length too
+            if !solution.is_natural() {
+                panic!(
+                    "mismatched types, expected length {:?} but found {:?}",
+                    value, arg
+                )
+            }
+            map.insert(param.clone(), Type::Const { value: solution });
+        } else if !unbound.is_empty() {
+            return;
+        }
+
+        let value = self.subst(map);
+        if value != *arg {
+            panic!(
+                "mismatched types, expected length {:?} but found {:?}",
+                value, arg
+            )
+        }
+    }
+}
+
+impl Add for Const {
+    type Output = Const;
+
+    fn add(mut self, rhs: Const) -> Const {
+        for (params, coefficient) in rhs.terms {
+            let sum = self
+                .terms
+                .get(&params)
+                .map_or(coefficient, |c| c.wrapping_add(coefficient));
+            if sum == 0 {
+                self.terms.remove(&params);
+            } else {
+                self.terms.insert(params, sum);
+            }
+        }
+        self
+    }
+}
+
+impl Neg for Const {
+    type Output = Const;
+
+    fn neg(self) -> Const {
+        self * Const::number(-1)
+    }
+}
+
+impl Sub for Const {
+    type Output = Const;
+
+    fn sub(self, rhs: Const) -> Const {
+        self + -rhs
+    }
+}
+
+impl Mul for Const {
+    type Output = Const;
+
+    fn mul(self, rhs: Const) -> Const {
+        self.terms
+            .iter()
+            .flat_map(|(a, x)| {
+                rhs.terms.iter().map(move |(b, y)| {
+                    let mut params: Vec<_> = a.iter().chain(b.iter()).cloned().collect();
+                    params.sort();
+                    Const::term(params, x.wrapping_mul(*y))
+                })
+            })
+            .fold(Const::default(), Add::add)
+    }
+}
+
+/// like the source, `2 * N + 1`
+impl fmt::Debug for Const {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.terms.is_empty() {
+            return write!(f, "0");
+        }
+        // the constant sorts first but reads last
+        let mut terms: Vec<_> = self.terms.iter().collect();
+        let constant = usize::from(terms[0].0.is_empty());
+        terms.rotate_left(constant);
+        for (i, (params, coefficient)) in terms.into_iter().enumerate() {
+            let mut coefficient = *coefficient;
+            if i > 0 {
+                write!(f, " {} ", if coefficient < 0 { '-' } else { '+' })?;
+                coefficient = coefficient.wrapping_abs();
+            }
+            let mut factors: Vec<String> = params.clone();
+            if coefficient != 1 || factors.is_empty() {
+                factors.insert(0, coefficient.to_string());
+            }
+            write!(f, "{}", factors.join(" * "))?;
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,465 @@
+//! exhaustiveness and reachability of `match` arms
+//!
+//! based on the pattern matrix algorithm from
+//! "Warnings for pattern matching" (Luc Maranget, 2007)
+
+use crate::{
+    ast::{
+        Assert, Assign, Ast, BinOp, Block, Bool, BoxNew, Call, Closure, CompoundAssign, Enclosed,
+        Expr, FnCall, IfElse, Index, Init, Loop, Match, Member, Pattern, Return, Slice, Stmt,
+        Struct, ToDyn, Tuple, UnOp, Variant, VecConcat, VecPop, VecPush,
+    },
+    ty::{FieldsKind, Type},
+};
+
+/// reports non-exhaustive matches and refutable `let` or parameter patterns as errors,
+/// and unreachable arms as warnings
+pub fn check(ast: &Ast) {
+    for f in ast.node.functions() {
+        for arg in f.args.iter() {
+            check_irrefutable(&arg.pattern, &arg.ty, "function argument");
+        }
+        check_block(&f.body);
+    }
+}
+
+fn check_block(block: &Block) {
+    for expr in block.exprs.iter() {
+        check_expr(expr);
+    }
+}
+
+fn check_expr(expr: &Expr) {
+    match expr {
+        Expr::Stmt(Stmt { expr })
+        | Expr::Return(Return { expr })
+        | Expr::UnOp(UnOp { expr, .. })
+        | Expr::Enclosed(Enclosed { expr })
+        | Expr::Member(Member { expr, .. })
+        | Expr::BoxNew(BoxNew { value: expr })
+        | Expr::ToDyn(ToDyn { value: expr, .. })
+        | Expr::Assert(Assert { cond: expr, .. })
+        | Expr::VecPop(VecPop { vec: expr, .. }) => check_expr(expr),
+        Expr::Slice(Slice { expr, lo, hi, .. }) => {
+            check_expr(expr);
+            for bound in lo.iter().chain(hi.iter()) {
+                check_expr(bound);
+            }
+        }
+        Expr::Block(block) | Expr::Loop(Loop { body: block }) => check_block(block),
+        Expr::IfElse(IfElse {
+            cond,
+            if_body,
+            else_body,
+        }) => {
+            check_expr(cond);
+            check_block(if_body);
+            if let Some(else_body) = else_body {
+                check_block(else_body);
+            }
+        }
+        Expr::Match(m) => {
+            check_expr(&m.scrutinee);
+            for arm in m.arms.iter() {
+                check_expr(&arm.body);
+            }
+            check_match(m);
+        }
+        Expr::FnCall(FnCall { args: exprs, .. })
+        | Expr::Tuple(Tuple { elements: exprs })
+        | Expr::Struct(Struct { fields: exprs, .. })
+        | Expr::Variant(Variant { fields: exprs, .. }) => {
+            for expr in exprs.iter() {
+                check_expr(expr);
+            }
+        }
+        Expr::Call(Call { callee, args }) => {
+            check_expr(callee);
+            for expr in args.iter() {
+                check_expr(expr);
+            }
+        }
+        Expr::Closure(Closure { params, body, .. }) => {
+            for param in params.iter() {
+                check_irrefutable(&param.pattern, &param.ty, "closure argument");
+            }
+            check_expr(body);
+        }
+        Expr::Init(Init { pattern, value }) => {
+            if let Some(value) = value {
+                check_expr(value);
+                check_irrefutable(pattern, &value.ty(), "local binding");
+            }
+        }
+        Expr::Assign(Assign { lhs, rhs })
+        | Expr::CompoundAssign(CompoundAssign { lhs, rhs, .. })
+        | Expr::BinOp(BinOp { lhs, rhs, .. })
+        | Expr::Index(Index {
+            expr: lhs,
+            index: rhs,
+            ..
+        })
+        | Expr::VecPush(VecPush {
+            vec: lhs,
+            value: rhs,
+        })
+        | Expr::VecConcat(VecConcat { lhs, rhs, .. }) => {
+            check_expr(lhs);
+            check_expr(rhs);
+        }
+        Expr::VecNew(_)
+        | Expr::Panic(_)
+        | Expr::Bool(_)
+        | Expr::Local(_)
+        | Expr::Static(_)
+        | Expr::FnRef(_)
+        | Expr::ConstExpr(_)
+        | Expr::Number(_) => {}
+    }
+}
+
+fn check_match(m: &Match) {
+    let tys = [m.scrutinee.ty()];
+
+    let mut rows = vec![];
+    for arm in m.arms.iter() {
+        let row = vec![lower(&arm.pattern)];
+        if usefulness(&rows, &row, &tys).is_empty() {
+            eprintln!(
+                "warning: unreachable pattern `{}`",
+                display(&row[0], &tys[0])
+            );
+        }
+        rows.push(row);
+    }
+
+    let witnesses = usefulness(&rows, &[Pat::Wild], &tys);
+    if !witnesses.is_empty() {
+        panic!(
+            "non-exhaustive patterns: {} not covered",
+            list_witnesses(&witnesses, &tys[0])
+        )
+    }
+}
+
+fn check_irrefutable(pattern: &Pattern, ty: &Type, place: &str) {
+    let tys = [ty.clone()];
+    let witnesses = usefulness(&[vec![lower(pattern)]], &[Pat::Wild], &tys);
+    if !witnesses.is_empty() {
+        panic!(
+            "refutable pattern in {}: {} not covered",
+            place,
+            list_witnesses(&witnesses, ty)
+        )
+    }
+}
+
+fn list_witnesses(witnesses: &[Vec<Pat>], ty: &Type) -> String {
+    let mut missing = witnesses
+        .iter()
+        .map(|w| format!("`{}`", display(&w[0], ty)))
+        .collect::<Vec<_>>();
+    match missing.len() {
+        1 => missing.pop().unwrap(),
+        2..=3 => {
+            let last = missing.pop().unwrap();
+            format!("{} and {}", missing.join(", "), last)
+        }
+        n => format!("{} and {} more", missing[..3].join(", "), n - 3),
+    }
+}
+
+/// the ways a value can be built, as far as patterns can tell them apart
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Ctor {
+    Bool(bool),
+    /// integers within the inclusive bounds
+    Range(i128, i128),
+    Variant(usize),
+    /// the only constructor of tuples, structs and pointers
+    Single,
+    /// values which only bindings and `_` can match
+    Opaque,
+}
+
+/// patterns are lowered to constructors applied to sub-patterns
+#[derive(Debug, Clone)]
+enum Pat {
+    Wild,
+    Ctor(Ctor, Vec<Pat>),
+}
+
+fn lower(pattern: &Pattern) -> Pat {
+    match pattern {
+        Pattern::Wildcard | Pattern::Binding { .. } => Pat::Wild,
+        Pattern::Int(value) => Pat::Ctor(Ctor::Range(*value as i128, *value as i128), vec![]),
+        Pattern::Range { lo, hi } => Pat::Ctor(Ctor::Range(*lo as i128, *hi as i128), vec![]),
+        Pattern::Bool(b) => Pat::Ctor(Ctor::Bool(matches!(b, Bool::True)), vec![]),
+        Pattern::Tuple(fields) | Pattern::Struct(fields) => {
+            Pat::Ctor(Ctor::Single, fields.iter().map(lower).collect())
+        }
+        Pattern::Ref(inner) => Pat::Ctor(Ctor::Single, vec![lower(inner)]),
+        Pattern::Variant { index, fields, .. } => {
+            Pat::Ctor(Ctor::Variant(*index), fields.iter().map(lower).collect())
+        }
+    }
+}
+
+fn all_ctors(ty: &Type) -> Vec<Ctor> {
+    match ty {
+        Type::Bool => vec![Ctor::Bool(false), Ctor::Bool(true)],
+        Type::I64 => vec![Ctor::Range(i64::MIN as i128, i64::MAX as i128)],
+        Type::Enum { variants, .. } => (0..variants.len()).map(Ctor::Variant).collect(),
+        Type::Tuple { .. } | Type::Unit | Type::Struct { .. } | Type::Ptr { .. } => {
+            vec![Ctor::Single]
+        }
+        _ => vec![Ctor::Opaque],
+    }
+}
+
+fn field_tys(ty: &Type, ctor: &Ctor) -> Vec<Type> {
+    match (ty, ctor) {
+        (Type::Enum { variants, .. }, Ctor::Variant(i)) => {
+            variants[*i].fields.iter().map(|f| f.ty.clone()).collect()
+        }
+        (Type::Tuple { elements }, Ctor::Single) => elements.clone(),
+        (Type::Struct { fields, .. }, Ctor::Single) => {
+            fields.iter().map(|f| f.ty.clone()).collect()
+        }
+        (Type::Ptr { to, .. }, Ctor::Single) => vec![*to.clone()],
+        _ => vec![],
+    }
+}
+
+/// whether every value built by `ctor` is also built by `head`
+fn covers(head: &Ctor, ctor: &Ctor) -> bool {
+    match (head, ctor) {
+        (Ctor::Range(lo, hi), Ctor::Range(a, b)) => lo <= a && b <= hi,
+        (Ctor::Opaque, _) => false,
+        _ => head == ctor,
+    }
+}
+
+/// splits integer ranges at the bounds of `heads`,
+/// so that each resulting range is either covered by a head or disjoint from it
+fn split(ctors: Vec<Ctor>, heads: &[&Ctor]) -> Vec<Ctor> {
+    let mut bounds = heads
+        .iter()
+        .filter_map(|head| match head {
+            Ctor::Range(lo, hi) => Some([*lo, hi + 1]),
+            _ => None,
+        })
+        .flatten()
+        .collect::<Vec<_>>();
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    ctors
+        .into_iter()
+        .flat_map(|ctor| match ctor {
+            Ctor::Range(lo, hi) => {
+                let mut res = vec![];
+                let mut start = lo;
+                for &b in bounds.iter().filter(|&&b| lo < b && b <= hi) {
+                    res.push(Ctor::Range(start, b - 1));
+                    start = b;
+                }
+                res.push(Ctor::Range(start, hi));
+                res
+            }
+            ctor => vec![ctor],
+        })
+        .collect()
+}
+
+/// adjacent missing ranges are reported as one
+fn merge(ctors: Vec<Ctor>) -> Vec<Ctor> {
+    let mut res: Vec<Ctor> = vec![];
+    for ctor in ctors {
+        match (res.last_mut(), &ctor) {
+            (Some(Ctor::Range(_, hi)), Ctor::Range(lo, new_hi)) if *hi + 1 == *lo => *hi = *new_hi,
+            _ => res.push(ctor),
+        }
+    }
+    res
+}
+
+/// rows of the matrix whose head matches `ctor`, with the head replaced by its fields
+fn specialize(row: &[Pat], ctor: &Ctor, arity: usize) -> Option<Vec<Pat>> {
+    let mut res = match &row[0] {
+        Pat::Wild => vec![Pat::Wild; arity],
+        Pat::Ctor(head, fields) if covers(head, ctor) => fields.clone(),
+        Pat::Ctor(..) => return None,
+    };
+    res.extend_from_slice(&row[1..]);
+    Some(res)
+}
+
+/// returns the values matched by `v` but by none of `rows`, empty when `v` is useless
+fn usefulness(rows: &[Vec<Pat>], v: &[Pat], tys: &[Type]) -> Vec<Vec<Pat>> {
+    let head = match v.first() {
+        Some(head) => head,
+        None if rows.is_empty() => return vec![vec![]],
+        None => return vec![],
+    };
+
+    let heads = rows
+        .iter()
+        .filter_map(|row| match &row[0] {
+            Pat::Ctor(ctor, _) => Some(ctor),
+            Pat::Wild => None,
+        })
+        .collect::<Vec<_>>();
+
+    match head {
+        Pat::Ctor(ctor, _) => split(vec![ctor.clone()], &heads)
+            .iter()
+            .flat_map(|ctor| usefulness_specialized(rows, v, tys, ctor))
+            .collect(),
+        Pat::Wild => {
+            let all = split(all_ctors(&tys[0]), &heads);
+            let missing = all
+                .iter()
+                .filter(|ctor| !heads.iter().any(|head| covers(head, ctor)))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if missing.is_empty() {
+                all.iter()
+                    .flat_map(|ctor| usefulness_specialized(rows, v, tys, ctor))
+                    .collect()
+            } else {
+                let default = rows
+                    .iter()
+                    .filter(|row| matches!(row[0], Pat::Wild))
+                    .map(|row| row[1..].to_vec())
+                    .collect::<Vec<_>>();
+
+                let missing = merge(missing);
+                usefulness(&default, &v[1..], &tys[1..])
+                    .into_iter()
+                    .flat_map(|witness| {
+                        let heads = if heads.is_empty() {
+                            vec![Pat::Wild]
+                        } else {
+                            missing
+                                .iter()
+                                .map(|ctor| {
+                                    let arity = field_tys(&tys[0], ctor).len();
+                                    Pat::Ctor(ctor.clone(), vec![Pat::Wild; arity])
+                                })
+                                .collect()
+                        };
+                        heads.into_iter().map(move |head| {
+                            let mut res = vec![head];
+                            res.extend_from_slice(&witness);
+                            res
+                        })
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+fn usefulness_specialized(
+    rows: &[Vec<Pat>],
+    v: &[Pat],
+    tys: &[Type],
+    ctor: &Ctor,
+) -> Vec<Vec<Pat>> {
+    let mut fields = field_tys(&tys[0], ctor);
+    let arity = fields.len();
+    fields.extend_from_slice(&tys[1..]);
+
+    let rows = rows
+        .iter()
+        .filter_map(|row| specialize(row, ctor, arity))
+        .collect::<Vec<_>>();
+    let v = specialize(v, ctor, arity).expect("head of v must cover the constructor");
+
+    usefulness(&rows, &v, &fields)
+        .into_iter()
+        .map(|witness| {
+            let (args, rest) = witness.split_at(arity);
+            let mut res = vec![Pat::Ctor(ctor.clone(), args.to_vec())];
+            res.extend_from_slice(rest);
+            res
+        })
+        .collect()
+}
+
+fn display(pat: &Pat, ty: &Type) -> String {
+    let (ctor, args) = match pat {
+        Pat::Wild => return "_".to_string(),
+        Pat::Ctor(ctor, args) => (ctor, args),
+    };
+
+    match ctor {
+        Ctor::Bool(b) => b.to_string(),
+        Ctor::Range(lo, hi) if lo == hi => display_int(*lo),
+        Ctor::Range(lo, hi) => format!("{}..={}", display_int(*lo), display_int(*hi)),
+        Ctor::Variant(i) => {
+            let (name, def) = match ty {
+                Type::Enum { name, variants } => (name, &variants[*i]),
+                _ => unreachable!(),
+            };
+            let fields = args
+                .iter()
+                .zip(def.fields.iter())
+                .map(|(arg, field)| (field, display(arg, &field.ty)));
+            match def.kind {
+                FieldsKind::Unit => format!("{}::{}", name, def.name),
+                FieldsKind::Tuple => format!(
+                    "{}::{}({})",
+                    name,
+                    def.name,
+                    fields.map(|(_, arg)| arg).collect::<Vec<_>>().join(", ")
+                ),
+                FieldsKind::Named => format!(
+                    "{}::{} {{ {} }}",
+                    name,
+                    def.name,
+                    fields
+                        .map(|(field, arg)| format!("{}: {}", field.name, arg))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            }
+        }
+        Ctor::Single => {
+            let args = args
+                .iter()
+                .zip(field_tys(ty, ctor).iter())
+                .map(|(arg, ty)| display(arg, ty))
+                .collect::<Vec<_>>();
+            match ty {
+                Type::Ptr { mutable: false, .. } => format!("&{}", args[0]),
+                Type::Ptr { mutable: true, .. } => format!("&mut {}", args[0]),
+                Type::Struct { name, fields } => format!(
+                    "{} {{ {} }}",
+                    name,
+                    fields
+                        .iter()
+                        .zip(args.iter())
+                        .map(|(field, arg)| format!("{}: {}", field.name, arg))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                _ if args.len() == 1 => format!("({},)", args[0]),
+                _ => format!("({})", args.join(", ")),
+            }
+        }
+        Ctor::Opaque => "_".to_string(),
+    }
+}
+
+fn display_int(value: i128) -> String {
+    if value == i64::MIN as i128 {
+        "i64::MIN".to_string()
+    } else if value == i64::MAX as i128 {
+        "i64::MAX".to_string()
+    } else {
+        value.to_string()
+    }
+}
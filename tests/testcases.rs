@@ -1,4 +1,7 @@
-use tools::assert_exit_code;
+use tools::{
+    assert_compile_error, assert_compile_warning, assert_exit_code, assert_exit_code_with_c,
+    assert_exit_code_with_files, assert_panic, emit_c_header,
+};
 
 mod tools;
 
@@ -60,12 +63,12 @@ fn skip_comment() {
 fn dereference() {
     let s = r"
     fn main() -> i64 {
-        let a = 1;
+        let mut a = 1;
 
-        let b = &a;
+        let mut b = &mut a;
         *b = 2;
 
-        let c = &b;
+        let c = &mut b;
         **c = 3;
 
         return a;
@@ -96,12 +99,12 @@ fn ptr_offset() {
 fn fib_array() {
     let s = r"
     fn main() -> i64 {
-        let a: [i64; 10];
+        let mut a: [i64; 10];
         let len = 10;
         a[0] = 0;
         a[1] = 1;
 
-        let i = 2;
+        let mut i = 2;
         loop {
             if i == len {
                 return a[len - 1];
@@ -113,7 +116,7 @@ fn fib_array() {
     }
     ";
 
-    let mut fib = vec![0; 10];
+    let mut fib = [0; 10];
     fib[1] = 1;
     for i in 2..10 {
         fib[i] = fib[i - 1] + fib[i - 2];
@@ -145,3 +148,2318 @@ fn short() {
 
     assert_exit_code(s, 1);
 }
+
+#[test]
+fn enum_match() {
+    let s = r"
+    enum Shape {
+        Circle(i64),
+        Rect { w: i64, h: i64 },
+        Empty,
+    }
+
+    fn area(s: Shape) -> i64 {
+        match s {
+            Shape::Circle(0) => 100,
+            Shape::Circle(r) => 3 * r * r,
+            Shape::Rect { w, h } => w * h,
+            Shape::Empty => 0,
+        }
+    }
+
+    fn main() -> i64 {
+        let a = Shape::Rect { w: 3, h: 4 };
+        let b = Shape::Circle(2);
+        return area(a) + area(b) + area(Shape::Empty);
+    }
+    ";
+
+    assert_exit_code(s, 24);
+}
+
+#[test]
+fn match_literals() {
+    let s = r"
+    fn main() -> i64 {
+        let n = 7;
+        let a = match n {
+            0 => 1,
+            -1 => 2,
+            x => x + 1,
+        };
+        let b = match a == 8 {
+            true => 10,
+            false => 20,
+        };
+        let c = match n {
+            7 => 100,
+            _ => 0,
+        };
+        return a + b + c;
+    }
+    ";
+
+    assert_exit_code(s, 118);
+}
+
+#[test]
+fn match_ranges() {
+    let s = r"
+    fn classify(n: i64) -> i64 {
+        match n {
+            i64::MIN..=-1 => 1,
+            0 => 2,
+            1..10 => 3,
+            10..=i64::MAX => 4,
+        }
+    }
+
+    fn main() -> i64 {
+        return (classify(-7) * 64) + (classify(0) * 16) + (classify(9) * 4) + classify(10);
+    }
+    ";
+
+    assert_exit_code(s, 112);
+}
+
+#[test]
+fn non_exhaustive_enum() {
+    let s = r"
+    enum Shape {
+        Circle(i64),
+        Rect { w: i64, h: i64 },
+        Empty,
+    }
+
+    fn main() -> i64 {
+        let s = Shape::Circle(1);
+        match s {
+            Shape::Circle(r) => r,
+            Shape::Rect { w, .. } => w,
+        }
+    }
+    ";
+
+    assert_compile_error(s, "non-exhaustive patterns: `Shape::Empty` not covered");
+}
+
+#[test]
+fn non_exhaustive_nested() {
+    let s = r"
+    enum Pair {
+        Both(bool, bool),
+        Neither,
+    }
+
+    fn main() -> i64 {
+        let p = Pair::Neither;
+        match p {
+            Pair::Both(true, _) => 1,
+            Pair::Both(_, true) => 2,
+            Pair::Neither => 3,
+        }
+    }
+    ";
+
+    assert_compile_error(s, "`Pair::Both(false, false)` not covered");
+}
+
+#[test]
+fn non_exhaustive_int() {
+    let s = r"
+    fn main() -> i64 {
+        let n = 3;
+        match n {
+            0..=9 => 1,
+            10..=i64::MAX => 2,
+        }
+    }
+    ";
+
+    assert_compile_error(s, "`i64::MIN..=-1` not covered");
+}
+
+#[test]
+fn unreachable_arm() {
+    let s = r"
+    fn main() -> i64 {
+        let b = true;
+        match b {
+            true => 1,
+            _ => 0,
+            false => 2,
+        }
+    }
+    ";
+
+    assert_compile_warning(s, "warning: unreachable pattern `false`");
+}
+
+#[test]
+fn tuples() {
+    let s = r"
+    fn divmod(a: i64, b: i64) -> (i64, i64) {
+        (a / b, a % b)
+    }
+
+    fn spread(x: i64) -> (i64, (i64, bool), i64) {
+        (x, (x + 1, true), x + 2)
+    }
+
+    fn nothing() {}
+
+    fn main() -> i64 {
+        let (q, r) = divmod(17, 5);
+        let mut t = spread(10);
+        let (a, (b, c), _) = t;
+        t.1.0 = 100;
+
+        let u: () = nothing();
+        let v = ();
+        let x = if c { t.1.0 + a } else { b };
+        return x + q + r + t.2;
+    }
+    ";
+
+    assert_exit_code(s, 127);
+}
+
+#[test]
+fn not_and_shifts() {
+    let s = r"
+    fn main() -> i64 {
+        let a = 1 << 4;
+        let b = (0 - 64) >> 2;
+        let c = !0;
+        let t = !false;
+        let f = !(3 > 2);
+        if t && !f {
+            (!5 & 255) - (a + (b + (c + 42)))
+        } else {
+            0
+        }
+    }
+    ";
+
+    assert_exit_code(s, 209);
+}
+
+#[test]
+fn compound_assign() {
+    let s = r"
+    fn bump(p: &mut i64) -> i64 {
+        *p += 1;
+        *p - 1
+    }
+
+    fn main() -> i64 {
+        let mut i = 0;
+        let mut a: [i64; 4];
+        a[0] = 0;
+        a[1] = 0;
+        a[bump(&mut i)] += 5;
+        a[bump(&mut i)] -= 3;
+        let mut x = 7;
+        x *= 6;
+        x /= 4;
+        x %= 7;
+        x <<= 3;
+        x >>= 1;
+        x |= 1;
+        x &= 13;
+        x ^= 2;
+        let mut t = (1, 2);
+        t.1 += 40;
+        return (a[0] + (a[1] + (i + x))) + t.1;
+    }
+    ";
+
+    assert_exit_code(s, 61);
+}
+
+#[test]
+fn refutable_let() {
+    let s = r"
+    fn main() -> i64 {
+        let (1, b) = (1, 2);
+        return b;
+    }
+    ";
+
+    assert_compile_error(s, "refutable pattern in local binding");
+}
+
+#[test]
+fn destructuring() {
+    let s = r"
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    enum Shape {
+        Circle(i64),
+        Rect { w: i64, h: i64 },
+    }
+
+    fn manhattan(Point { x, y }: Point, (dx, dy): (i64, i64)) -> i64 {
+        (x + dx) + (y + dy)
+    }
+
+    fn first(&v: &i64, _: bool) -> i64 {
+        v
+    }
+
+    fn main() -> i64 {
+        let mut p = Point { x: 1, y: 2 };
+        p.y = 5;
+        let Point { x: a, y: b } = p;
+
+        let n = 10;
+        let r = &n;
+        let &m = r;
+
+        let ((c, _), Point { y, .. }) = ((3, true), p);
+        let s = match (Shape::Rect { w: 2, h: 3 }, &p) {
+            (Shape::Circle(_), _) => 0,
+            (Shape::Rect { w, h }, &Point { x, .. }) => w * h * x,
+        };
+        return manhattan(p, (a, b)) + first(&m, true) + c + y + s;
+    }
+    ";
+
+    assert_exit_code(s, 36);
+}
+
+#[test]
+fn refutable_param() {
+    let s = r"
+    enum Shape {
+        Circle(i64),
+        Empty,
+    }
+
+    fn radius(Shape::Circle(r): Shape) -> i64 {
+        r
+    }
+
+    fn main() -> i64 {
+        return radius(Shape::Circle(1));
+    }
+    ";
+
+    assert_compile_error(
+        s,
+        "refutable pattern in function argument: `Shape::Empty` not covered",
+    );
+}
+
+#[test]
+fn mutability() {
+    let s = r"
+    fn push(mut n: i64, out: &mut (i64, i64)) {
+        n += 1;
+        (*out).1 = n;
+    }
+
+    fn main() -> i64 {
+        let mut pair = (1, 2);
+        let (mut a, b) = pair;
+        a *= 10;
+        push(a + b, &mut pair);
+        let r = &pair;
+        return (*r).1 + pair.0;
+    }
+    ";
+
+    assert_exit_code(s, 14);
+}
+
+#[test]
+fn assign_to_immutable() {
+    let s = r"
+    fn main() -> i64 {
+        let a = 1;
+        a = 2;
+        return a;
+    }
+    ";
+
+    assert_compile_error(s, "cannot assign to `a`, as it is not declared as mutable");
+}
+
+#[test]
+fn write_through_shared_ref() {
+    let s = r"
+    fn main() -> i64 {
+        let mut a = 1;
+        let b = &a;
+        *b = 2;
+        return a;
+    }
+    ";
+
+    assert_compile_error(s, "cannot assign to data behind a `&` reference");
+}
+
+#[test]
+fn unused_mut() {
+    let s = r"
+    fn main() -> i64 {
+        let mut a = 1;
+        return a;
+    }
+    ";
+
+    assert_compile_warning(s, "warning: variable `a` does not need to be mutable");
+}
+
+#[test]
+fn shadowing_in_block() {
+    let s = r"
+    fn main() -> i64 {
+        let mut x = 1;
+        let y = { let x = 2; x };
+        x = 3;
+        x + y
+    }
+    ";
+
+    assert_exit_code(s, 5);
+}
+
+#[test]
+fn const_and_static() {
+    let s = r"
+    const N: i64 = 2 * 4;
+    const BIG: i64 = 1 << 40;
+    const ON: bool = !(N > 10);
+    static mut COUNTER: i64 = 0;
+    static BASE: i64 = N + 2;
+
+    fn tick() {
+        COUNTER += 1;
+    }
+
+    fn main() -> i64 {
+        let mut a: [i64; N];
+        let mut i = 0;
+        loop {
+            a[i] = i;
+            tick();
+            i += 1;
+            if i == N {
+                return if ON { (a[N - 1] + COUNTER) + ((BIG >> 38) + BASE) } else { 0 };
+            };
+        }
+    }
+    ";
+
+    assert_exit_code(s, 29);
+}
+
+#[test]
+fn assign_to_immutable_static() {
+    let s = r"
+    static LIMIT: i64 = 10;
+
+    fn main() -> i64 {
+        LIMIT = 20;
+        return LIMIT;
+    }
+    ";
+
+    assert_compile_error(s, "cannot assign to immutable static item `LIMIT`");
+}
+
+#[test]
+fn extern_c() {
+    let s = r#"
+    extern "C" {
+        fn labs(x: i64) -> i64;
+        fn malloc(size: i64) -> &mut i64;
+        fn free(p: &mut i64);
+        fn printf(fmt: &i64, ...) -> i64;
+    }
+
+    fn main() -> i64 {
+        let p = malloc(16);
+        *p = 30;
+        *(p + 1) = 0 - 12;
+        let n = 1 + (*p + labs(*(p + 1)));
+        free(p);
+        return n;
+    }
+    "#;
+
+    assert_exit_code(s, 43);
+}
+
+#[test]
+fn extern_c_ints_and_variadics() {
+    let s = r#"
+    extern "C" {
+        fn printf(fmt: &i64, ...) -> i32;
+        fn syscall(number: i64, ...) -> i64;
+        fn getpid() -> i32;
+        fn putchar(c: u8) -> u8;
+        fn abs(x: i32) -> i32;
+    }
+
+    fn main() -> i64 {
+        // `"%d\n"` packed into a word
+        let fmt = 680997;
+        let mut n = printf(&fmt, 42) + putchar(0 - 191);
+        if syscall(39) == getpid() {
+            n += 1;
+        }
+        if abs(0 - 2147483648) < 0 {
+            n += 1;
+        }
+        n
+    }
+    "#;
+
+    assert_exit_code(s, 70);
+}
+
+#[test]
+fn extern_c_bool_return() {
+    let s = r#"
+    extern "C" {
+        fn is_pos(x: i64) -> bool;
+    }
+
+    pub extern "C" fn check() -> i64 {
+        let a = if is_pos(0 - 5) { 1 } else { 2 };
+        let b = if is_pos(5) { 10 } else { 20 };
+        a + b
+    }
+    "#;
+    // what gcc -O2 makes of `return x > 0;`, the upper bits of rax are left as they were
+    let c = r#"
+    #include <stdint.h>
+
+    int64_t check(void);
+
+    __asm__(
+        ".globl is_pos\n"
+        "is_pos:\n"
+        "    movq $0x100, %rax\n"
+        "    testq %rdi, %rdi\n"
+        "    setg %al\n"
+        "    ret\n"
+    );
+
+    int main(void) {
+        return (int)check();
+    }
+    "#;
+
+    assert_exit_code_with_c(s, c, 12);
+}
+
+#[test]
+fn extern_c_byte_pointer() {
+    let s = r#"
+    extern "C" {
+        fn malloc(n: u64) -> &u8;
+        fn calloc(n: u64, size: u64) -> &mut u8;
+        fn memset(p: &u8, c: i32, n: u64) -> &u8;
+        fn strlen(s: &u8) -> u64;
+        fn free(p: &u8);
+    }
+
+    fn main() -> i64 {
+        let p = malloc(8);
+        memset(p, 0, 8);
+        memset(p, 65, 3);
+        let q = calloc(8, 1);
+        memset(q, 66, 4);
+        *(q + 1) = *p;
+        let n = strlen(p) + strlen(q);
+        let same = *(q + 1) == *p;
+        let differ = *q != *p;
+        free(p);
+        free(q);
+        if same && differ { n } else { 0 }
+    }
+    "#;
+
+    assert_exit_code(s, 7);
+
+    let s = r#"
+    extern "C" {
+        fn atoi(s: &i32) -> i32;
+    }
+
+    fn main() -> i64 {
+        0
+    }
+    "#;
+
+    assert_compile_error(
+        s,
+        "`&i32` cannot cross the C ABI, sofa only points at i64 and u8",
+    );
+
+    let s = r#"
+    extern "C" {
+        fn printf(fmt: &i64, ...) -> i32;
+    }
+
+    fn main() -> i64 {
+        printf("hi\n")
+    }
+    "#;
+
+    assert_compile_error(s, "string literals are not supported yet");
+}
+
+#[test]
+fn export_to_c() {
+    let s = r#"
+    fn square(x: i64) -> i64 {
+        x * x
+    }
+
+    pub extern "C" fn sum_squares(a: i64, b: i64) -> i64 {
+        square(a) + square(b)
+    }
+
+    pub extern "C" fn store(out: &mut i64, flag: bool) {
+        *out = if flag { 7 } else { 0 };
+    }
+    "#;
+    let c = r"
+    #include <stdbool.h>
+    #include <stdint.h>
+
+    int64_t sum_squares(int64_t a, int64_t b);
+    void store(int64_t *out, bool flag);
+
+    int main(void) {
+        int64_t n;
+        store(&n, true);
+        return (int)(sum_squares(3, 4) + n);
+    }
+    ";
+
+    assert_exit_code_with_c(s, c, 32);
+    assert_eq!(
+        emit_c_header(s),
+        "#pragma once\n\n#include <stdbool.h>\n#include <stdint.h>\n\n\
+         int64_t sum_squares(int64_t a, int64_t b);\n\
+         void store(int64_t *out, bool flag);\n"
+    );
+}
+
+#[test]
+fn stack_arguments() {
+    let s = r#"
+    struct Pair { a: i64, b: i64, }
+
+    fn many(a: i64, b: i64, c: i64, d: i64, e: i64, f: i64, g: i64, h: i64, (i, j): (i64, i64), p: Pair) -> i64 {
+        ((a - b) + (c * d)) + ((e - f) + ((g * h) + ((i - j) + (p.a - p.b))))
+    }
+
+    fn big(a: i64, b: i64, c: i64, d: i64, e: i64, f: i64, g: i64) -> (i64, i64, i64) {
+        (a + g, b + f, c + (d + e))
+    }
+
+    extern "C" {
+        fn labs(x: i64) -> i64;
+    }
+
+    fn main() -> i64 {
+        let t = big(1, 2, 3, 4, 5, 6, 7);
+        let r = many(10, 1, 2, 3, 4, 1, 2, 5, (9, 2), Pair { a: 6, b: 1 });
+        return labs(r) + (t.0 + (t.1 + t.2));
+    }
+    "#;
+
+    assert_exit_code(s, 68);
+}
+
+#[test]
+fn stack_arguments_from_c() {
+    let s = r#"
+    pub extern "C" fn weigh(a: i64, b: i64, c: i64, d: i64, e: i64, f: i64, g: i64, h: i64) -> i64 {
+        a + (2 * b) + (3 * c) + (4 * d) + (5 * e) + (6 * f) + (7 * g) + (8 * h)
+    }
+    "#;
+    let c = r"
+    #include <stdint.h>
+
+    int64_t weigh(int64_t, int64_t, int64_t, int64_t, int64_t, int64_t, int64_t, int64_t);
+
+    int main(void) {
+        return (int)weigh(1, 1, 1, 1, 1, 1, 1, 2);
+    }
+    ";
+
+    assert_exit_code_with_c(s, c, 44);
+}
+
+#[test]
+fn large_frames() {
+    let s = r"
+    fn sum_to(n: i64) -> i64 {
+        let mut acc = (0, 0);
+        if n > 0 {
+            acc.0 = n + sum_to(n - 1);
+        };
+        acc.0
+    }
+
+    fn main() -> i64 {
+        let mut big: [i64; 1000];
+        let mut i = 0;
+        loop {
+            big[i] = i;
+            i += 1;
+            if i == 1000 {
+                return (big[999] - big[900]) + sum_to(10);
+            };
+        }
+    }
+    ";
+
+    assert_exit_code(s, 154);
+}
+
+#[test]
+fn boxes() {
+    let s = r"
+    enum Tree {
+        Leaf(i64),
+        Pair(Box<i64>, Box<i64>),
+    }
+
+    fn make(x: i64) -> Box<i64> {
+        Box::new(x)
+    }
+
+    fn weight(t: Tree) -> i64 {
+        match t {
+            Tree::Leaf(n) => n,
+            Tree::Pair(a, b) => *a + *b,
+        }
+    }
+
+    fn main() -> i64 {
+        let mut b = Box::new(1);
+        *b += 2;
+        let t = (make(4), Box::new(Box::new(5)));
+        let keep = Tree::Pair(Box::new(6), Box::new(7));
+        let n = match keep {
+            Tree::Leaf(_) => 0,
+            Tree::Pair(_, _) => 1,
+        };
+
+        // boxes freed every iteration are reused, or this would not fit in memory
+        let page: [i64; 10000];
+        let mut i = 0;
+        loop {
+            if i == 100000 {
+                return *b + *t.0 + **t.1 + n + weight(Tree::Pair(Box::new(8), Box::new(9)));
+            };
+            let big = Box::new(page);
+            b = Box::new(*b);
+            i += 1;
+        }
+    }
+    ";
+
+    assert_exit_code(s, 30);
+}
+
+#[test]
+fn drop_at_block_end() {
+    let s = r"
+    fn main() -> i64 {
+        let t = {
+            let b = Box::new((3, 4));
+            *b
+        };
+        {
+            let b = Box::new(42);
+        };
+        // the last block freed is the first one handed out again, as it was left
+        let p = sofa_alloc(8);
+        *p + t.0 + t.1
+    }
+    ";
+
+    assert_exit_code(s, 49);
+}
+
+#[test]
+fn move_out_of_field() {
+    let s = r"
+    fn main() -> i64 {
+        let t = (Box::new(1), 2);
+        let b = t.0;
+        *b
+    }
+    ";
+
+    assert_compile_error(s, "cannot move Box { to: I64 } out of a field");
+}
+
+#[test]
+fn moves() {
+    let s = r"
+    fn eat(b: Box<i64>) -> i64 {
+        *b
+    }
+
+    fn f(c: bool) -> i64 {
+        let mut b = Box::new(5);
+        if c {
+            return eat(b);
+        }
+        let n = *b;
+        b = Box::new(n + 1);
+        let m = eat(b);
+        b = Box::new(m);
+        let r = &b;
+        **r + *b
+    }
+
+    fn main() -> i64 {
+        f(false) + f(true)
+    }
+    ";
+
+    assert_exit_code(s, 17);
+}
+
+#[test]
+fn use_after_move() {
+    let s = r"
+    fn eat(b: Box<i64>) -> i64 {
+        *b
+    }
+
+    fn main() -> i64 {
+        let b = Box::new(5);
+        eat(b);
+        let c = Box::new(9);
+        *b
+    }
+    ";
+
+    assert_compile_error(s, "use of moved value `b`");
+
+    let s = r"
+    fn main() -> i64 {
+        let mut v: Vec<i64> = Vec::new();
+        v.push(1);
+        let w = v;
+        v.push(2);
+        w.len()
+    }
+    ";
+
+    assert_compile_error(s, "use of moved value `v`");
+
+    let s = r"
+    enum Opt {
+        Some(Box<i64>),
+        None,
+    }
+
+    fn main() -> i64 {
+        let o = Opt::Some(Box::new(3));
+        let n = match o {
+            Opt::Some(b) => *b,
+            Opt::None => 0,
+        };
+        match o {
+            Opt::Some(b) => *b,
+            Opt::None => n,
+        }
+    }
+    ";
+
+    assert_compile_error(s, "use of moved value `o`");
+
+    let s = r"
+    fn eat(b: Box<i64>) -> i64 {
+        *b
+    }
+
+    fn main() -> i64 {
+        let b = Box::new(5);
+        loop {
+            eat(b);
+        }
+    }
+    ";
+
+    assert_compile_error(s, "use of moved value `b`");
+}
+
+#[test]
+fn slices() {
+    let s = r"
+    fn sum(s: &[i64]) -> i64 {
+        let mut acc = 0;
+        let mut i = 0;
+        loop {
+            if i == s.len() {
+                return acc;
+            };
+            acc += s[i];
+            i += 1;
+        }
+    }
+
+    fn fill(s: &mut [i64], v: i64) {
+        let mut i = 0;
+        loop {
+            if i == s.len() {
+                return ();
+            };
+            s[i] = v;
+            i += 1;
+        }
+    }
+
+    fn main() -> i64 {
+        let mut a: [i64; 5];
+        fill(&mut a[..], 1);
+        fill(&mut a[1..3], 10);
+        let whole = &a[..];
+        let mut v: Vec<i64> = Vec::new();
+        let mut i = 0;
+        loop {
+            v.push(i);
+            i += 1;
+            if i == 100 {
+                let tail = sum(whole) + sum(&whole[3..]);
+                return tail + v.pop() + v.len() + v[10] + sum(&v[..3]) + a.len();
+            };
+        }
+    }
+    ";
+
+    assert_exit_code(s, 241);
+}
+
+#[test]
+fn vecs() {
+    let s = r"
+    fn total(mut v: Vec<(i64, Box<i64>)>) -> i64 {
+        let mut acc = 0;
+        loop {
+            if v.len() == 0 {
+                return acc;
+            };
+            let (a, b) = v.pop();
+            acc += a * *b;
+        }
+    }
+
+    fn round() -> i64 {
+        let mut v = Vec::<(i64, Box<i64>)>::new();
+        let mut big: Vec<[i64; 100]> = Vec::new();
+        let page: [i64; 100];
+        let mut i = 0;
+        loop {
+            if i == 1000 {
+                return total(v);
+            };
+            v.push((i, Box::new(2)));
+            big.push(page);
+            i += 1;
+        }
+    }
+
+    fn main() -> i64 {
+        let mut n = 0;
+        let mut out = 0;
+        loop {
+            if n == 200 {
+                return out % 256;
+            };
+            out = round();
+            n += 1;
+        }
+    }
+    ";
+
+    assert_exit_code(s, 88);
+}
+
+#[test]
+fn vec_element_inferred() {
+    let s = r"
+    fn main() -> i64 {
+        let mut v = Vec::new();
+        let n = v.len();
+        v.push(Box::new(4));
+        v.push(Box::new(5));
+        let mut w = Vec::new();
+        if n == 0 {
+            w.push((1, 2));
+        };
+        *v[0] + *v[1] + w[0].1 + w.len()
+    }
+    ";
+
+    assert_exit_code(s, 12);
+
+    let s = r"
+    fn main() -> i64 {
+        let v = Vec::new();
+        v.len()
+    }
+    ";
+
+    assert_compile_error(s, "type annotations needed for `Vec::new()`");
+}
+
+#[test]
+fn push_to_immutable_vec() {
+    let s = r"
+    fn main() -> i64 {
+        let v: Vec<i64> = Vec::new();
+        v.push(1);
+        v.len()
+    }
+    ";
+
+    assert_compile_error(
+        s,
+        "cannot borrow `v` as mutable, as it is not declared as mutable",
+    );
+}
+
+#[test]
+fn index_out_of_bounds() {
+    let s = r"
+    fn main() -> i64 {
+        let mut a: [i64; 3];
+        let mut i = 0;
+        loop {
+            a[i] = i;
+            i += 1;
+        }
+    }
+    ";
+
+    assert_panic(
+        s,
+        "panicked at <console>:6:13:\nindex out of bounds: the len is 3 but the index is 3",
+    );
+}
+
+#[test]
+fn range_out_of_bounds() {
+    let s = r"
+    fn main() -> i64 {
+        let mut v: Vec<i64> = Vec::new();
+        v.push(1);
+        v.push(2);
+        let s = &v[1..];
+        s[0] + s[1..3].len()
+    }
+    ";
+
+    assert_panic(s, "range 1..3 out of bounds for length 1");
+}
+
+#[test]
+fn panics() {
+    let s = r#"
+    fn div(a: i64, b: i64) -> i64 {
+        if b == 0 {
+            panic("attempt to divide by zero");
+        }
+        a / b
+    }
+
+    fn main() -> i64 {
+        let x = if div(6, 3) == 2 { 1 } else { panic("6 / 3 is 2") };
+        div(x, 0)
+    }
+    "#;
+
+    assert_panic(
+        s,
+        "panicked at <console>:4:13:\nattempt to divide by zero\n",
+    );
+}
+
+#[test]
+fn asserts() {
+    let s = r#"
+    fn main() -> i64 {
+        let mut v: Vec<i64> = Vec::new();
+        v.push(3);
+        assert(v.len() == 1);
+        assert(v[0] == 3, "first element");
+        assert(v.pop() == 4, "popped the wrong element");
+        0
+    }
+    "#;
+
+    assert_panic(s, "panicked at <console>:7:9:\npopped the wrong element\n");
+}
+
+#[test]
+fn assert_without_message() {
+    let s = r"
+    fn main() -> i64 {
+        assert(1 == 2);
+        0
+    }
+    ";
+
+    assert_panic(s, "panicked at <console>:3:9:\nassertion failed\n");
+}
+
+#[test]
+fn assert_non_bool() {
+    let s = r"
+    fn main() -> i64 {
+        assert(1);
+        0
+    }
+    ";
+
+    assert_compile_error(s, "mismatched types, expected Bool but found I64");
+}
+
+#[test]
+fn overflow() {
+    let s = r"
+    fn main() -> i64 {
+        let mut x = 1;
+        loop {
+            x *= 3;
+        }
+    }
+    ";
+
+    assert_panic(
+        s,
+        "panicked at <console>:5:13:\nattempt to multiply with overflow\n",
+    );
+}
+
+#[test]
+fn division_overflow() {
+    let s = r"
+    const MIN: i64 = (-9223372036854775807) - 1;
+
+    fn main() -> i64 {
+        let d = -1;
+        MIN / d
+    }
+    ";
+
+    assert_panic(s, "attempt to divide with overflow");
+}
+
+#[test]
+fn negation_overflow() {
+    let s = r"
+    fn main() -> i64 {
+        let m = (-9223372036854775807) - 1;
+        -m
+    }
+    ";
+
+    assert_panic(
+        s,
+        "panicked at <console>:4:9:\nattempt to negate with overflow\n",
+    );
+}
+
+#[test]
+fn pop_from_empty_vec() {
+    let s = r"
+    fn main() -> i64 {
+        let mut v: Vec<i64> = Vec::new();
+        v.push(1);
+        v.pop() + v.pop()
+    }
+    ";
+
+    assert_panic(s, "panicked at <console>:5:19:\npop from empty vec\n");
+}
+
+#[test]
+fn out_of_memory() {
+    let s = r"
+    fn main() -> i64 {
+        let p = sofa_alloc(1000000000000000);
+        *p
+    }
+    ";
+
+    assert_panic(s, "memory allocation of 1000000000000000 bytes failed\n");
+}
+
+#[test]
+fn remainder_by_zero() {
+    let s = r"
+    fn main() -> i64 {
+        let d = 0;
+        7 % d
+    }
+    ";
+
+    assert_panic(
+        s,
+        "attempt to calculate the remainder with a divisor of zero",
+    );
+}
+
+#[test]
+fn wrapping_arithmetic() {
+    let s = r"
+    const MAX: i64 = 9223372036854775807;
+    const MIN: i64 = (-9223372036854775807) - 1;
+
+    fn main() -> i64 {
+        let mut n = 0;
+        if MAX.wrapping_add(1) == MIN {
+            n += 1;
+        }
+        if MIN.wrapping_sub(1) == MAX {
+            n += 2;
+        }
+        if MIN.wrapping_mul(-1) == MIN {
+            n += 4;
+        }
+        if MIN.wrapping_div(-1) == MIN {
+            n += 8;
+        }
+        if MIN.wrapping_rem(-1) == 0 {
+            n += 16;
+        }
+        n + 7.wrapping_div(2)
+    }
+    ";
+
+    assert_exit_code(s, 34);
+}
+
+#[test]
+fn generics() {
+    let s = r"
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    fn max<T>(a: T, b: T) -> T {
+        if a > b {
+            a
+        } else {
+            b
+        }
+    }
+
+    fn max3<T>(a: T, b: T, c: T) -> T {
+        max(max(a, b), c)
+    }
+
+    fn swap<A, B>(p: (A, B)) -> (B, A) {
+        (p.1, p.0)
+    }
+
+    fn pick<T>(c: bool, a: T, b: T) -> T {
+        if c {
+            a
+        } else {
+            b
+        }
+    }
+
+    fn first<T>(s: &[T]) -> T {
+        s[0]
+    }
+
+    fn wrap<T>(x: T) -> Vec<T> {
+        let mut v: Vec<T> = Vec::new();
+        v.push(x);
+        v
+    }
+
+    fn main() -> i64 {
+        let p = pick(false, Point { x: 1, y: 2 }, Point { x: 3, y: 4 });
+        let s = swap((true, 5));
+        let v = wrap::<i64>(11);
+        let b = wrap(Box::new(4));
+        max3(3, 9, 2) + p.x + p.y + s.0 + first(&v[..]) + *b[0]
+    }
+    ";
+
+    assert_exit_code(s, 36);
+}
+
+#[test]
+fn generic_mismatch() {
+    let s = r"
+    fn max<T>(a: T, b: T) -> T {
+        if a > b {
+            a
+        } else {
+            b
+        }
+    }
+
+    fn main() -> i64 {
+        max(1, true)
+    }
+    ";
+
+    assert_compile_error(s, "mismatched types, expected I64 but found Bool");
+}
+
+#[test]
+fn generic_uninferred() {
+    let s = r"
+    fn size<T>() -> i64 {
+        0
+    }
+
+    fn main() -> i64 {
+        size()
+    }
+    ";
+
+    assert_compile_error(s, "type annotations needed for T in the call to size");
+}
+
+#[test]
+fn traits() {
+    let s = r"
+    struct Rect {
+        w: i64,
+        h: i64,
+    }
+
+    struct Square {
+        side: i64,
+    }
+
+    trait Shape {
+        fn area(&self) -> i64;
+        fn scale(&mut self, k: i64);
+    }
+
+    impl Rect {
+        fn new(w: i64, h: i64) -> Rect {
+            Rect { w: w, h: h }
+        }
+
+        fn square(side: i64) -> Self {
+            Self::new(side, side)
+        }
+
+        fn perimeter(&self) -> i64 {
+            2 * (self.w + self.h)
+        }
+    }
+
+    impl Shape for Rect {
+        fn area(&self) -> i64 {
+            self.w * self.h
+        }
+
+        fn scale(&mut self, k: i64) {
+            self.w *= k;
+            self.h *= k;
+        }
+    }
+
+    impl Shape for Square {
+        fn area(&self) -> i64 {
+            self.side * self.side
+        }
+
+        fn scale(&mut self, k: i64) {
+            self.side *= k;
+        }
+    }
+
+    fn total<T: Shape>(a: &T, b: &T) -> i64 {
+        a.area() + b.area()
+    }
+
+    fn grow<T: Shape>(mut s: T) -> T {
+        s.scale(2);
+        s
+    }
+
+    fn main() -> i64 {
+        let mut r = Rect::new(2, 3);
+        r.scale(2);
+        let s = Square { side: 3 };
+        let p = &r;
+        let b = Box::new(Rect::square(1));
+        let g = grow(Square { side: 1 });
+        r.area() + p.perimeter() + s.area() + total(&r, &r) + b.area() + g.area() + Rect::new(1, 1).area()
+    }
+    ";
+
+    assert_exit_code(s, 107);
+}
+
+#[test]
+fn trait_bound_unsatisfied() {
+    let s = r"
+    trait Shape {
+        fn area(&self) -> i64;
+    }
+
+    fn area<T: Shape>(s: &T) -> i64 {
+        s.area()
+    }
+
+    fn main() -> i64 {
+        area(&3)
+    }
+    ";
+
+    assert_compile_error(s, "the trait bound `I64: Shape` is not satisfied");
+}
+
+#[test]
+fn missing_trait_item() {
+    let s = r"
+    struct Circle {
+        r: i64,
+    }
+
+    trait Shape {
+        fn area(&self) -> i64;
+        fn scale(&mut self, k: i64);
+    }
+
+    impl Shape for Circle {
+        fn area(&self) -> i64 {
+            3 * self.r * self.r
+        }
+    }
+
+    fn main() -> i64 {
+        0
+    }
+    ";
+
+    assert_compile_error(s, "not all trait items implemented, missing: `scale`");
+}
+
+#[test]
+fn method_on_immutable_receiver() {
+    let s = r"
+    struct Counter {
+        n: i64,
+    }
+
+    impl Counter {
+        fn bump(&mut self) {
+            self.n += 1;
+        }
+    }
+
+    fn main() -> i64 {
+        let c = Counter { n: 0 };
+        c.bump();
+        c.n
+    }
+    ";
+
+    assert_compile_error(s, "cannot borrow `c` as mutable");
+}
+
+#[test]
+fn builtin_methods_through_references() {
+    let s = r"
+    fn fill(v: &mut Vec<i64>, n: i64) {
+        v.push(n);
+        v.push(n + 1);
+    }
+
+    fn size(v: &Vec<i64>) -> i64 {
+        v.len()
+    }
+
+    fn main() -> i64 {
+        let mut v: Vec<i64> = Vec::new();
+        fill(&mut v, 3);
+        let xs: [i64; 3];
+        let b = Box::new(xs);
+        let last = (&mut v).pop();
+        size(&v) + last + b.len()
+    }
+    ";
+
+    assert_exit_code(s, 8);
+
+    let s = r"
+    fn fill(v: &Vec<i64>) {
+        v.push(1);
+    }
+
+    fn main() -> i64 {
+        let v: Vec<i64> = Vec::new();
+        fill(&v);
+        0
+    }
+    ";
+
+    assert_compile_error(s, "cannot borrow data behind a `&` reference as mutable");
+}
+
+#[test]
+fn dyn_trait() {
+    let s = r"
+    struct Rect {
+        w: i64,
+        h: i64,
+    }
+
+    struct Circle {
+        r: i64,
+    }
+
+    struct Blob {
+        parts: Vec<i64>,
+        inner: Box<i64>,
+    }
+
+    trait Shape {
+        fn area(&self) -> i64;
+        fn scale(&mut self, k: i64);
+    }
+
+    impl Shape for Rect {
+        fn area(&self) -> i64 {
+            self.w * self.h
+        }
+        fn scale(&mut self, k: i64) {
+            self.w *= k;
+            self.h *= k;
+        }
+    }
+
+    impl Shape for Circle {
+        fn area(&self) -> i64 {
+            3 * self.r * self.r
+        }
+        fn scale(&mut self, k: i64) {
+            self.r *= k;
+        }
+    }
+
+    impl Shape for Blob {
+        fn area(&self) -> i64 {
+            self.parts.len() + *self.inner
+        }
+        fn scale(&mut self, k: i64) {
+            self.parts.push(k);
+        }
+    }
+
+    fn total(shapes: &[Box<dyn Shape>]) -> i64 {
+        let mut sum = 0;
+        let mut i = 0;
+        loop {
+            if i == shapes.len() {
+                return sum;
+            }
+            sum += shapes[i].area();
+            i += 1;
+        }
+    }
+
+    fn grow(s: &mut dyn Shape) {
+        s.scale(2);
+    }
+
+    fn erased<T: Shape>(x: &T) -> i64 {
+        let s: &dyn Shape = x;
+        s.area()
+    }
+
+    fn main() -> i64 {
+        let mut r = Rect { w: 1, h: 2 };
+        grow(&mut r);
+        let mut shapes: Vec<Box<dyn Shape>> = Vec::new();
+        shapes.push(Box::new(r));
+        shapes.push(Box::new(Circle { r: 1 }));
+        let mut parts: Vec<i64> = Vec::new();
+        parts.push(1);
+        let mut b: Box<dyn Shape> = Box::new(Blob { parts: parts, inner: Box::new(10) });
+        b.scale(5);
+        shapes.push(b);
+        let c = Circle { r: 2 };
+        total(&shapes[..]) + erased(&c)
+    }
+    ";
+
+    assert_exit_code(s, 35);
+}
+
+#[test]
+fn dyn_incompatible_trait() {
+    let s = r"
+    trait Shape {
+        fn area(&self) -> i64;
+        fn new() -> Self;
+    }
+
+    fn area(s: &dyn Shape) -> i64 {
+        s.area()
+    }
+
+    fn main() -> i64 {
+        0
+    }
+    ";
+
+    assert_compile_error(
+        s,
+        "the trait `Shape` is not dyn compatible because of method `new`",
+    );
+}
+
+#[test]
+fn dyn_mutable_method_through_shared() {
+    let s = r"
+    struct Counter {
+        n: i64,
+    }
+
+    trait Bump {
+        fn bump(&mut self);
+    }
+
+    impl Bump for Counter {
+        fn bump(&mut self) {
+            self.n += 1;
+        }
+    }
+
+    fn main() -> i64 {
+        let mut c = Counter { n: 0 };
+        let b: &dyn Bump = &c;
+        b.bump();
+        c.n
+    }
+    ";
+
+    assert_compile_error(s, "cannot borrow data behind a `&` reference as mutable");
+}
+
+#[test]
+fn function_pointers_and_closures() {
+    let s = r"
+    fn double(x: i64) -> i64 {
+        x * 2
+    }
+
+    fn apply(f: fn(i64) -> i64, x: i64) -> i64 {
+        f(x)
+    }
+
+    fn map(xs: &mut [i64], f: impl Fn(i64) -> i64) {
+        let mut i = 0;
+        loop {
+            if i == xs.len() {
+                return ();
+            };
+            xs[i] = f(xs[i]);
+            i += 1;
+        }
+    }
+
+    fn fold(xs: &[i64], init: i64, f: impl Fn(i64, i64) -> i64) -> i64 {
+        if xs.len() == 0 {
+            init
+        } else {
+            fold(&xs[1..], f(init, xs[0]), f)
+        }
+    }
+
+    fn main() -> i64 {
+        let f: fn(i64) -> i64 = double;
+        let k = 3;
+        let mut xs: [i64; 3];
+        xs[0] = 1;
+        xs[1] = 2;
+        xs[2] = 3;
+        map(&mut xs[..], |x| x + k);
+        let mut calls = 0;
+        let sum = fold(&xs[..], 0, |acc, x| {
+            calls += 1;
+            acc + x
+        });
+        let add = |a: i64, b: i64| a + b + k;
+        apply(f, sum) + calls + add(1, 2) + apply(double, 1)
+    }
+    ";
+
+    assert_exit_code(s, 41);
+}
+
+#[test]
+fn nested_closures() {
+    let s = r"
+    fn twice(f: impl Fn(i64) -> i64, x: i64) -> i64 {
+        f(f(x))
+    }
+
+    fn main() -> i64 {
+        let k = 2;
+        let mut total = 0;
+        let g = |x: i64| {
+            let h = |y: i64| y * (k + x);
+            total += h(1);
+            twice(h, x)
+        };
+        let r = g(1) + g(2);
+        r + total
+    }
+    ";
+
+    assert_exit_code(s, 48);
+}
+
+#[test]
+fn closure_parameter_needs_annotation() {
+    let s = r"
+    fn main() -> i64 {
+        let f = |x| x;
+        f(1)
+    }
+    ";
+
+    assert_compile_error(s, "type annotations needed for closure parameter x");
+}
+
+#[test]
+fn closure_mutating_immutable_capture() {
+    let s = r"
+    fn main() -> i64 {
+        let calls = 0;
+        let f = |x: i64| {
+            calls += 1;
+            x
+        };
+        f(1)
+    }
+    ";
+
+    assert_compile_error(
+        s,
+        "cannot assign to `calls`, as it is not declared as mutable",
+    );
+}
+
+#[test]
+fn closure_returned_in_tuple() {
+    let s = r"
+    fn adder(k: i64) -> (impl Fn(i64) -> i64, i64) {
+        (|x: i64| x + k, 0)
+    }
+
+    fn main() -> i64 {
+        let f = adder(5);
+        f.1
+    }
+    ";
+
+    assert_compile_error(
+        s,
+        "adder cannot return a closure, it borrows from the frame creating it",
+    );
+}
+
+#[test]
+fn inline_modules() {
+    let s = r"
+    fn area() -> i64 {
+        1000
+    }
+
+    mod util {
+        pub fn area() -> i64 {
+            1
+        }
+
+        fn hidden() -> i64 {
+            2
+        }
+
+        pub mod deep {
+            pub fn up() -> i64 {
+                super::hidden() + crate::area()
+            }
+        }
+    }
+
+    use util::deep::up as climb;
+
+    fn main() -> i64 {
+        util::area() + climb() - area()
+    }
+    ";
+
+    assert_exit_code(s, 3);
+}
+
+#[test]
+fn exported_function_in_module() {
+    let s = r#"
+    mod m {
+        pub extern "C" fn twice(x: i64) -> i64 {
+            x + x
+        }
+    }
+
+    pub extern "C" fn six() -> i64 {
+        m::twice(3)
+    }
+    "#;
+    let c = r"
+    #include <stdint.h>
+
+    int64_t twice(int64_t x);
+    int64_t six(void);
+
+    int main(void) {
+        return (int)(twice(5) + six());
+    }
+    ";
+
+    assert_exit_code_with_c(s, c, 16);
+}
+
+#[test]
+fn file_modules() {
+    let main = r"
+    mod geometry;
+
+    use geometry::{Rect, Shape};
+
+    fn main() -> i64 {
+        let r = Rect::new(2, 3);
+        let c = geometry::shapes::Circle { r: 2 };
+        let s: &dyn Shape = &c;
+        r.area() + s.area() + geometry::SIDES
+    }
+    ";
+    let geometry = r"
+    pub const SIDES: i64 = 4;
+
+    pub trait Shape {
+        fn area(&self) -> i64;
+    }
+
+    pub struct Rect {
+        w: i64,
+        h: i64,
+    }
+
+    impl Rect {
+        pub fn new(w: i64, h: i64) -> Rect {
+            Rect { w, h }
+        }
+    }
+
+    impl Shape for Rect {
+        fn area(&self) -> i64 {
+            self.w * self.h
+        }
+    }
+
+    pub mod shapes;
+    ";
+    let shapes = r"
+    use super::Shape;
+
+    pub struct Circle {
+        pub r: i64,
+    }
+
+    impl Shape for Circle {
+        fn area(&self) -> i64 {
+            3 * self.r * self.r
+        }
+    }
+    ";
+
+    assert_exit_code_with_files(
+        &[
+            ("main.sofa", main),
+            ("geometry.sofa", geometry),
+            ("geometry/shapes.sofa", shapes),
+        ],
+        22,
+    );
+}
+
+#[test]
+fn private_items() {
+    let s = r"
+    mod util {
+        fn hidden() -> i64 {
+            1
+        }
+    }
+
+    fn main() -> i64 {
+        util::hidden()
+    }
+    ";
+
+    assert_compile_error(s, "function `hidden` is private");
+
+    let s = r"
+    mod geometry {
+        pub struct Rect {
+            pub w: i64,
+            h: i64,
+        }
+
+        pub fn square(side: i64) -> Rect {
+            Rect { w: side, h: side }
+        }
+    }
+
+    fn main() -> i64 {
+        let r = geometry::square(2);
+        r.w + r.h
+    }
+    ";
+
+    assert_compile_error(s, "field `h` of struct `Rect` is private");
+}
+
+#[test]
+fn type_aliases_and_newtypes() {
+    let s = r"
+    type Index = i64;
+    type Grid = [Index; 3];
+
+    struct Meters(i64);
+    struct Pair(i64, bool);
+
+    fn total(m: Meters, n: Meters) -> Meters {
+        Meters(m.0 + n.0)
+    }
+
+    fn main() -> Index {
+        let m = total(Meters(3), Meters(4));
+        let Meters(x) = m;
+        let mut g: Grid;
+        g[2] = 3;
+        let y = match Pair(5, true) {
+            Pair(a, true) => a,
+            Pair(_, false) => 0,
+        };
+        x + y + g[2] + m.0
+    }
+    ";
+
+    assert_exit_code(s, 22);
+}
+
+#[test]
+fn type_aliases_out_of_order() {
+    let s = r"
+    type A = B;
+    type B = [i64; 2];
+
+    mod units {
+        pub type M = Meters;
+        pub type Meters = i64;
+    }
+
+    fn main() -> i64 {
+        let mut a: A;
+        a[1] = 4;
+        let m: units::M = 3;
+        a[1] + m
+    }
+    ";
+
+    assert_exit_code(s, 7);
+
+    let s = r"
+    type A = B;
+    type B = A;
+
+    fn main() -> i64 {
+        let a: A = 1;
+        a
+    }
+    ";
+
+    assert_compile_error(s, "cycle detected when expanding type alias `A`");
+
+    let s = r"
+    type List = (i64, List);
+
+    fn main() -> i64 {
+        0
+    }
+    ";
+
+    assert_compile_error(s, "cycle detected when expanding type alias `List`");
+}
+
+#[test]
+fn newtypes_are_distinct() {
+    let s = r"
+    struct Meters(i64);
+
+    fn main() -> i64 {
+        let m: Meters = 5;
+        m.0
+    }
+    ";
+
+    assert_compile_error(s, "mismatched types");
+
+    let s = r"
+    mod units {
+        pub struct Meters(i64);
+        pub type M = Meters;
+    }
+
+    fn main() -> i64 {
+        let m = units::M(3);
+        m.0
+    }
+    ";
+
+    assert_compile_error(s, "field `0` of struct `Meters` is private");
+
+    let s = r"
+    struct Meters(i64);
+
+    fn f() -> Meters {
+        5
+    }
+
+    fn main() -> i64 {
+        f().0
+    }
+    ";
+
+    assert_compile_error(s, "mismatched types");
+
+    let s = r"
+    struct Meters(i64);
+
+    fn f() -> Meters {
+        return 5;
+    }
+
+    fn main() -> i64 {
+        f().0
+    }
+    ";
+
+    assert_compile_error(s, "mismatched types");
+
+    let s = r"
+    struct Meters(i64);
+
+    fn main() -> i64 {
+        let mut m = Meters(1);
+        m = 5;
+        m.0
+    }
+    ";
+
+    assert_compile_error(s, "mismatched types");
+
+    let s = r"
+    struct Meters(i64);
+
+    fn f(x: i64) -> i64 {
+        x
+    }
+
+    fn main() -> i64 {
+        f(Meters(1))
+    }
+    ";
+
+    assert_compile_error(s, "mismatched types");
+}
+
+#[test]
+fn mutable_references_given_as_shared() {
+    let s = r"
+    trait Shape {
+        fn area(&self) -> i64;
+    }
+
+    struct Square {
+        side: i64,
+    }
+
+    impl Shape for Square {
+        fn area(&self) -> i64 {
+            self.side * self.side
+        }
+    }
+
+    fn read(p: &i64) -> i64 {
+        *p
+    }
+
+    fn first(xs: &[i64]) -> i64 {
+        xs[0]
+    }
+
+    fn measure(s: &dyn Shape) -> i64 {
+        s.area()
+    }
+
+    fn main() -> i64 {
+        let mut x = 2;
+        let mut a: [i64; 2];
+        a[0] = 3;
+        let mut sq = Square { side: 2 };
+        let s: &mut dyn Shape = &mut sq;
+        read(&mut x) + first(&mut a[..]) + measure(s)
+    }
+    ";
+
+    assert_exit_code(s, 9);
+}
+
+#[test]
+fn const_generics() {
+    let s = r"
+    const SIZE: i64 = 2;
+
+    fn sum<const N: i64>(a: [i64; N]) -> i64 {
+        let mut total = 0;
+        let mut i = 0;
+        loop {
+            if i == N {
+                return total;
+            }
+            total = total + a[i];
+            i = i + 1;
+        }
+    }
+
+    fn first<T, const N: i64>(a: [T; N + 1]) -> T {
+        a[0]
+    }
+
+    fn len_of<const N: i64>(a: [i64; 2 * N]) -> i64 {
+        a.len() + N
+    }
+
+    fn twice<const M: i64>(a: [i64; M + M]) -> i64 {
+        len_of(a)
+    }
+
+    fn main() -> i64 {
+        let mut xs: [i64; SIZE + 1];
+        xs[0] = 1;
+        xs[1] = 2;
+        xs[2] = 3;
+        let mut ys: [i64; 2 * SIZE];
+        sum(xs) + first(xs) + twice(ys) + sum::<{ 1 + 2 }>(xs)
+    }
+    ";
+
+    assert_exit_code(s, 19);
+}
+
+#[test]
+fn const_length_precedence() {
+    let s = r"
+    fn grow<const N: i64>(a: [i64; N]) -> [i64; 2 * N + 1] {
+        let mut b: [i64; 2 * N + 1];
+        let mut i = 0;
+        loop {
+            if i == b.len() {
+                return b;
+            }
+            b[i] = 2;
+            i += 1;
+        }
+    }
+
+    fn sum<const M: i64>(a: [i64; M]) -> i64 {
+        let mut total = 0;
+        let mut i = 0;
+        loop {
+            if i == M {
+                return total;
+            }
+            total += a[i];
+            i += 1;
+        }
+    }
+
+    fn main() -> i64 {
+        let a: [i64; 2];
+        let b = grow(a);
+        let c: [i64; (2 - 1) * 3 - 1 - 1];
+        sum(b) + b.len() + c.len()
+    }
+    ";
+
+    assert_exit_code(s, 16);
+
+    let s = r"
+    fn f<const N: i64>(a: [i64; 2 * N + 1]) -> i64 {
+        N
+    }
+
+    fn main() -> i64 {
+        let a: [i64; 4];
+        f(a)
+    }
+    ";
+
+    assert_compile_error(s, "mismatched types, expected length 2 * N + 1 but found 4");
+}
+
+#[test]
+fn const_generic_length_mismatch() {
+    let s = r"
+    fn half<const N: i64>(a: [i64; 2 * N]) -> i64 {
+        N
+    }
+
+    fn main() -> i64 {
+        let mut xs: [i64; 3];
+        half(xs)
+    }
+    ";
+
+    assert_compile_error(s, "mismatched types, expected length 2 * N but found 3");
+
+    let s = r"
+    fn pair(a: [i64; 2]) -> i64 {
+        a[0]
+    }
+
+    fn any<const N: i64>(a: [i64; N]) -> i64 {
+        pair(a)
+    }
+
+    fn main() -> i64 {
+        0
+    }
+    ";
+
+    assert_compile_error(s, "mismatched types, expected length 2 but found N");
+
+    let s = r"
+    fn f<const N: i64>(a: [i64; N + 1]) -> i64 {
+        N
+    }
+
+    fn main() -> i64 {
+        let xs: [i64; 0];
+        f(xs)
+    }
+    ";
+
+    assert_compile_error(s, "mismatched types, expected length N + 1 but found 0");
+}
+
+#[test]
+fn block_values() {
+    let s = r"
+    fn main() -> i64 {
+        let x = { 1; 2 };
+        let y = { let z = 3; z + x };
+        x + y
+    }
+    ";
+
+    assert_exit_code(s, 7);
+}
+
+#[test]
+fn length_indexed_vecs() {
+    let s = r"
+    fn concat3<T, const N: i64, const M: i64, const K: i64>(
+        a: Vec<T, N>,
+        b: Vec<T, M>,
+        c: Vec<T, K>,
+    ) -> Vec<T, K + M + N> {
+        a.concat(b).concat(c)
+    }
+
+    fn first<const N: i64>(v: Vec<i64, N + 1>) -> i64 {
+        v.head()
+    }
+
+    fn main() -> i64 {
+        let empty: Vec<i64, 0> = Vec::new();
+        let a = empty.push(1).push(2);
+        let b: Vec<i64, 0> = Vec::new();
+        let b = b.push(10);
+        let c: Vec<i64, 0> = Vec::new();
+        let v = concat3(a, b, c.push(20).push(30));
+        let n = v.len();
+        let x = { let i = 2; v[i] + v[4] };
+        n + x + first(v)
+    }
+    ";
+
+    assert_exit_code(s, 46);
+}
+
+#[test]
+fn length_indexed_vec_mismatch() {
+    let s = r"
+    fn first<const N: i64>(v: Vec<i64, N>) -> i64 {
+        v.head()
+    }
+
+    fn main() -> i64 {
+        0
+    }
+    ";
+
+    assert_compile_error(s, "cannot prove that the length N is at least 1");
+
+    let s = r"
+    fn join<const N: i64, const M: i64>(a: Vec<i64, N>, b: Vec<i64, M>) -> Vec<i64, N + M + 1> {
+        a.concat(b)
+    }
+
+    fn main() -> i64 {
+        0
+    }
+    ";
+
+    assert_compile_error(
+        s,
+        "mismatched types, expected length M + N + 1 but found M + N",
+    );
+
+    let s = r"
+    fn main() -> i64 {
+        let a: Vec<i64, 0> = Vec::new();
+        let b: Vec<i64, 2> = a.push(1);
+        b.len()
+    }
+    ";
+
+    assert_compile_error(s, "mismatched types, expected length 2 but found 1");
+}
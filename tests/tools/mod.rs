@@ -1,34 +1,151 @@
 use std::process::Command;
 
-pub(crate) fn assert_exit_code(s: &str, expected: i32) {
+/// a fresh directory for the files of one test case
+fn testcase_dir() -> String {
     let testcase_id: u32 = rand::random();
-    let test_asm_name = format!("./target/tmp/testcase{}.s", testcase_id);
-    let test_bin_name = format!("./target/tmp/testcase{}", testcase_id);
+    let dir = format!("./target/tmp/testcase{}", testcase_id);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// compiles the program `input` gives sofac, like `["-c", s]`, into `dir` and links it with
+/// the C source `c` if any, returns the path of the binary
+fn build(dir: &str, input: [&str; 2], c: Option<&str>) -> String {
+    let asm_name = format!("{}/main.s", dir);
+    let bin_name = format!("{}/main", dir);
 
     let _ = Command::new("cargo")
-        .args(["run", "-q", "--", "-c", s, "-o", test_asm_name.as_str()])
+        .args(["run", "-q", "--"])
+        .args(input)
+        .args(["-o", asm_name.as_str()])
         .spawn()
         .expect("failed to execute sofac")
         .wait()
         .unwrap();
 
+    let mut sources = vec![asm_name];
+    if let Some(c) = c {
+        let c_name = format!("{}/main.c", dir);
+        std::fs::write(&c_name, c).unwrap();
+        sources.insert(0, c_name);
+    }
     let _ = Command::new("gcc")
-        .args([
-            test_asm_name.as_str(),
-            "-o",
-            test_bin_name.as_str(),
-            "-static",
-        ])
+        .args(&sources)
+        .args(["-o", bin_name.as_str(), "-static"])
         .spawn()
-        .expect("failed to assemble with gcc")
+        .expect("failed to compile with gcc")
         .wait()
         .unwrap();
 
-    let status = Command::new(test_bin_name.as_str())
+    bin_name
+}
+
+pub(crate) fn assert_exit_code(s: &str, expected: i32) {
+    let dir = testcase_dir();
+    let bin_name = build(&dir, ["-c", s], None);
+
+    let status = Command::new(bin_name.as_str())
+        .status()
+        .expect("failed to run binary");
+
+    assert_eq!(status.code(), Some(expected));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// runs `s`, which has to panic with `expected` in its message
+pub(crate) fn assert_panic(s: &str, expected: &str) {
+    let dir = testcase_dir();
+    let bin_name = build(&dir, ["-c", s], None);
+
+    let output = Command::new(bin_name.as_str())
+        .output()
+        .expect("failed to run binary");
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(101), "no panic: {}", stderr);
+    assert!(
+        stderr.contains(expected),
+        "{:?} not found in {}",
+        expected,
+        stderr
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// links the exported functions of `s` with the C source `c`, whose `main` is the entry point
+pub(crate) fn assert_exit_code_with_c(s: &str, c: &str, expected: i32) {
+    let dir = testcase_dir();
+    let bin_name = build(&dir, ["-c", s], Some(c));
+
+    let status = Command::new(bin_name.as_str())
         .status()
         .expect("failed to run binary");
 
     assert_eq!(status.code(), Some(expected));
-    std::fs::remove_file(test_asm_name).unwrap();
-    std::fs::remove_file(test_bin_name).unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// runs the crate made of `files`, paths relative to its root with the root file first
+pub(crate) fn assert_exit_code_with_files(files: &[(&str, &str)], expected: i32) {
+    let dir = testcase_dir();
+    for (path, s) in files.iter() {
+        let path = std::path::Path::new(&dir).join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, s).unwrap();
+    }
+    let root = format!("{}/{}", dir, files[0].0);
+    let bin_name = build(&dir, ["-f", root.as_str()], None);
+
+    let status = Command::new(bin_name.as_str())
+        .status()
+        .expect("failed to run binary");
+
+    assert_eq!(status.code(), Some(expected));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// the C header declaring the exported functions of `s`
+pub(crate) fn emit_c_header(s: &str) -> String {
+    let output = Command::new("cargo")
+        .args(["run", "-q", "--", "-c", s, "-s", "--emit", "c-header"])
+        .output()
+        .expect("failed to execute sofac");
+
+    assert!(output.status.success(), "failed to emit a header");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// compiles `s` and returns whether it succeeded along with the diagnostics
+fn compile(s: &str) -> (bool, String) {
+    let output = Command::new("cargo")
+        .args(["run", "-q", "--", "-c", s, "-s"])
+        .output()
+        .expect("failed to execute sofac");
+
+    (
+        output.status.success(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+pub(crate) fn assert_compile_error(s: &str, expected: &str) {
+    let (success, stderr) = compile(s);
+    assert!(!success, "compilation unexpectedly succeeded");
+    assert!(
+        stderr.contains(expected),
+        "{:?} not found in {}",
+        expected,
+        stderr
+    );
+}
+
+pub(crate) fn assert_compile_warning(s: &str, expected: &str) {
+    let (success, stderr) = compile(s);
+    assert!(success, "compilation failed: {}", stderr);
+    assert!(
+        stderr.contains(expected),
+        "{:?} not found in {}",
+        expected,
+        stderr
+    );
 }